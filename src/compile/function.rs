@@ -12,11 +12,51 @@ pub struct FunctionStub {
     pub name: String,
 
     /// Number of parameters for this function. Types are not yet enforced at this point.
+    ///
+    /// For variadic functions, this does not include the trailing `*$rest` parameter.
     pub params: usize,
 
+    /// Whether this function accepts any number of arguments past `params`.
+    pub variadic: bool,
+
+    /// Number of trailing parameters that have default values, and may be left out of a call.
+    pub defaults: usize,
+
+    /// Names of this function's parameters, which arguments may be passed by.
+    ///
+    /// Builtin functions have no parameter names.
+    pub param_names: Vec<String>,
+
+    /// Types of this function's parameters, which calls are dispatched by when several functions
+    /// share the same name and number of parameters.
+    pub param_tys: Vec<TyExpr>,
+
     pub return_ty: TyExpr,
 }
 
+impl FunctionStub {
+    /// Whether a call with the given number of arguments may resolve to this function.
+    pub fn accepts(&self, args: usize) -> bool {
+        args >= self.params - self.defaults && (self.variadic || args <= self.params)
+    }
+
+    /// Whether this function has the same name and number of parameters as another function, so
+    /// that calls to them can only be told apart by the types of their arguments.
+    pub fn same_signature(&self, other: &FunctionStub) -> bool {
+        self.name == other.name && self.params == other.params && self.variadic == other.variadic
+    }
+
+    /// Gets the name that a function is known by when it is one of several functions that calls
+    /// are dispatched between, e.g. `area(Square)`.
+    pub fn overload_name(name: &str, param_tys: &[TyExpr]) -> String {
+        let tys: Vec<_> = param_tys
+            .iter()
+            .map(TyExpr::to_string)
+            .collect();
+        format!("{}({})", name, tys.join(", "))
+    }
+}
+
 impl vm::Symbolic for FunctionStub {
     type Symbol = vm::FunctionSymbol;
 
@@ -1,9 +1,17 @@
 mod scope;
 mod symbol;
 mod function;
+mod ty;
 mod state;
+mod module;
+mod pragma;
+mod warning;
 
 pub use self::scope::*;
 pub use self::symbol::*;
 pub use self::function::*;
+pub use self::ty::*;
 pub use self::state::*;
+pub use self::module::*;
+pub use self::pragma::*;
+pub use self::warning::*;
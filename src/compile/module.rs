@@ -0,0 +1,59 @@
+use std::{
+    env,
+    path::PathBuf,
+};
+use compile::{
+    FunctionStub,
+    TyStub,
+};
+
+/// The file extension that module source files use.
+const MODULE_EXTENSION: &str = "npl";
+
+/// The environment variable listing extra directories to search for modules in.
+const SEARCH_PATH_VAR: &str = "NPL_PATH";
+
+/// The separator between the segments of a qualified name, e.g. `Some::Module::func`.
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// A module that has been compiled, along with everything that it exports.
+///
+/// Exported functions and types are made visible to importers under their qualified names.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub functions: Vec<FunctionStub>,
+    pub tys: Vec<TyStub>,
+}
+
+impl Module {
+    /// Gets the path of a module's source file relative to a search directory, e.g.
+    /// `Some/Module.npl` for `Some::Module`.
+    pub fn relative_path(name: &str) -> PathBuf {
+        let mut path: PathBuf = name.split(NAMESPACE_SEPARATOR).collect();
+        path.set_extension(MODULE_EXTENSION);
+        path
+    }
+
+    /// Gets the qualified name of an item exported by this module.
+    pub fn qualify(&self, item: &str) -> String {
+        format!("{}{}{}", self.name, NAMESPACE_SEPARATOR, item)
+    }
+}
+
+/// Splits a qualified name into its module name and the name of the item within that module.
+///
+/// Unqualified names have no module.
+pub fn split_qualified_name(name: &str) -> (Option<&str>, &str) {
+    match name.rfind(NAMESPACE_SEPARATOR) {
+        Some(index) => (Some(&name[.. index]), &name[index + NAMESPACE_SEPARATOR.len() ..]),
+        None => (None, name),
+    }
+}
+
+/// Gets the module search directories listed in the `NPL_PATH` environment variable.
+pub fn env_search_path() -> Vec<PathBuf> {
+    env::var_os(SEARCH_PATH_VAR)
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,91 @@
+use compile::{WarningCategory, WarningSet};
+use syntax::tree::Pragma;
+use vm::{self, Overflow};
+
+/// The compiler settings that pragmas change.
+///
+/// A pragma lasts until the end of the block or file that it is used in, and functions are
+/// compiled with the pragmas in effect where they were declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pragmas {
+    /// Whether variables must be declared with `my` or `our` before they are used, set by
+    /// `use strict vars`.
+    pub strict_vars: bool,
+
+    /// The categories of compiler warnings that are emitted, which are all on unless they're
+    /// turned off with `no warnings`.
+    pub warnings: WarningSet,
+
+    /// The pragmas that are checked while the program runs.
+    pub runtime: vm::Pragmas,
+}
+
+impl Pragmas {
+    /// Turns the setting of a pragma on or off.
+    ///
+    /// Pragmas that are given no arguments apply to everything that they control, e.g. `use
+    /// strict;` is the same as `use strict vars, numbers;`.
+    pub fn apply(&mut self, pragma: &Pragma) -> Result<(), String> {
+        let enabled = pragma.enabled;
+        match pragma.name.as_str() {
+            "strict" => for arg in args_or(pragma, &["vars", "numbers"]) {
+                match arg {
+                    "vars" => self.strict_vars = enabled,
+                    "numbers" => self.runtime.strict_numbers = enabled,
+                    _ => return Err(unknown_arg(pragma, arg)),
+                }
+            },
+            // warnings about unset values are given while the program runs, and every other
+            // category is given by the compiler
+            "warnings" if pragma.args.is_empty() => {
+                self.runtime.warn_unset = enabled;
+                self.warnings = if enabled { WarningSet::all() } else { WarningSet::default() };
+            }
+            "warnings" => for arg in &pragma.args {
+                match (arg.as_str(), WarningCategory::from_name(arg)) {
+                    ("unset", _) => self.runtime.warn_unset = enabled,
+                    (_, Some(category)) if enabled => self.warnings.insert(category),
+                    (_, Some(category)) => self.warnings.remove(category),
+                    (_, None) => return Err(unknown_arg(pragma, arg)),
+                }
+            },
+            "overflow" => {
+                if !enabled {
+                    return Err("`no overflow` is not allowed; overflowing is an error unless another `use overflow` is on".to_string());
+                }
+                self.runtime.overflow = match pragma.args.as_slice() {
+                    [mode] => Overflow::from_name(mode).ok_or_else(|| unknown_arg(pragma, mode))?,
+                    _ => return Err("`use overflow` takes exactly one of `error`, `wrap`, or `float`".to_string()),
+                };
+            }
+            name => return Err(format!("unknown pragma `{}`", name)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Pragmas {
+    fn default() -> Self {
+        Pragmas {
+            strict_vars: false,
+            warnings: WarningSet::all(),
+            runtime: vm::Pragmas::default(),
+        }
+    }
+}
+
+/// Gets the arguments given to a pragma, or the given defaults if there are none.
+fn args_or<'p>(pragma: &'p Pragma, defaults: &'p [&'p str]) -> Vec<&'p str> {
+    if pragma.args.is_empty() {
+        defaults.to_vec()
+    } else {
+        pragma.args
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+fn unknown_arg(pragma: &Pragma, arg: &str) -> String {
+    format!("unknown argument `{}` in `{}`", arg, pragma)
+}
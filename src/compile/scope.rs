@@ -7,6 +7,7 @@ use compile::{
     TySymbolAlloc,
     FunctionSymbolAlloc,
     FunctionStub,
+    TyStub,
     VariableSymbolAlloc,
 };
 use ir;
@@ -58,6 +59,19 @@ impl<T, A> Scope<T, A>
         self.scope.pop()
     }
 
+    /// Removes every scope layer past the given depth, so that something may be compiled without
+    /// seeing them.
+    ///
+    /// The removed layers are returned, and must be put back with `restore_scopes`.
+    pub fn detach_scopes(&mut self, depth: usize) -> Vec<Vec<Rc<T>>> {
+        self.scope.split_off(depth)
+    }
+
+    /// Puts back scope layers that were removed with `detach_scopes`.
+    pub fn restore_scopes(&mut self, mut scopes: Vec<Vec<Rc<T>>>) {
+        self.scope.append(&mut scopes);
+    }
+
     /// Pushes the given value to the current scope.
     ///
     /// This function will panic if there is no current scope.
@@ -204,13 +218,15 @@ impl<T, A> ReserveSymbol for Scope<T, A>
 /// A specialized implementation of a scope for VM types.
 #[derive(Clone, Debug)]
 pub struct TyScope {
-    scope: Scope<vm::Ty, TySymbolAlloc>,
+    scope: Scope<TyStub, TySymbolAlloc>,
+    vm_tys: Vec<vm::Ty>,
 }
 
 impl TyScope {
     pub fn new() -> Self {
         TyScope {
             scope: Scope::empty(TySymbolAlloc::new()),
+            vm_tys: vec![],
         }
     }
 
@@ -228,21 +244,32 @@ impl TyScope {
         self.scope.push_empty_scope();
         for ty in BUILTINS {
             let sym = self.reserve_symbol();
-            self.scope.push_value(vm::Ty::Builtin(*ty, sym));
+            let stub = TyStub {
+                name: ty.name().to_string(),
+                symbol: sym,
+                variants: vec![],
+            };
+            self.scope.push_value(stub);
+            self.push_vm_ty(vm::Ty::Builtin(*ty, sym));
         }
         self
     }
 
-    /// Gets the defined type instance for the given builtin.
-    pub fn get_builtin(&self, builtin: vm::BuiltinTy) -> &vm::Ty {
-        self.get_value_by(|ty| match ty {
-            vm::Ty::Builtin(b, _) => *b == builtin,
-            _ => false,
-        }).expect("could not find builtin")
+    pub fn push_vm_ty(&mut self, ty: vm::Ty) {
+        self.vm_tys.push(ty);
+    }
+
+    /// Gets the defined type stub for the given builtin.
+    pub fn get_builtin(&self, builtin: vm::BuiltinTy) -> &TyStub {
+        // builtins always live in the topmost scope layer
+        self.scopes()
+            .next()
+            .and_then(|mut builtins| builtins.find(|ty| ty.name() == builtin.name()))
+            .expect("could not find builtin")
     }
 
     /// Looks up a type by the given type expression.
-    pub fn get_value_by_expr(&self, ty_expr: &ir::TyExpr) -> Option<&vm::Ty> {
+    pub fn get_value_by_expr(&self, ty_expr: &ir::TyExpr) -> Option<&TyStub> {
         match ty_expr {
             ir::TyExpr::Any => Some(self.get_builtin(vm::BuiltinTy::Any)),
             ir::TyExpr::Definite(s) => self.get_value_by_name(s),
@@ -250,20 +277,26 @@ impl TyScope {
         }
     }
 
-    pub fn into_all(mut self) -> Vec<vm::Ty> {
-        // clear the current scope because it holds Rc<T> in the 'all' array
-        self.scope.scope.clear();
-        self.scope
-            .all
-            .into_iter()
-            .map(Rc::try_unwrap)
-            .map(Result::unwrap)
-            .collect()
+    /// Looks up an enum variant by its name.
+    ///
+    /// # Returns
+    /// The stub of the type that defines the variant, along with the variant's tag.
+    pub fn get_variant_by_name(&self, name: &str) -> Option<(&TyStub, usize)> {
+        self.iter()
+            .filter_map(|ty| ty.variants
+                .iter()
+                .position(|variant| variant.name == name)
+                .map(|tag| (ty, tag)))
+            .next()
+    }
+
+    pub fn vm_tys(&self) -> &[vm::Ty] {
+        &self.vm_tys
     }
 }
 
 impl Deref for TyScope {
-    type Target = Scope<vm::Ty, TySymbolAlloc>;
+    type Target = Scope<TyStub, TySymbolAlloc>;
 
     fn deref(&self) -> &Self::Target {
         &self.scope
@@ -298,6 +331,13 @@ impl FunctionScope {
                 name: function.name.clone(),
                 symbol: function.symbol,
                 params: function.params.len(),
+                variadic: false,
+                defaults: 0,
+                param_names: vec![],
+                param_tys: function.params
+                    .iter()
+                    .map(|ty| ir::TyExpr::from_builtin_ty(*ty))
+                    .collect(),
                 return_ty: ir::TyExpr::from_builtin_ty(function.return_ty.into()),
             };
             self.push_value(stub);
@@ -311,8 +351,19 @@ impl FunctionScope {
         self.vm_functions.push(function);
     }
 
+    /// Looks up the function that a call with the given number of arguments resolves to.
+    ///
+    /// A function that takes exactly that many parameters is preferred over a function with
+    /// default or variadic parameters.
     pub fn get_stub_by_params(&self, name: &str, params: usize) -> Option<&FunctionStub> {
-        self.get_value_by(|function| function.params == params && function.name() == name)
+        self.get_value_by(|function| function.params == params && !function.variadic && function.name() == name)
+            .or_else(|| self.get_value_by(|function| function.accepts(params) && function.name() == name))
+    }
+
+    /// Looks up the function with the given name and exact signature.
+    pub fn get_stub_by_signature(&self, name: &str, params: usize, variadic: bool) -> Option<&FunctionStub> {
+        self.get_value_by(|function| function.params == params && function.variadic == variadic
+                          && function.name() == name)
     }
 
     pub fn get_builtin(&self, name: &str) -> Option<&vm::Function> {
@@ -321,8 +372,8 @@ impl FunctionScope {
             .next()
     }
 
-    pub fn into_vm_functions(self) -> Vec<vm::Function> {
-        self.vm_functions
+    pub fn vm_functions(&self) -> &[vm::Function] {
+        &self.vm_functions
     }
 }
 
@@ -361,12 +412,35 @@ impl VariableScope {
             .unwrap()
     }
 
-    pub fn into_all(mut self) -> Vec<Variable> {
-        self.scope.scope.clear();
-        self.scope.all
-            .into_iter()
-            .map(|r| Rc::try_unwrap(r).unwrap())
-            .collect()
+    /// Pushes a new variable to the outermost scope, which holds the global variables.
+    ///
+    /// This function will panic if there is no current scope.
+    pub fn push_global_value(&mut self, name: &str) -> vm::VariableSymbol {
+        let symbol = self.scope.symbol_alloc.reserve_global_symbol();
+        let rc = Rc::new(Variable(name.to_string(), symbol));
+        self.scope.scope.first_mut()
+            .expect("attempted to push global value to empty scope")
+            .push(Rc::clone(&rc));
+        self.scope.all.push(rc);
+        symbol
+    }
+
+    /// Reserves a global variable that the language itself uses, such as the topic variable `$_`,
+    /// if it hasn't been reserved already.
+    ///
+    /// This function will panic if there is no current scope.
+    pub fn reserve_global(&mut self, name: &str) -> vm::VariableSymbol {
+        match self.get_global_value_by_name(name) {
+            Some(global) => global.symbol(),
+            None => self.push_global_value(name),
+        }
+    }
+
+    /// Looks up a global variable by its name.
+    pub fn get_global_value_by_name(&self, name: &str) -> Option<&Variable> {
+        self.scopes()
+            .next()
+            .and_then(|mut globals| globals.find(|value| value.name() == name))
     }
 }
 
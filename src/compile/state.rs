@@ -1,11 +1,32 @@
-use std::collections::{HashMap, HashSet};
-use syntax::token::{Op, AssignOp};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    iter,
+    mem,
+    path::{Path, PathBuf},
+};
+use common::read_file;
+use syntax::{
+    token::{Op, AssignOp},
+    tree::{Visibility, DeclKind, MapKind, Pragma},
+    Range,
+    Ranged,
+    OpTable,
+};
 use compile::{
+    self,
+    Module,
     FunctionStub,
     FunctionScope,
+    TyStub,
     TyScope,
     VariableScope,
     ReserveSymbol,
+    Pragmas,
+    Warning,
+    WarningCategory,
+    WarningLevel,
+    WarningSet,
+    SourceRange,
 };
 use ir::*;
 use vm::{
@@ -18,23 +39,129 @@ pub type Error = String;
 /// A compilation result.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// The argument given for each parameter of a function call, in parameter order.
+///
+/// Parameters that were not given an argument are `None`.
+type ArgSlots<'v, 'n> = Vec<Option<&'v Value<'n>>>;
+
 
 /// IR to bytecode compiler, complete with state.
 #[derive(Debug, Clone)]
 pub struct CompileState {
     operators: HashMap<Op, vm::FunctionSymbol>,
+    unary_operators: HashMap<Op, vm::FunctionSymbol>,
+    /// Operators that have been overloaded by user types, which are checked before falling back
+    /// to the operator's function in `operators`.
+    op_overloads: HashMap<Op, Vec<(vm::TySymbol, vm::FunctionSymbol)>>,
     body: Vec<Bc>,
     ty_scope: TyScope,
     function_scope: FunctionScope,
     variable_scope: VariableScope,
+    /// Warnings that have been emitted by the code being compiled.
+    warnings: Vec<Warning>,
+    /// Categories of warnings that stop compilation when they're emitted.
+    denied_warnings: WarningSet,
+    /// How each local variable of the code being compiled has been used so far.
+    variable_uses: BTreeMap<vm::VariableSymbol, VariableUse>,
+    /// The names of functions that have been called or referred to.
+    used_functions: HashSet<String>,
+    /// The function that the code being compiled belongs to, if any.
+    function: Option<FunctionFrame>,
+    /// Directories that modules are searched for in, in order.
+    search_path: Vec<PathBuf>,
+    /// The binary operators known while parsing, which is shared by every file and module so
+    /// that operators declared in one can be used by the others.
+    op_table: OpTable,
+    /// Modules whose operators have been added to `op_table`, by name.
+    parsed_modules: HashSet<String>,
+    /// Modules that have finished compiling, by name.
+    modules: HashMap<String, Module>,
+    /// Modules that are in the middle of compiling, from outermost to innermost.
+    loading: Vec<String>,
+    /// Qualified names of functions that are private to a module or type, along with a
+    /// description of what they're private to.
+    private_functions: HashMap<String, String>,
+    /// The number of VM blocks that the bytecode being compiled is nested inside of, relative to
+    /// the function that it belongs to.
+    block_depth: usize,
+    /// The loops that the bytecode being compiled is inside of, from outermost to innermost.
+    loops: Vec<LoopFrame>,
+    /// The pragmas in effect for the code being compiled.
+    pragmas: Pragmas,
+    /// The pragmas that each file and function start out with, before they use any of their own.
+    default_pragmas: Pragmas,
+    /// The values that `BEGIN` blocks have left in global variables, which the program starts out
+    /// with.
+    global_values: BTreeMap<vm::VariableSymbol, vm::Value>,
+    /// The constant pool, which holds literals and the values of `const` declarations.
+    constants: Vec<vm::Value>,
+    /// The slots in the constant pool that hold each literal.
+    literals: HashMap<Const, vm::ConstantSymbol>,
+    /// The constants that have been declared with `const`, by name.
+    named_constants: HashMap<String, vm::ConstantSymbol>,
+    /// The name of the source that the code being compiled comes from, as far as is known.
+    source: Option<String>,
+    /// The name of the source that the last error while compiling came from, if it's known.
+    error_source: Option<String>,
+    /// The VM that `BEGIN` blocks and the values of constants are run in.
+    compile_time_vm: CompileTimeVm,
     repl: bool,
 }
 
+/// The values of global variables, as they were left by code that ran.
+type GlobalValues = Vec<(vm::VariableSymbol, vm::Value)>;
+
+/// A VM that is kept around to run code at compile time.
+///
+/// While it isn't running anything, it holds a stand-in compiler, which is swapped with the real
+/// one whenever code runs so that anything the code evals stays compiled. It's made the first time
+/// it's needed, and isn't copied when the compiler is cloned.
+#[derive(Debug, Default)]
+struct CompileTimeVm(Option<Box<vm::Vm>>);
+
+impl Clone for CompileTimeVm {
+    fn clone(&self) -> Self {
+        CompileTimeVm::default()
+    }
+}
+
+/// How a local variable has been used, so that unused variables can be warned about.
+#[derive(Debug, Clone)]
+struct VariableUse {
+    name: String,
+    range: SourceRange,
+    /// The warnings that were on where the variable was declared.
+    warnings: WarningSet,
+    read: bool,
+    written: bool,
+}
+
+/// A function that is being compiled, which `return` statements are checked against.
+#[derive(Debug, Clone)]
+struct FunctionFrame {
+    name: String,
+    return_ty: TyExpr,
+    /// Whether any `return` in the function returns a value.
+    returns_value: bool,
+    /// Whether a `return` without a value has been warned about, which makes warning that the
+    /// function never returns a value redundant.
+    warned_bare_return: bool,
+}
+
+/// A loop that `break` and `continue` may jump out of.
+#[derive(Debug, Clone)]
+struct LoopFrame {
+    label: Option<String>,
+    /// The block depth of the loop's body.
+    depth: usize,
+}
+
 impl CompileState {
     pub fn new() -> Self {
         let builtin_functions = vm::BUILTIN_FUNCTIONS.iter()
             .cloned()
             .chain(vm::BUILTIN_OPERATORS.iter().map(|(_, f)| f).cloned())
+            .chain(vm::BUILTIN_UNARY_OPERATORS.iter().map(|(_, f)| f).cloned())
             .collect();
         let function_scope = FunctionScope::new()
             .with_builtins(builtin_functions);
@@ -45,17 +172,74 @@ impl CompileState {
                 .symbol();
             operators.insert(op.clone(), sym);
         }
+        let mut unary_operators = HashMap::new();
+        for (ref op, ref function) in vm::BUILTIN_UNARY_OPERATORS.iter() {
+            let sym = function_scope.get_stub_by_params(&function.name, function.params.len())
+                .unwrap()
+                .symbol();
+            unary_operators.insert(op.clone(), sym);
+        }
         let ty_scope = TyScope::new().with_builtins();
         CompileState {
             operators,
+            unary_operators,
+            op_overloads: HashMap::new(),
             ty_scope,
             function_scope,
             variable_scope: VariableScope::new(),
             body: vec![],
+            warnings: vec![],
+            denied_warnings: WarningSet::default(),
+            variable_uses: BTreeMap::new(),
+            used_functions: HashSet::new(),
+            function: None,
+            search_path: compile::env_search_path(),
+            op_table: OpTable::new(),
+            parsed_modules: HashSet::new(),
+            modules: HashMap::new(),
+            loading: vec![],
+            private_functions: HashMap::new(),
+            block_depth: 0,
+            loops: vec![],
+            pragmas: Pragmas::default(),
+            default_pragmas: Pragmas::default(),
+            global_values: BTreeMap::new(),
+            constants: vec![],
+            literals: HashMap::new(),
+            named_constants: HashMap::new(),
+            source: None,
+            error_source: None,
+            compile_time_vm: CompileTimeVm::default(),
             repl: false,
         }
     }
 
+    /// Sets whether unset values are warned about while the program runs, in code that hasn't
+    /// used `use warnings` or `no warnings`.
+    pub fn with_warnings(mut self, enabled: bool) -> Self {
+        self.default_pragmas.runtime.warn_unset = enabled;
+        self.pragmas = self.default_pragmas;
+        self
+    }
+
+    /// Sets how warnings of the given category are reported.
+    ///
+    /// Silenced categories may still be turned back on with `use warnings`, but categories that
+    /// are errors stop compilation wherever they're emitted.
+    pub fn with_warning_level(mut self, category: WarningCategory, level: WarningLevel) -> Self {
+        match level {
+            WarningLevel::Silent => self.default_pragmas.warnings.remove(category),
+            _ => self.default_pragmas.warnings.insert(category),
+        }
+        if level == WarningLevel::Error {
+            self.denied_warnings.insert(category);
+        } else {
+            self.denied_warnings.remove(category);
+        }
+        self.pragmas = self.default_pragmas;
+        self
+    }
+
     pub fn repl() -> Self {
         let mut compile_state = CompileState::new();
         compile_state.repl = true;
@@ -67,53 +251,55 @@ impl CompileState {
         self.function_scope.push_empty_scope();
         self.variable_scope.push_empty_scope();
         self.ty_scope.push_empty_scope();
+        self.variable_scope.reserve_global(TOPIC);
+        self.variable_scope.reserve_global(EVAL_ERROR);
     }
 
-    pub fn into_compile_unit(self) -> CompileUnit {
-        let CompileState {
-            // drop operators; they just keep track of the operators that the functions point at
-            operators: _,
-            body,
-            ty_scope,
-            function_scope,
-            mut variable_scope,
-            repl: _repl,
-        } = self;
-
-        let globals = variable_scope.pop_scope()
-            .unwrap()
-            .iter()
+    /// Gathers everything that has been compiled so far into a unit that a VM may run.
+    ///
+    /// Only what the VM runs is copied out of this state, so this may be called as often as code
+    /// needs to be run.
+    pub fn to_compile_unit(&self) -> CompileUnit {
+        let globals: Vec<_> = self.variable_scope
+            .iter_local()
             .map(|v| v.symbol())
             .collect();
-        let variables = variable_scope.into_all();
+        let global_values = globals.iter()
+            .map(|symbol| self.global_values.get(symbol).cloned().unwrap_or(vm::Value::Unset))
+            .collect();
+        let variables = self.variable_scope
+            .all()
+            .cloned()
+            .collect();
 
-        let mut tys = ty_scope.into_all();
-        let mut functions = function_scope.into_vm_functions();
+        let mut tys = self.ty_scope.vm_tys().to_vec();
+        let mut functions = self.function_scope.vm_functions().to_vec();
         // unstable sort is OK because there (hypothetically) are not duplicates
         functions.sort_unstable_by(|a, b| a.symbol().cmp(&b.symbol()));
         tys.sort_unstable_by(|a, b| a.symbol().cmp(&b.symbol()));
 
         CompileUnit {
-            body,
+            body: self.body.clone(),
             functions,
             tys,
             variables,
             globals,
+            global_values,
+            constants: self.constants.clone(),
+            pragmas: self.default_pragmas.runtime,
         }
     }
 
-    pub fn to_compile_unit(&self) -> CompileUnit {
-        // TODO : deep clone, this breaks the REPL
-        // or better REPL
-        self.clone()
-            .into_compile_unit()
+    /// Adds a directory to the end of the module search path.
+    pub fn add_search_path(&mut self, path: impl Into<PathBuf>) {
+        self.search_path.push(path.into());
     }
 
-    pub fn feed_str(&mut self, filename: &str, contents: &str) -> Result<()> {
+    /// Parses the given source into an IR tree, without compiling it.
+    pub fn parse_str<'n>(filename: &'n str, contents: &str) -> Result<IrTree<'n>> {
         use syntax::{Lexer, Parser};
-        use ir::IrTree;
 
-        let lexer = Lexer::new(contents.chars(), &filename);
+        let lexer = Lexer::new(contents.chars(), filename);
         let parser = Parser::from_lexer(lexer);
         let tree = match parser.into_parse_tree() {
             Ok(t) => t,
@@ -122,12 +308,89 @@ impl CompileState {
             },
         };
 
-        let ir_tree = IrTree::from_syntax(&tree);
-        
-        self.feed(&ir_tree)
+        Ok(IrTree::from_syntax(&tree))
+    }
+
+    /// Parses the given source into an IR tree, without compiling it.
+    ///
+    /// Unlike `parse_str`, the source may use operators declared by anything parsed before it, as
+    /// well as by the modules it uses. Operators it declares are known to anything parsed after
+    /// it.
+    pub fn parse<'n>(&mut self, filename: &'n str, contents: &str) -> Result<IrTree<'n>> {
+        use syntax::{Lexer, Parser, used_modules};
+
+        for name in used_modules(contents, filename) {
+            self.parse_module_operators(&name);
+        }
+        let lexer = Lexer::new(contents.chars(), filename);
+        let parser = Parser::from_lexer(lexer);
+        let tree = match parser.into_parse_tree_with(&mut self.op_table) {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(format!("parse error: {}", e));
+            },
+        };
+
+        Ok(IrTree::from_syntax(&tree))
+    }
+
+    /// Parses the module with the given name, if it hasn't been already, so that the operators it
+    /// declares can be parsed in the code that uses it.
+    ///
+    /// Errors are ignored here; they're reported when the module is compiled.
+    fn parse_module_operators(&mut self, name: &str) {
+        if !self.parsed_modules.insert(name.to_string()) {
+            return;
+        }
+        if let Some(path) = self.find_module(name) {
+            if let Ok(contents) = read_file(&path) {
+                let filename = path.display().to_string();
+                let _ = self.parse(&filename, &contents);
+            }
+        }
+    }
+
+    pub fn feed_str(&mut self, filename: &str, contents: &str) -> Result<Vec<Warning>> {
+        // operators declared by code that fails to compile are forgotten along with the rest of it
+        let op_table = self.op_table.clone();
+        let parsed_modules = self.parsed_modules.clone();
+        let result = self.parse(filename, contents)
+            .and_then(|ir_tree| self.feed(&ir_tree));
+        if result.is_err() {
+            self.op_table = op_table;
+            self.parsed_modules = parsed_modules;
+        }
+        result
+    }
+
+    /// Compiles code passed to `eval` at runtime, in the context of the program that has already
+    /// been compiled.
+    ///
+    /// Like a repl line, the code may define globals and functions that code compiled afterwards
+    /// can use, e.g. later evals. The program that is already running was compiled before they
+    /// existed, so it can't call them by name. Its body is kept out of the program's body.
+    ///
+    /// # Returns
+    /// The compile unit whose body is the compiled code, along with the warnings emitted while
+    /// compiling it.
+    pub fn compile_eval(&mut self, code: &str) -> Result<(CompileUnit, Vec<Warning>)> {
+        let body = mem::take(&mut self.body);
+        let repl = mem::replace(&mut self.repl, true);
+        let warnings = self.feed_str("(eval)", code);
+        self.repl = repl;
+        let eval_body = mem::replace(&mut self.body, body);
+        let warnings = warnings?;
+        let mut compile_unit = self.to_compile_unit();
+        compile_unit.body = eval_body;
+        Ok((compile_unit, warnings))
     }
 
-    pub fn feed<'n>(&mut self, ir_tree: &IrTree<'n>) -> Result<()> {
+    /// Compiles an IR tree, adding it to the program.
+    ///
+    /// # Returns
+    /// The warnings emitted while compiling the tree, or an error if any of them are in a
+    /// category that has been turned into errors.
+    pub fn feed<'n>(&mut self, ir_tree: &IrTree<'n>) -> Result<Vec<Warning>> {
         let known_good = self.clone();
         let result = {
             let mut feed = || {
@@ -135,75 +398,515 @@ impl CompileState {
                     // repls get a new body each time
                     self.body.clear();
                 }
-                // gather all function stubs
-                let stubs = self.compile_function_stubs(ir_tree.functions())?;
-                self.function_scope.push_all_values(stubs);
-
-                for user_type in ir_tree.user_types() {
-                    let ty = vm::Ty::User(self.compile_user_type(user_type)?);
-                    self.ty_scope.push_value(ty);
-                }
-
-                // compile functions
-                for function in ir_tree.functions() {
-                    let function = self.compile_function(function)?;
-                    self.function_scope.push_vm_function(vm::Function::User(function));
+                for module in ir_tree.uses() {
+                    self.compile_use(module)?;
                 }
+                self.declare_globals(ir_tree.actions())?;
+                self.compile_declarations(ir_tree)?;
 
                 let mut body = self.compile_action_list(ir_tree.actions())?;
                 self.body.append(&mut body);
-                Ok(())
+
+                // a repl may use its variables and functions in lines that haven't been fed yet
+                if self.repl {
+                    self.variable_uses.clear();
+                } else {
+                    self.check_unused_variables();
+                    self.check_unused_functions(ir_tree.functions())?;
+                }
+                let mut warnings = mem::take(&mut self.warnings);
+                // warnings are emitted by several passes, so they're put back in source order
+                let mut sources: Vec<Option<String>> = vec![];
+                for warning in &warnings {
+                    if !sources.contains(&warning.range.source) {
+                        sources.push(warning.range.source.clone());
+                    }
+                }
+                warnings.sort_by_key(|warning| {
+                    let start = warning.range.range.start();
+                    (sources.iter().position(|source| *source == warning.range.source), start.line, start.col)
+                });
+                let denied: Vec<_> = warnings.iter()
+                    .filter(|warning| self.denied_warnings.contains(warning.category))
+                    .collect();
+                if let Some(first) = denied.first() {
+                    self.source = first.range.source.clone();
+                    let denied: Vec<_> = denied.iter()
+                        .map(ToString::to_string)
+                        .collect();
+                    Err(self.err(denied.join("\n")))
+                } else {
+                    Ok(warnings)
+                }
             };
             feed()
         };
 
-        // undo any changes if an error occurred
+        // undo any changes if an error occurred, keeping track of where it came from
         if result.is_err() {
+            let error_source = self.source.take();
             *self = known_good;
+            self.error_source = error_source;
+        } else {
+            self.source = None;
+            self.error_source = None;
+        }
+        result
+    }
+
+    /// Gets the name of the source that the last error while compiling came from, if it's known.
+    pub fn error_source(&self) -> Option<&str> {
+        self.error_source.as_deref()
+    }
+
+    /// Notes that the code being compiled comes from the source of the given range, so that any
+    /// error while compiling it can be blamed on that source.
+    fn compiling_at(&mut self, range: Range) {
+        if let Some(source) = range.start().source_name {
+            if self.source.as_deref() != Some(source) {
+                self.source = Some(source.to_string());
+            }
+        }
+    }
+
+    /// Applies a pragma to the code that is compiled after it.
+    fn compile_pragma(&mut self, pragma: &Pragma) -> Result<()> {
+        let applied = self.pragmas.apply(pragma);
+        applied.map_err(|e| self.err(format!("{}: {}", SourceRange::from(pragma.range), e)))
+    }
+
+    /// Compiles all types, functions, and operators declared by the given IR tree.
+    fn compile_declarations<'n>(&mut self, ir_tree: &IrTree<'n>) -> Result<()> {
+        // gather all type stubs
+        let ty_stubs = self.compile_ty_stubs(ir_tree.user_types())?;
+        self.ty_scope.push_all_values(ty_stubs);
+
+        // gather function stubs for all types, so public type functions may be called from anywhere
+        let mut ty_function_stubs = vec![];
+        for user_type in ir_tree.user_types() {
+            let stubs = self.compile_ty_function_stubs(user_type)?;
+            ty_function_stubs.push(stubs);
+        }
+
+        // gather all function stubs, including enum variant constructors
+        let constructors = ir_tree.user_types()
+            .iter()
+            .flat_map(|ty| ty.variants.iter())
+            .map(|variant| &variant.constructor);
+        let operator_functions = ir_tree.operators()
+            .iter()
+            .map(|operator| &operator.function);
+        let stubs = self.compile_function_stubs(ir_tree.functions()
+                                                .iter()
+                                                .chain(operator_functions)
+                                                .chain(constructors))?;
+        self.function_scope.push_all_values(stubs);
+
+        // register operators, so types can overload them
+        for operator in ir_tree.operators() {
+            if let Some(range) = operator.function.range {
+                self.compiling_at(range);
+            }
+            if self.operators.contains_key(&operator.op) {
+                return Err(self.err(format!("operator `{}` has already been defined", operator.op)));
+            }
+            let symbol = self.function_scope.get_stub_by_params(operator.function.name(), 2)
+                .unwrap()
+                .symbol();
+            self.operators.insert(operator.op.clone(), symbol);
+        }
+
+        // compile types
+        for (user_type, stubs) in ir_tree.user_types().iter().zip(ty_function_stubs) {
+            let ty = self.compile_user_type(user_type, stubs)?;
+            if user_type.is_enum() {
+                self.compile_variant_constructors(user_type, &ty)?;
+            }
+            self.ty_scope.push_vm_ty(vm::Ty::User(ty));
+        }
+
+        // compile functions
+        let operator_functions = ir_tree.operators()
+            .iter()
+            .map(|operator| &operator.function);
+        for function in ir_tree.functions().iter().chain(operator_functions) {
+            let function = self.compile_function(function)?;
+            self.function_scope.push_vm_function(vm::Function::User(function));
+        }
+        Ok(())
+    }
+
+    /// Imports the given module, compiling it first if it has not been compiled yet.
+    ///
+    /// Everything that the module exports is made visible in the current scope under its qualified
+    /// name, e.g. `Some::Module::func`.
+    fn compile_use(&mut self, name: &str) -> Result<()> {
+        if self.loading.iter().any(|loading| loading == name) {
+            let cycle = self.loading
+                .iter()
+                .skip_while(|loading| *loading != name)
+                .map(String::as_str)
+                .chain(iter::once(name))
+                .collect::<Vec<_>>();
+            return Err(self.err(format!("module import cycle: {}", cycle.join(" -> "))));
+        }
+        if !self.modules.contains_key(name) {
+            let module = self.compile_module(name)?;
+            self.modules.insert(name.to_string(), module);
+        }
+
+        let module = &self.modules[name];
+        for function in &module.functions {
+            let qualified = module.qualify(&function.name);
+            let exists = self.function_scope
+                .get_local_value_by(|f| f.name == qualified && f.params == function.params && f.variadic == function.variadic)
+                .is_some();
+            if !exists {
+                self.function_scope.push_value(FunctionStub { name: qualified, ..function.clone() });
+            }
+        }
+        for ty in &module.tys {
+            let qualified = module.qualify(&ty.name);
+            if self.ty_scope.get_local_value_by_name(&qualified).is_none() {
+                let variants = ty.variants
+                    .iter()
+                    .map(|variant| vm::VariantDef { name: module.qualify(&variant.name), ..variant.clone() })
+                    .collect();
+                self.ty_scope.push_value(TyStub { name: qualified, variants, ..ty.clone() });
+            }
         }
+        Ok(())
+    }
+
+    /// Finds the file that the module with the given name lives in on the search path.
+    fn find_module(&self, name: &str) -> Option<PathBuf> {
+        let relative_path = Module::relative_path(name);
+        self.search_path
+            .iter()
+            .map(|dir| dir.join(&relative_path))
+            .find(|path| path.is_file())
+    }
+
+    /// Finds, parses, and compiles the module with the given name.
+    ///
+    /// Modules are compiled in their own namespace, which only sees builtins and the modules it
+    /// imports itself.
+    fn compile_module(&mut self, name: &str) -> Result<Module> {
+        let path = if let Some(path) = self.find_module(name) {
+            path
+        } else {
+            let relative_path = Module::relative_path(name);
+            let searched = self.search_path
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(self.err(format!("could not find module `{}` ({} not found in: {})",
+                                        name, relative_path.display(), searched.join(", "))));
+        };
+        // errors in the module name it, so they're blamed on the code that uses it
+        let outer_source = self.source.clone();
+        let result = self.compile_module_file(name, &path);
+        self.source = outer_source;
         result
     }
 
-    fn compile_function_stubs<'n>(&mut self, functions: &[Function<'n>]) -> Result<Vec<FunctionStub>> {
+    /// Parses and compiles the module with the given name from the file that it lives in.
+    fn compile_module_file(&mut self, name: &str, path: &Path) -> Result<Module> {
+        let filename = path.display().to_string();
+        let contents = read_file(path)
+            .map_err(|e| self.err(format!("could not read module `{}` from {}: {}", name, filename, e)))?;
+        let ir_tree = self.parse(&filename, &contents)
+            .map_err(|e| self.err(format!("in module `{}`: {}", name, e)))?;
+        if !ir_tree.actions().is_empty() {
+            return Err(self.err(format!("module `{}` may only contain declarations, but {} has top-level statements",
+                                        name, filename)));
+        }
+
+        // builtins always live in the topmost function and type scope layers, which stay visible
+        self.loading.push(name.to_string());
+        let function_scopes = self.function_scope.detach_scopes(1);
+        let ty_scopes = self.ty_scope.detach_scopes(1);
+        let variable_scopes = self.variable_scope.detach_scopes(0);
+        self.function_scope.push_empty_scope();
+        self.ty_scope.push_empty_scope();
+        self.variable_scope.push_empty_scope();
+
+        let result = ir_tree.uses()
+            .iter()
+            .try_for_each(|module| self.compile_use(module))
+            .and_then(|_| self.compile_declarations(&ir_tree));
+
+        let functions = self.function_scope.pop_scope().unwrap();
+        let tys = self.ty_scope.pop_scope().unwrap();
+        self.variable_scope.pop_scope();
+        self.function_scope.restore_scopes(function_scopes);
+        self.ty_scope.restore_scopes(ty_scopes);
+        self.variable_scope.restore_scopes(variable_scopes);
+        self.loading.pop();
+        result.map_err(|e| self.err(format!("in module `{}`: {}", name, e)))?;
+
+        let mut module = Module {
+            name: name.to_string(),
+            functions: vec![],
+            tys: vec![],
+        };
+        // public functions are exported without having to be listed
+        let public_functions = ir_tree.functions()
+            .iter()
+            .filter(|function| function.visibility == Visibility::Public)
+            .map(Function::name);
+        let mut exports: Vec<&str> = vec![];
+        for export in ir_tree.exports().iter().map(String::as_str).chain(public_functions) {
+            if !exports.contains(&export) {
+                exports.push(export);
+            }
+        }
+        for export in exports {
+            let mut exported_functions: Vec<FunctionStub> = functions.iter()
+                .filter(|function| function.name == export)
+                .map(|function| (**function).clone())
+                .collect();
+            let exported_ty = tys.iter()
+                .find(|ty| ty.name == export);
+            if let Some(ty) = exported_ty {
+                // exporting a type exports its public functions and variant constructors as well
+                let ty_prefix = format!("{}{}", ty.name, compile::NAMESPACE_SEPARATOR);
+                exported_functions.extend(functions.iter()
+                    .filter(|function| function.name.starts_with(&ty_prefix)
+                            || ty.variants.iter().any(|variant| variant.name == function.name))
+                    .map(|function| (**function).clone()));
+                module.tys.push((**ty).clone());
+            } else if exported_functions.is_empty() {
+                return Err(self.err(format!("module `{}` exports `{}`, which it does not define", name, export)));
+            }
+            module.functions.append(&mut exported_functions);
+        }
+
+        for function in &functions {
+            if !module.functions.iter().any(|exported| exported.symbol == function.symbol) {
+                self.private_functions.insert(module.qualify(&function.name), format!("module `{}`", name));
+            }
+        }
+        Ok(module)
+    }
+
+    fn compile_function_stubs<'f, 'n: 'f>(&mut self, functions: impl IntoIterator<Item=&'f Function<'n>>)
+        -> Result<Vec<FunctionStub>>
+    {
         // gather all function stubs
-        let mut stubs = vec![];
+        let mut stubs: Vec<FunctionStub> = vec![];
         for function in functions {
-            if self.function_scope.get_value_by_name(function.name()).is_some() {
-                return Err(self.err(format!("function `{}` defined twice in the same scope", function.name())));
+            if let Some(range) = function.range {
+                self.compiling_at(range);
             }
             let stub = FunctionStub {
                 name: function.name().to_string(),
                 symbol: self.function_scope.reserve_symbol(),
-                params: function.params.len(),
+                params: function.fixed_param_count(),
+                variadic: function.is_variadic(),
+                defaults: function.default_param_count(),
+                param_names: function.param_names(),
+                param_tys: function.param_tys(),
                 return_ty: function.return_ty.clone(),
             };
+            let defined_in_batch = stubs.iter()
+                .any(|other| other.same_signature(&stub) && other.param_tys == stub.param_tys);
+            // builtins may be overloaded with a different number of parameters
+            let builtin = vm::BUILTIN_FUNCTIONS.iter()
+                .any(|builtin| builtin.name == stub.name);
+            let defined = if builtin {
+                self.function_scope.iter().any(|other| other.same_signature(&stub))
+            } else {
+                self.function_scope.get_value_by_name(function.name()).is_some()
+            };
+            if defined || defined_in_batch {
+                return Err(self.err(format!("function `{}` defined twice in the same scope", function.name())));
+            }
+            if let (true, Some(range)) = (builtin, function.range) {
+                let warnings = self.function_pragmas(function)?.warnings;
+                self.warn_in(warnings, WarningCategory::ShadowedBuiltin, range,
+                             format!("function `{}` has the same name as a builtin function", stub.name));
+            }
+            stubs.push(stub);
+        }
+
+        // functions that only differ by their parameter types are dispatched between at runtime
+        let mut dispatch_stubs: Vec<FunctionStub> = vec![];
+        for stub in &stubs {
+            let overloads: Vec<&FunctionStub> = stubs.iter()
+                .filter(|other| other.same_signature(stub))
+                .collect();
+            if overloads.len() > 1 && !dispatch_stubs.iter().any(|dispatch| dispatch.same_signature(stub)) {
+                let dispatch = self.compile_dispatch_function(&overloads)?;
+                dispatch_stubs.push(dispatch);
+            }
+        }
+        for stub in &mut stubs {
+            if dispatch_stubs.iter().any(|dispatch| dispatch.same_signature(stub)) {
+                stub.name = FunctionStub::overload_name(&stub.name, &stub.param_tys);
+            }
+        }
+        stubs.append(&mut dispatch_stubs);
+        Ok(stubs)
+    }
+
+    /// Compiles the function that calls are dispatched through when several functions have the
+    /// same name and number of parameters.
+    ///
+    /// # Returns
+    /// The stub that calls to these functions resolve to.
+    fn compile_dispatch_function(&mut self, overloads: &[&FunctionStub]) -> Result<FunctionStub> {
+        let first = overloads[0];
+        if overloads.iter().any(|stub| stub.defaults != first.defaults) {
+            return Err(self.err(format!("every function `{}` that takes {} parameter(s) must have the same number of default parameters",
+                                        first.name, first.params)));
+        }
+
+        let mut candidates = vec![];
+        for stub in overloads {
+            let mut param_tys = vec![];
+            for (ty, param_name) in stub.param_tys.iter().zip(stub.param_names.iter()) {
+                let ty = match ty {
+                    TyExpr::Definite(ty_name) => match self.ty_scope.get_value_by_name(ty_name) {
+                        Some(ty) => Some(ty.symbol()),
+                        None => return Err(self.err(format!("unknown type name for parameter `{}` in function definition `{}`: `{}`",
+                                                            param_name, stub.name, ty_name))),
+                    },
+                    _ => None,
+                };
+                param_tys.push(ty);
+            }
+            candidates.push(vm::DispatchCandidate { function: stub.symbol, param_tys });
+        }
+
+        // named arguments and return values are only known if every candidate agrees on them
+        let param_names = if overloads.iter().all(|stub| stub.param_names == first.param_names) {
+            first.param_names.clone()
+        } else {
+            vec![]
+        };
+        let return_ty = if overloads.iter().all(|stub| stub.return_ty == first.return_ty) {
+            first.return_ty.clone()
+        } else {
+            TyExpr::Any
+        };
+        let stub = FunctionStub {
+            name: first.name.clone(),
+            symbol: self.function_scope.reserve_symbol(),
+            params: first.params,
+            variadic: first.variadic,
+            defaults: first.defaults,
+            param_names,
+            param_tys: vec![TyExpr::Any; first.params],
+            return_ty,
+        };
+        self.function_scope.push_vm_function(vm::Function::Dispatch(vm::DispatchFunction {
+            symbol: stub.symbol,
+            name: stub.name.clone(),
+            params: stub.params,
+            variadic: stub.variadic,
+            defaults: stub.defaults,
+            candidates,
+        }));
+        Ok(stub)
+    }
+
+    fn compile_ty_stubs<'n>(&mut self, user_types: &[UserTy<'n>]) -> Result<Vec<TyStub>> {
+        // gather all type stubs
+        let mut stubs: Vec<TyStub> = vec![];
+        for udt in user_types {
+            self.compiling_at(udt.range);
+            // check if this type is already defined
+            if self.ty_scope.get_local_value_by_name(&udt.name).is_some()
+                || stubs.iter().any(|stub| stub.name == udt.name) {
+                return Err(self.err(format!("type `{}` has already been defined in this scope", udt.name)));
+            }
+            let variants = udt.variants
+                .iter()
+                .map(|variant| vm::VariantDef { name: variant.name.clone(), fields: variant.field_count() })
+                .collect();
+            let stub = TyStub {
+                name: udt.name.clone(),
+                symbol: self.ty_scope.reserve_symbol(),
+                variants,
+            };
             stubs.push(stub);
         }
         Ok(stubs)
     }
 
-    fn compile_user_type<'n>(&mut self, udt: &UserTy<'n>) -> Result<vm::UserTy> {
-        // TODO(predicate) : order-agnostic user defined types
+    /// Gathers the function stubs for all functions and operators defined inside of the given type.
+    ///
+    /// Public functions are made visible outside of the type under the type's name, e.g.
+    /// `Counter::new`. Private functions may only be called from inside of the type.
+    fn compile_ty_function_stubs<'n>(&mut self, udt: &UserTy<'n>) -> Result<Vec<FunctionStub>> {
+        self.compiling_at(udt.range);
+        let operator_functions = udt.operators
+            .iter()
+            .map(|operator| &operator.function);
+        self.function_scope.push_empty_scope();
+        let stubs = self.compile_function_stubs(udt.functions.iter().chain(operator_functions));
+        self.function_scope.pop_scope();
+        let stubs = stubs?;
+
+        // operator functions are only reachable through their operators, and functions that are
+        // dispatched between are only reachable through their dispatch function
+        for stub in &stubs {
+            let function = udt.functions
+                .iter()
+                .find(|function| function.name() == stub.name && function.fixed_param_count() == stub.params
+                      && function.is_variadic() == stub.variadic);
+            let function = if let Some(function) = function {
+                function
+            } else {
+                continue;
+            };
+            let qualified = format!("{}{}{}", udt.name, compile::NAMESPACE_SEPARATOR, stub.name);
+            if function.visibility == Visibility::Public {
+                self.function_scope.push_value(FunctionStub { name: qualified, ..stub.clone() });
+            } else {
+                self.private_functions.insert(qualified, format!("type `{}`", udt.name));
+            }
+        }
+        Ok(stubs)
+    }
+
+    fn compile_user_type<'n>(&mut self, udt: &UserTy<'n>, stubs: Vec<FunctionStub>) -> Result<vm::UserTy> {
+        self.compiling_at(udt.range);
         self.function_scope.push_empty_scope();
         if !udt.parents.is_empty() {
             // TODO(predicate) : deal with udt parents
             unimplemented!("TODO : compile IR user-defined type with parents");
         }
 
-        // check if this type is already defined
-        if self.ty_scope.get_local_value_by_name(&udt.name).is_some() {
-            return Err(self.err(format!("type `{}` has already been defined in this scope", udt.name)));
-        }
-        
-        // collect function stubs
-        let stubs = self.compile_function_stubs(&udt.functions)?;
+        // the type stub was reserved before any type or function was compiled
+        let user_ty_symbol = self.ty_scope.get_local_value_by_name(&udt.name)
+            .unwrap_or_else(|| panic!("stub for type {} was expected to exist, but does not", udt.name))
+            .symbol();
+
+        // function stubs were gathered before any type or function was compiled
         self.function_scope.push_all_values(stubs);
+        let operator_functions = udt.operators
+            .iter()
+            .map(|operator| &operator.function);
 
-        // TODO(predicate) order agnostic user types
+        // register operator overloads
+        for operator in &udt.operators {
+            if !self.operators.contains_key(&operator.op) {
+                return Err(self.err(format!("type `{}` overloads operator `{}`, which has not been defined",
+                                            udt.name, operator.op)));
+            }
+            let symbol = self.function_scope.get_stub_by_params(operator.function.name(), 2)
+                .unwrap()
+                .symbol();
+            self.op_overloads.entry(operator.op.clone())
+                .or_default()
+                .push((user_ty_symbol, symbol));
+        }
 
         // compile functions
         let mut udt_functions = vec![];
-        for ir_function in &udt.functions {
+        for ir_function in udt.functions.iter().chain(operator_functions) {
             let function = self.compile_function(ir_function)?;
             // XXX(predicate) : reference functions instead of cloning them
             udt_functions.push(function.symbol);
@@ -231,98 +934,384 @@ impl CompileState {
                 .unwrap()
                 .symbol()
         };
-        let user_ty_symbol = self.ty_scope.reserve_symbol();
+
+        let variants = self.ty_scope.get_value_by_symbol(user_ty_symbol)
+            .unwrap()
+            .variants
+            .clone();
 
         Ok(vm::UserTy{
             name: udt.name.clone(),
             symbol: user_ty_symbol,
             predicate,
             functions: udt_functions,
+            variants,
         })
     }
 
+    /// Compiles the constructor functions for every variant of the given enum type.
+    ///
+    /// Constructor stubs are gathered alongside the other function stubs, so that variants may be
+    /// constructed before their enum has been declared.
+    fn compile_variant_constructors<'n>(&mut self, udt: &UserTy<'n>, ty: &vm::UserTy) -> Result<()> {
+        for (tag, variant) in udt.variants.iter().enumerate() {
+            let mut constructor = self.compile_function(&variant.constructor)?;
+            // params are always the first locals of a function
+            let fields = constructor.params;
+            for field in &constructor.locals[0 .. fields] {
+                constructor.body.push(Bc::PushSymbolValue(*field));
+            }
+            constructor.body.push(Bc::MakeVariant { ty: ty.symbol, tag, fields });
+            constructor.body.push(Bc::Ret(None));
+            self.function_scope.push_vm_function(vm::Function::User(constructor));
+        }
+        Ok(())
+    }
+
     /// Converts a sequence of IR actions to a sequence of bytecode.
     fn compile_action_list<'n>(&mut self, actions: &[Action<'n>]) -> Result<Vec<Bc>> {
+        self.compile_scoped_action_list(actions, |_| Ok(vec![]))
+    }
+
+    /// Compiles a list of actions, followed by the bytecode compiled by `tail`.
+    ///
+    /// Pragmas used by the actions last until the end of the list, including `tail`.
+    fn compile_scoped_action_list<'n, F>(&mut self, actions: &[Action<'n>], tail: F) -> Result<Vec<Bc>>
+        where F: FnOnce(&mut Self) -> Result<Vec<Bc>>
+    {
+        let outer_pragmas = self.pragmas;
+        let result = self.compile_pragma_action_list(actions, tail);
+        self.pragmas = outer_pragmas;
+        result
+    }
+
+    fn compile_pragma_action_list<'n, F>(&mut self, actions: &[Action<'n>], tail: F) -> Result<Vec<Bc>>
+        where F: FnOnce(&mut Self) -> Result<Vec<Bc>>
+    {
         let mut body = vec![];
-        for action in actions {
-            body.append(&mut self.compile_action(action)?);
+        for (index, action) in actions.iter().enumerate() {
+            if let Some(range) = action.range() {
+                self.compiling_at(range);
+            }
+            if let Action::Pragma(pragma) = action {
+                let outer_runtime = self.pragmas.runtime;
+                self.compile_pragma(pragma)?;
+                let runtime = self.pragmas.runtime;
+                if runtime != outer_runtime {
+                    // the rest of the list runs in a block, so the VM can restore the outer
+                    // pragmas however the block is exited
+                    let rest = &actions[index + 1 ..];
+                    let block = self.compile_nested(1, |this| this.compile_pragma_action_list(rest, tail))?;
+                    body.push(Bc::PragmaBlock(runtime, block));
+                    return Ok(body);
+                }
+            } else {
+                body.append(&mut self.compile_action(action)?);
+                self.check_unreachable(action, &actions[index + 1 ..]);
+            }
         }
+        body.append(&mut tail(self)?);
         Ok(body)
     }
 
     /// Compiles an IR action into a sequence of bytecode.
     fn compile_action<'n>(&mut self, action: &Action<'n>) -> Result<Vec<Bc>> {
         let thunk = match action {
-            Action::Eval(value) => self.compile_value(value, ValueContext::Push)?,
+            // declaring variables without assigning to them doesn't use them
+            Action::Eval(Value::Declare(kind, target)) => self.declare_variables(*kind, target)?,
+            Action::Eval(value) => self.compile_value_wanting(value, ValueContext::Push, vm::CallContext::Void)?,
             Action::Assign(lhs, op, rhs) => self.compile_action_assign(lhs, *op, rhs)?,
-            Action::Loop(block) => {
-                let mut loop_body = self.compile_action_list(block)?;
+            Action::Loop { label, condition, block } => {
+                let mut loop_body = self.compile_loop(label, |this| {
+                    let mut body = vec![];
+                    if let Some(condition) = condition {
+                        // a true condition skips to the loop's body, and a false one exits the loop
+                        let mut check = this.compile_nested(1, |this| this.compile_comparison(condition))?;
+                        check.push(Bc::ConditionBlock(vec![Bc::ExitBlock(1)]));
+                        check.push(Bc::ExitBlock(1));
+                        body.push(Bc::Block(check));
+                    }
+                    body.append(&mut this.compile_action_list(block)?);
+                    Ok(body)
+                })?;
                 loop_body.push(Bc::Compare(Condition::Always));
                 loop_body.push(Bc::JumpBlockTop(0));
-                loop_body
+                vec![Bc::Block(loop_body)]
             },
+            Action::For { label, target, iterable, block } => {
+                let array_sym = self.variable_scope.push_anonymous_symbol()
+                    .symbol();
+                let index_sym = self.variable_scope.push_anonymous_symbol()
+                    .symbol();
+                let mut for_body = self.compile_value_wanting(iterable, ValueContext::StoreInto(array_sym), vm::CallContext::List)?;
+                for_body.push(Bc::Store(index_sym, vm::Value::Int(0)));
+
+                // each iteration assigns the next item to the target, exiting once there are none
+                let mut loop_body = vec![
+                    Bc::NextItem { array: array_sym, index: index_sym },
+                    Bc::ConditionBlock(vec![Bc::ExitBlock(1)]),
+                ];
+                loop_body.append(&mut self.compile_loop(label, |this| {
+                    let mut body = this.compile_assign_target(target)?;
+                    this.exempt_loop_target(target);
+                    body.append(&mut this.compile_action_list(block)?);
+                    Ok(body)
+                })?);
+                loop_body.push(Bc::Compare(Condition::Always));
+                loop_body.push(Bc::JumpBlockTop(0));
+                for_body.push(Bc::Block(loop_body));
+                for_body
+            }
             Action::Block(block) => self.compile_action_list(block)?,
+            Action::Begin(range, block) => {
+                self.run_begin_block(*range, block)?;
+                vec![]
+            }
+            Action::Const(range, name, value) => {
+                self.declare_constant(*range, name, value)?;
+                vec![]
+            }
             Action::ConditionBlock { if_block, elseif_blocks, else_block } => {
                 let mut bc = vec![];
 
-                // if block
-                {
-                    bc.append(&mut self.compile_comparison(&if_block.condition)?);
-                    bc.append(&mut self.compile_action(&if_block.action)?);
-                }
-
-                // elseif blocks
-                for block in elseif_blocks {
+                // if and elseif blocks; the first one that runs exits the whole if block
+                for block in iter::once(if_block.as_ref()).chain(elseif_blocks.iter()) {
                     bc.append(&mut self.compile_comparison(&block.condition)?);
-                    bc.append(&mut self.compile_action(&block.action)?);
+                    let mut block_body = self.compile_nested(2, |this| this.compile_action(&block.action))?;
+                    block_body.push(Bc::ExitBlock(1));
+                    bc.push(Bc::ConditionBlock(block_body));
                 }
 
                 // else block
                 if let Some(block) = else_block {
-                    bc.append(&mut self.compile_action(block)?);
+                    bc.append(&mut self.compile_nested(1, |this| this.compile_action(block))?);
                 }
 
                 vec![Bc::Block(bc)]
             }
-            Action::Return(None) => vec![Bc::Ret(None)],
-            Action::Return(Some(ref s)) => self.compile_value(s, ValueContext::Ret)?,
-            Action::Break => vec![Bc::Compare(Condition::Always), Bc::ExitBlock(0)],
-            Action::Continue => vec![Bc::Compare(Condition::Always), Bc::JumpBlockTop(0)],
+            Action::Pragma(_) => unreachable!("pragmas are compiled along with the list of actions that they're in"),
+            Action::Return(range, None) => {
+                self.check_return(*range, None);
+                vec![Bc::Ret(None)]
+            }
+            Action::Return(range, Some(ref s)) => {
+                self.check_return(*range, Some(s));
+                self.compile_value(s, ValueContext::Ret)?
+            }
+            Action::Break(_, label) => {
+                let depth = self.loop_jump_depth(label.as_ref(), "break")?;
+                vec![Bc::Compare(Condition::Always), Bc::ExitBlock(depth)]
+            }
+            Action::Continue(_, label) => {
+                let depth = self.loop_jump_depth(label.as_ref(), "continue")?;
+                vec![Bc::Compare(Condition::Always), Bc::JumpBlockTop(depth)]
+            }
+            Action::Match(m) => self.compile_match(m, vec![], |this, action| this.compile_action(action))?,
         };
         Ok(thunk)
     }
 
-    /// Compiles an IR function into a VM function.
-    pub fn compile_function<'n>(&mut self, function: &Function<'n>) -> Result<vm::UserFunction> {
-        self.function_scope.push_empty_scope();
-        self.variable_scope.push_empty_scope();
+    /// Compiles a `BEGIN` block and runs it right away, keeping the values that it leaves in global
+    /// variables for the rest of the program.
+    fn run_begin_block(&mut self, range: Range, block: &[Action]) -> Result<()> {
+        if self.function.is_some() {
+            return Err(self.err(format!("{}: `BEGIN` blocks may not be used inside of functions", SourceRange::from(range))));
+        }
+        let body = self.compile_detached(|this| this.compile_action_list(block))?;
+        let (global_values, _) = self.run_at_compile_time(body)
+            .map_err(|e| self.err(format!("{}: error in `BEGIN` block: {}", SourceRange::from(range), e)))?;
+        self.global_values.extend(global_values);
+        Ok(())
+    }
 
-        let symbol = match &function.symbol {
-            Symbol::Function(name) => {
-                self.function_scope.get_stub_by_params(name, function.params.len())
-                    .expect(&format!("symbol for function {} (param count {}) was expected to exist, but does not",
-                                     name, function.params.len()))
-                    .symbol()
-            },
-            sym => panic!("got non-function symbol name from IR::Function: {:?}", sym),
-        };
+    /// Reserves a slot in the constant pool for a constant, so that functions compiled before its
+    /// value is computed may refer to it.
+    fn reserve_constant(&mut self, range: Range, name: &str) -> Result<()> {
+        if self.named_constants.contains_key(name) {
+            return Err(self.err(format!("{}: constant `{}` is already declared", SourceRange::from(range), name)));
+        }
+        self.constants.push(vm::Value::Unset);
+        self.named_constants.insert(name.to_string(), vm::ConstantSymbol(self.constants.len() - 1));
+        Ok(())
+    }
 
-        let mut param_names = HashSet::new();
-        let mut body: Vec<Bc> = vec![];
-        for param in &function.params {
-            let param_name = param.name()
-                .to_string();
-            if param_names.contains(&param_name) {
-                return Err(self.err(format!("duplicate function parameter `{}` in function definition `{}`",
-                                            param_name,
-                                            function.symbol.name())));
-            }
+    /// Computes the value of a constant, and puts it in the constant's slot in the constant pool.
+    fn declare_constant(&mut self, range: Range, name: &str, value: &Value) -> Result<()> {
+        if self.function.is_some() {
+            return Err(self.err(format!("{}: constants may not be declared inside of functions", SourceRange::from(range))));
+        }
+        let body = self.compile_detached(|this| this.compile_value(value, ValueContext::Push))?;
+        let (_, value) = self.run_at_compile_time(body)
+            .map_err(|e| self.err(format!("{}: error in value of constant `{}`: {}", SourceRange::from(range), name, e)))?;
+        let constant = self.named_constants[name];
+        self.constants[*constant] = value.unwrap_or(vm::Value::Unset);
+        Ok(())
+    }
+
+    /// Compiles bytecode that is run on its own at compile time, so it can't break out of any
+    /// loops around it.
+    fn compile_detached<F>(&mut self, compile: F) -> Result<Vec<Bc>>
+        where F: FnOnce(&mut Self) -> Result<Vec<Bc>>
+    {
+        let loops = mem::take(&mut self.loops);
+        let block_depth = mem::replace(&mut self.block_depth, 0);
+        let result = compile(self);
+        self.loops = loops;
+        self.block_depth = block_depth;
+        result
+    }
+
+    /// Runs bytecode right away, with the globals, functions, and constants that have been
+    /// compiled so far.
+    ///
+    /// # Returns
+    /// The values that the bytecode left in global variables, along with the value that it left
+    /// on top of the stack.
+    fn run_at_compile_time(&mut self, body: Vec<Bc>) -> Result<(GlobalValues, Option<vm::Value>)> {
+        let mut compile_unit = self.to_compile_unit();
+        compile_unit.body = body;
+        compile_unit.pragmas = self.pragmas.runtime;
+        let mut vm = self.compile_time_vm.0
+            .take()
+            .unwrap_or_else(|| {
+                let mut vm = vm::Vm::new();
+                vm.set_compiler(CompileState::new());
+                Box::new(vm)
+            });
+        // anything that the bytecode evals stays compiled
+        mem::swap(self, vm.compiler_mut().expect("compile-time compiler was taken"));
+        let result = vm.repl_launch(compile_unit);
+        mem::swap(self, vm.compiler_mut().expect("compile-time compiler was taken"));
+        self.warnings.append(&mut vm.take_warnings());
+        let result = result.map(|value| (vm.global_values(), value));
+        vm.unload();
+        self.compile_time_vm = CompileTimeVm(Some(vm));
+        result
+    }
+
+    /// Adds a literal to the constant pool, unless it's already in it.
+    ///
+    /// # Returns
+    /// A reference to the literal in the constant pool.
+    fn intern_constant(&mut self, literal: &Const) -> vm::ConstantSymbol {
+        if let Some(constant) = self.literals.get(literal) {
+            return *constant;
+        }
+        self.constants.push(literal.clone().into());
+        let constant = vm::ConstantSymbol(self.constants.len() - 1);
+        self.literals.insert(literal.clone(), constant);
+        constant
+    }
+
+    /// Converts a literal into a VM value, which refers to the constant pool unless the literal is
+    /// `undef`.
+    fn literal_value(&mut self, value: &Const) -> vm::Value {
+        match value {
+            Const::Undef => vm::Value::Unset,
+            value => vm::Value::Constant(self.intern_constant(value)),
+        }
+    }
+
+    /// Compiles bytecode that will be nested inside of the given number of VM blocks, so that any
+    /// `break` or `continue` inside of it knows how many blocks to jump out of.
+    fn compile_nested<F>(&mut self, blocks: usize, compile: F) -> Result<Vec<Bc>>
+        where F: FnOnce(&mut Self) -> Result<Vec<Bc>>
+    {
+        self.block_depth += blocks;
+        let result = compile(self);
+        self.block_depth -= blocks;
+        result
+    }
+
+    /// Compiles the body of a loop, which `break` and `continue` inside of it may refer to.
+    fn compile_loop<F>(&mut self, label: &Option<String>, compile: F) -> Result<Vec<Bc>>
+        where F: FnOnce(&mut Self) -> Result<Vec<Bc>>
+    {
+        if let Some(label) = label {
+            if self.loops.iter().any(|frame| frame.label.as_ref() == Some(label)) {
+                return Err(self.err(format!("loop label `{}` is already used by an enclosing loop", label)));
+            }
+        }
+        self.loops.push(LoopFrame { label: label.clone(), depth: self.block_depth + 1 });
+        let result = self.compile_nested(1, compile);
+        self.loops.pop();
+        result
+    }
+
+    /// Gets the number of blocks that a `break` or `continue` must jump out of to reach the body
+    /// of the innermost loop, or of the loop with the given label.
+    fn loop_jump_depth(&self, label: Option<&String>, keyword: &str) -> Result<usize> {
+        let frame = match label {
+            Some(label) => self.loops.iter()
+                .rev()
+                .find(|frame| frame.label.as_ref() == Some(label))
+                .ok_or_else(|| self.err(format!("`{} {}` does not refer to an enclosing loop", keyword, label)))?,
+            None => self.loops.last()
+                .ok_or_else(|| self.err(format!("`{}` may only be used inside of a loop", keyword)))?,
+        };
+        Ok(self.block_depth - frame.depth)
+    }
+
+    /// Compiles an IR function into a VM function.
+    pub fn compile_function<'n>(&mut self, function: &Function<'n>) -> Result<vm::UserFunction> {
+        if let Some(range) = function.range {
+            self.compiling_at(range);
+        }
+        self.function_scope.push_empty_scope();
+        self.variable_scope.push_empty_scope();
+        // loops outside of the function can't be broken out of from inside of it
+        let outer_loops = mem::take(&mut self.loops);
+        let outer_block_depth = mem::replace(&mut self.block_depth, 0);
+        let outer_variable_uses = mem::take(&mut self.variable_uses);
+        let outer_function = self.function.replace(FunctionFrame {
+            name: function.name().to_string(),
+            return_ty: function.return_ty.clone(),
+            returns_value: false,
+            warned_bare_return: false,
+        });
+        // functions are compiled with the pragmas used before they were declared
+        let function_pragmas = self.function_pragmas(function)?;
+        let outer_pragmas = mem::replace(&mut self.pragmas, function_pragmas);
+        let pragmas = self.pragmas.runtime;
+
+        let symbol = match &function.symbol {
+            Symbol::Function(name) => {
+                // functions that are dispatched between are known by their overload name
+                let overload_name = FunctionStub::overload_name(name, &function.param_tys());
+                self.function_scope.get_stub_by_signature(&overload_name, function.fixed_param_count(), function.is_variadic())
+                    .or_else(|| self.function_scope.get_stub_by_signature(name, function.fixed_param_count(), function.is_variadic()))
+                    .expect(&format!("symbol for function {} (param count {}) was expected to exist, but does not",
+                                     name, function.params.len()))
+                    .symbol()
+            },
+            sym => panic!("got non-function symbol name from IR::Function: {:?}", sym),
+        };
+
+        let mut param_names = HashSet::new();
+        let mut body: Vec<Bc> = vec![];
+        for param in &function.params {
+            let param_name = param.name()
+                .to_string();
+            if param_names.contains(&param_name) {
+                return Err(self.err(format!("duplicate function parameter `{}` in function definition `{}`",
+                                            param_name,
+                                            function.symbol.name())));
+            }
+
+            match param {
+                FunctionParam::Variable { symbol: _, ty, default } => {
+                    // TyExpr::None and TyExpr::All are simply not checked
+                    let local_symbol = self.variable_scope.reserve_symbol();
+                    self.variable_scope.push_value(Variable(param_name.clone(), local_symbol));
+
+                    // parameters that were left out of the call get their default value before
+                    // their type is checked
+                    if let Some(default) = default {
+                        body.push(Bc::Compare(Condition::Omitted(local_symbol)));
+                        let default_body = self.compile_value(default, ValueContext::StoreInto(local_symbol))?;
+                        body.push(Bc::ConditionBlock(default_body));
+                    }
 
-            match param {
-                FunctionParam::Variable { symbol: _, ty, default } => {
-                    // TyExpr::None and TyExpr::All are simply not checked
-                    let local_symbol = self.variable_scope.reserve_symbol();
-                    self.variable_scope.push_value(Variable(param_name.clone(), local_symbol));
                     if let TyExpr::Definite(ty_name) = ty {
                         if let Some(ty) = self.ty_scope.get_value_by_name(ty_name) {
                             // insert the predicate check here
@@ -333,10 +1322,11 @@ impl CompileState {
                         }
                     }
 
-                    if let Some(_default) = default {
-                        // TODO: default param values
-                    }
-
+                }
+                FunctionParam::Variadic { symbol: _ } => {
+                    // extra arguments are always collected into an array
+                    let local_symbol = self.variable_scope.reserve_symbol();
+                    self.variable_scope.push_value(Variable(param_name.clone(), local_symbol));
                 }
                 FunctionParam::SelfKw => { unimplemented!("Self keyword param in ir::compile_function") }
             }
@@ -361,22 +1351,70 @@ impl CompileState {
             .ok_or(format!("undefined type: {}", function.return_ty))?
             .symbol();
         body.append(&mut self.compile_action_list(&function.body)?);
-        let locals = self.variable_scope.all()
+        self.check_unused_variables();
+        let frame = self.function.take().unwrap();
+        if let (TyExpr::Definite(ty_name), false, false, Some(range)) =
+            (&frame.return_ty, frame.returns_value, frame.warned_bare_return, function.range)
+        {
+            self.warn(WarningCategory::ReturnTy, range,
+                      format!("function `{}` declares that it returns `{}`, but never returns a value", frame.name, ty_name));
+        }
+        // only variables in this function's scope layer are its locals
+        let locals = self.variable_scope.iter_local()
             .map(Variable::symbol)
             .collect();
+        self.variable_scope.pop_scope();
         self.function_scope.pop_scope();
+        self.loops = outer_loops;
+        self.block_depth = outer_block_depth;
+        self.variable_uses = outer_variable_uses;
+        self.function = outer_function;
+        self.pragmas = outer_pragmas;
         Ok(vm::UserFunction {
             symbol,
             name: function.name().to_string(),
-            params: function.params.len(),
+            params: function.fixed_param_count(),
+            variadic: function.is_variadic(),
+            defaults: function.default_param_count(),
             return_ty,
             locals,
-            body
+            body,
+            pragmas,
         })
     }
 
     /// Compiles an assignment (lhs, operator, and rhs) into a thunk.
     fn compile_action_assign(&mut self, lhs: &Value, op: AssignOp, rhs: &Value) -> Result<Vec<Bc>> {
+        if let Value::Declare(kind, _) = lhs {
+            if op != AssignOp::Equals {
+                return Err(self.err(format!("`{}` may not be used to assign to a variable declared with `{}`", op, kind)));
+            }
+            // the value is evaluated before the variables that it's assigned to are declared
+            let want = match lhs {
+                Value::Declare(_, target) if matches!(target.as_ref(), Value::Array(_)) => vm::CallContext::List,
+                _ => vm::CallContext::Scalar,
+            };
+            let mut assign_body = self.compile_value_wanting(rhs, ValueContext::Push, want)?;
+            assign_body.append(&mut self.compile_assign_target(lhs)?);
+            return Ok(assign_body);
+        }
+
+        if let Value::Array(targets) = lhs {
+            if op != AssignOp::Equals {
+                return Err(self.err(format!("`{}` may not be used to assign to a list", op)));
+            }
+            if let Value::Array(values) = rhs {
+                if values.len() != targets.len() {
+                    return Err(self.err(format!("cannot assign a list of {} item(s) to a list of {} item(s)",
+                                                values.len(), targets.len())));
+                }
+            }
+            // the whole RHS is evaluated before anything is assigned, so values may be swapped
+            let mut assign_body = self.compile_value_wanting(rhs, ValueContext::Push, vm::CallContext::List)?;
+            assign_body.append(&mut self.compile_assign_target(lhs)?);
+            return Ok(assign_body);
+        }
+
         let lhs_context = match &lhs {
             // if there's only a Symbol::Variable on the LHS, then we can do a direct store into
             // this value
@@ -385,12 +1423,21 @@ impl CompileState {
                 let vm_symbol = match range_sym.as_inner() {
                     Symbol::Function(f) =>
                         return Err(self.err(format!("found function `{}` on the lhs of an assignment, which is not valid", f))),
+                    Symbol::Bareword(b) if self.named_constants.contains_key(b) =>
+                        return Err(self.err(format!("{}: cannot assign to constant `{}`", SourceRange::from(range_sym.0), b))),
                     Symbol::Bareword(b) =>
                         return Err(self.err(format!("found bareword `{}` on the lhs of an assignment, which is not valid", b))),
-                    Symbol::Variable(s) => self.lookup_or_insert_local_variable(s).clone(),
+                    Symbol::Variable(_) => self.lookup_variable(range_sym)?,
                 };
+                self.note_variable_use(vm_symbol, op != AssignOp::Equals, true);
                 ValueContext::StoreInto(vm_symbol)
             }
+            Value::ArrayAccess(_, _) => {
+                let location = lhs.range()
+                    .map(|range| format!("{}: ", SourceRange::from(range)))
+                    .unwrap_or_default();
+                return Err(self.err(format!("{}cannot assign to an item of an array", location)));
+            }
             // other, more "complex" values on the LHS mean that we need to do a push and then pop
             // off a symbol ref
             _ => ValueContext::Push,
@@ -404,10 +1451,18 @@ impl CompileState {
             AssignOp::SplatEquals => Some(Op::Splat),
             AssignOp::FSlashEquals => Some(Op::FSlash),
             AssignOp::TildeEquals => Some(Op::Tilde),
+            AssignOp::DefinedOrEquals => Some(Op::DefinedOr),
             AssignOp::Equals => None,
         };
 
-        if let Some(op) = vm_op {
+        if let (AssignOp::DefinedOrEquals, ValueContext::StoreInto(sym), false) =
+            (op, &lhs_context, self.op_overloads.contains_key(&Op::DefinedOr))
+        {
+            // only assign when the variable is unset, rather than storing it into itself
+            let store_body = self.compile_nested(1, |this| this.compile_value(rhs, lhs_context.clone()))?;
+            assign_body.push(Bc::Compare(Condition::Unset(vm::Value::Ref(*sym))));
+            assign_body.push(Bc::ConditionBlock(store_body));
+        } else if let Some(op) = vm_op {
             let lhs = Box::new(lhs.clone());
             let rhs = Box::new(rhs.clone());
             assign_body.append(&mut self.compile_value(&Value::BinaryExpr(lhs, op, rhs), lhs_context)?)
@@ -427,61 +1482,128 @@ impl CompileState {
         Ok(assign_body)
     }
 
+    /// Compiles the assignment of the value on top of the stack to a variable, or to a list of
+    /// targets that the value is unpacked into item-by-item.
+    fn compile_assign_target(&mut self, target: &Value) -> Result<Vec<Bc>> {
+        match target {
+            Value::Symbol(range_sym) => if let Symbol::Variable(_) = range_sym.as_inner() {
+                let sym = self.lookup_variable(range_sym)?;
+                self.note_variable_use(sym, false, true);
+                Ok(vec![Bc::Pop(sym)])
+            } else if self.named_constants.contains_key(range_sym.as_inner().name()) {
+                Err(self.err(format!("{}: cannot assign to constant `{}`", SourceRange::from(range_sym.0),
+                                     range_sym.as_inner().name())))
+            } else {
+                Err(self.err(format!("cannot assign to `{}`, since it is not a variable", range_sym.as_inner().name())))
+            },
+            Value::Array(targets) => {
+                let mut body = vec![Bc::Unpack(targets.len())];
+                // the last item is on top of the stack
+                for target in targets.iter().rev() {
+                    body.append(&mut self.compile_assign_target(target)?);
+                }
+                Ok(body)
+            }
+            Value::Declare(kind, target) => {
+                let mut body = self.declare_variables(*kind, target)?;
+                body.append(&mut self.compile_assign_target(target)?);
+                Ok(body)
+            }
+            _ => Err(self.err("only variables and lists of variables may be assigned to in a list".to_string())),
+        }
+    }
+
     /// Converts a value known to be an immediate into a VM value.
-    fn convert_immediate_value(&mut self, value: &Value) -> vm::Value {
+    fn convert_immediate_value(&mut self, value: &Value) -> Result<vm::Value> {
         match value {
-            Value::Const(value) => {
-                let value = value.as_inner()
-                    .clone();
-                vm::Value::from(value)
-            },
+            Value::Const(value) => Ok(self.literal_value(value.as_inner())),
             Value::Symbol(value) => {
-                let sym = self.lookup_or_insert_local_variable(value.as_inner().name())
-                    .clone();
-                vm::Value::Ref(sym)
+                if let Symbol::Bareword(b) = value.as_inner() {
+                    if let Some(constant) = self.named_constants.get(b) {
+                        return Ok(vm::Value::Constant(*constant));
+                    }
+                    if let Some(variant) = self.unit_variant_value(b) {
+                        return Ok(variant);
+                    }
+                }
+                let sym = self.lookup_variable(value)?;
+                self.note_variable_use(sym, true, false);
+                Ok(vm::Value::Ref(sym))
             },
             _ => panic!("{:?} is not an immediate value", value),
         }
     }
 
+    /// Gets the constant value of an enum variant with no fields, if `name` refers to one.
+    fn unit_variant_value(&self, name: &str) -> Option<vm::Value> {
+        let (ty, tag) = self.ty_scope.get_variant_by_name(name)?;
+        if ty.variants[tag].fields == 0 {
+            // variants imported from a module are still named by their unqualified name
+            let (_, name) = compile::split_qualified_name(name);
+            Some(vm::Value::Variant(vm::VariantValue {
+                ty: ty.symbol,
+                tag,
+                name: name.to_string(),
+                fields: vec![],
+            }))
+        } else {
+            None
+        }
+    }
+
     /// Compiles the given value (with usage context) into a thunk.
     fn compile_value(&mut self, value: &Value, context: ValueContext) -> Result<Vec<Bc>> {
         match value {
-            Value::Const(value) => Ok(context.with_value_to_bytecode(value.as_inner().clone().into())),
+            Value::Const(value) => {
+                let value = self.literal_value(value.as_inner());
+                Ok(context.with_value_to_bytecode(value))
+            }
             Value::Symbol(sym) => {
                 match sym.as_inner() {
                     Symbol::Function(s) => {
-                        let function = self.function_scope.get_value_by_name(s)
-                            .ok_or(format!("unknown function `{}`", s))?
-                            .clone();
-                        Ok(context.with_value_to_bytecode(vm::Value::FunctionRef(function.symbol)))
+                        let symbol = self.get_function_ref(s)?;
+                        Ok(context.with_value_to_bytecode(vm::Value::FunctionRef(symbol)))
                     }
                     Symbol::Bareword(b) => {
-                        if let Some(stub) = self.function_scope.get_value_by_name(b) {
-                            Ok(context.with_value_to_bytecode(vm::Value::FunctionRef(stub.symbol)))
+                        if let Some(constant) = self.named_constants.get(b) {
+                            Ok(context.with_value_to_bytecode(vm::Value::Constant(*constant)))
+                        } else if let Some(variant) = self.unit_variant_value(b) {
+                            Ok(context.with_value_to_bytecode(variant))
+                        } else if self.function_scope.get_value_by_name(b).is_some() {
+                            let symbol = self.get_function_ref(b)?;
+                            Ok(context.with_value_to_bytecode(vm::Value::FunctionRef(symbol)))
                         } else {
-                            unimplemented!("compiling IR to bytecode => bareword lookup (name: {})", b)
+                            Err(self.err(format!("{}: unknown bareword `{}`", SourceRange::from(sym.0), b)))
                         }
                     }
-                    Symbol::Variable(s) => {
-                        let symbol = self.lookup_or_insert_local_variable(s).clone();
+                    Symbol::Variable(_) => {
+                        let symbol = self.lookup_variable(sym)?;
+                        self.note_variable_use(symbol, true, false);
                         Ok(context.with_symbol_to_bytecode(symbol))
                     }
                 }
             }
-            Value::ArrayAccess(_, _) => unimplemented!("compiling IR to bytecode => array access"),
+            Value::ArrayAccess(array, index) => {
+                let mut access_body = self.compile_value(array, ValueContext::Push)?;
+                access_body.append(&mut self.compile_value(index, ValueContext::Push)?);
+                access_body.push(Bc::Index);
+                access_body.append(&mut context.with_stack_top_to_bytecode());
+                Ok(access_body)
+            }
+            // `&&` and `||` only evaluate their RHS when it decides the result
+            Value::BinaryExpr(_, op @ Op::And, _) | Value::BinaryExpr(_, op @ Op::Or, _)
+                if !self.op_overloads.contains_key(op) => self.compile_comparison_value(value, context),
+            Value::BinaryExpr(lhs, Op::DefinedOr, rhs) if !self.op_overloads.contains_key(&Op::DefinedOr) =>
+                self.compile_defined_or(lhs, rhs, context),
             Value::BinaryExpr(lhs, op, rhs) => {
-                let op_function_symbol = if let Some(sym) = self.operators.get(op) {
-                    *sym
-                } else {
+                if !self.operators.contains_key(op) {
                     return Err(self.err(format!("`{}` is not a legal binary operator", op)));
-                };
+                }
                 let mut expr_body = vec![];
-                // TODO : compound this into a lambda or something
 
                 // LHS
                 let lhs_value = if lhs.is_immediate() {
-                    self.convert_immediate_value(lhs)
+                    self.convert_immediate_value(lhs)?
                 } else {
                     let lhs_sym = self.variable_scope.push_anonymous_symbol()
                         .symbol();
@@ -490,123 +1612,907 @@ impl CompileState {
                 };
                 // RHS
                 let rhs_value = if rhs.is_immediate() {
-                    self.convert_immediate_value(rhs)
+                    self.convert_immediate_value(rhs)?
                 } else {
                     let rhs_sym = self.variable_scope.push_anonymous_symbol()
                         .symbol();
                     expr_body.append(&mut self.compile_value(rhs, ValueContext::StoreInto(rhs_sym.clone()))?);
                     vm::Value::Ref(rhs_sym)
                 };
-                // TODO : operators which don't return a value
-                expr_body.push(Bc::PushValue(rhs_value));
-                expr_body.push(Bc::PushValue(lhs_value));
-                expr_body.push(Bc::Call(op_function_symbol));
-                match context {
-                    ValueContext::Push => {}
-                    ValueContext::StoreInto(sym) => expr_body.push(Bc::Pop(sym)),
-                    ValueContext::Ret => expr_body.push(Bc::Ret(None)),
+                expr_body.append(&mut self.compile_operator_call(op, lhs_value, rhs_value)?);
+                expr_body.append(&mut context.with_stack_top_to_bytecode());
+                Ok(expr_body)
+            }
+            Value::Chain(_, _) => self.compile_comparison_value(value, context),
+            Value::Array(items) | Value::List(items) => {
+                let mut array_body = vec![];
+                for item in items {
+                    array_body.append(&mut self.compile_value_wanting(item, ValueContext::Push, vm::CallContext::List)?);
+                }
+                array_body.push(Bc::MakeArray(items.len()));
+                // a returned list is counted in scalar context
+                if let (Value::List(_), ValueContext::Ret) = (value, &context) {
+                    array_body.push(Bc::ScalarList);
                 }
+                array_body.append(&mut context.with_stack_top_to_bytecode());
+                Ok(array_body)
+            }
+            Value::Match(m) => {
+                // a match with no matching arms evaluates to an unset value
+                let fallback = context.clone().with_value_to_bytecode(vm::Value::Unset);
+                self.compile_match(m, fallback, |this, value| this.compile_value(value, context.clone()))
+            }
+            Value::Conditional(condition, then_value, else_value) => {
+                let mut conditional_body = self.compile_comparison(condition)?;
+                let mut then_body = self.compile_nested(2, |this| this.compile_value(then_value, context.clone()))?;
+                then_body.push(Bc::ExitBlock(1));
+                conditional_body.push(Bc::ConditionBlock(then_body));
+                conditional_body.append(&mut self.compile_nested(1, |this| this.compile_value(else_value, context))?);
+                Ok(vec![Bc::Block(conditional_body)])
+            }
+            Value::Block(actions, value) => {
+                self.compile_scoped_action_list(actions, |this| match value {
+                    Some(value) => this.compile_value(value, context),
+                    None => Ok(context.with_value_to_bytecode(vm::Value::Unset)),
+                })
+            }
+            Value::UnaryExpr(Op::Splat, _) => {
+                Err(self.err("`*` may only be used to splat an array into the arguments of a function call".to_string()))
+            }
+            Value::NamedArg(name, _) => {
+                Err(self.err(format!("named argument `{}` may only be passed to a function call", name)))
+            }
+            Value::Declare(kind, target) => {
+                let mut body = self.declare_variables(*kind, target)?;
+                body.append(&mut self.compile_value(target, context)?);
+                Ok(body)
+            }
+            Value::Map { kind, target, body, list } => {
+                let array_sym = self.variable_scope.push_anonymous_symbol()
+                    .symbol();
+                let index_sym = self.variable_scope.push_anonymous_symbol()
+                    .symbol();
+                let mut map_body = self.compile_value_wanting(list, ValueContext::StoreInto(array_sym), vm::CallContext::List)?;
+                map_body.push(Bc::Store(index_sym, vm::Value::Int(0)));
+                // the result is built up on the stack, so it isn't shared with earlier evaluations
+                map_body.push(Bc::PushValue(vm::Value::Array(vec![])));
+
+                let mut loop_body = vec![
+                    Bc::NextItem { array: array_sym, index: index_sym },
+                    Bc::ConditionBlock(vec![Bc::ExitBlock(1)]),
+                ];
+                loop_body.append(&mut self.compile_nested(1, |this| {
+                    let mut item_body = vec![];
+                    match kind {
+                        MapKind::Map => {
+                            item_body.append(&mut this.compile_assign_target(target)?);
+                            item_body.append(&mut this.compile_value(body, ValueContext::Push)?);
+                            item_body.push(Bc::AppendItem);
+                        }
+                        MapKind::Grep => {
+                            // the item is kept aside, in case the block assigns to the target
+                            let item_sym = this.variable_scope.push_anonymous_symbol()
+                                .symbol();
+                            item_body.push(Bc::Pop(item_sym));
+                            item_body.push(Bc::PushSymbolValue(item_sym));
+                            item_body.append(&mut this.compile_assign_target(target)?);
+                            item_body.append(&mut this.compile_comparison(body)?);
+                            item_body.push(Bc::ConditionBlock(vec![
+                                Bc::PushSymbolValue(item_sym),
+                                Bc::AppendItem,
+                            ]));
+                        }
+                    }
+                    Ok(item_body)
+                })?);
+                loop_body.push(Bc::Compare(Condition::Always));
+                loop_body.push(Bc::JumpBlockTop(0));
+                map_body.push(Bc::Block(loop_body));
+                map_body.append(&mut context.with_stack_top_to_bytecode());
+                Ok(map_body)
+            }
+            Value::UnaryExpr(op, value) => {
+                let op_function_symbol = if let Some(sym) = self.unary_operators.get(op) {
+                    *sym
+                } else {
+                    return Err(self.err(format!("`{}` is not a legal unary operator", op)));
+                };
+                let mut expr_body = self.compile_value(value, ValueContext::Push)?;
+                expr_body.push(Bc::Call(op_function_symbol, vm::CallContext::Scalar));
+                expr_body.append(&mut context.with_stack_top_to_bytecode());
                 Ok(expr_body)
             }
-            Value::UnaryExpr(_, _) => unimplemented!("compiling IR to bytecode => unary operation"), 
             Value::FunCall(expr, args) => {
-                let mut funcall_body = vec![];
-                for arg in args {
-                    funcall_body.append(&mut self.compile_value(arg, ValueContext::Push)?);
+                // a call being returned is made in whatever context its caller was called in
+                let want = if context == ValueContext::Ret { vm::CallContext::Caller } else { vm::CallContext::Scalar };
+                self.compile_funcall(expr, args, context, want)
+            }
+        }
+    }
+
+    /// Compiles a call to a binary operator on two values that have already been evaluated,
+    /// pushing the result.
+    ///
+    /// If any types overload the operator, the first type that the LHS belongs to gets to handle
+    /// it.
+    fn compile_operator_call(&mut self, op: &Op, lhs_value: vm::Value, rhs_value: vm::Value) -> Result<Vec<Bc>> {
+        let op_function_symbol = if let Some(sym) = self.operators.get(op) {
+            *sym
+        } else {
+            return Err(self.err(format!("`{}` is not a legal binary operator", op)));
+        };
+        let overloads = self.op_overloads.get(op)
+            .cloned()
+            .unwrap_or_default();
+        // TODO : operators which don't return a value
+        let call_op = |function| vec![
+            Bc::PushValue(lhs_value.clone()),
+            Bc::PushValue(rhs_value.clone()),
+            Bc::Call(function, vm::CallContext::Scalar),
+        ];
+        if overloads.is_empty() {
+            return Ok(call_op(op_function_symbol));
+        }
+        let mut dispatch_body = vec![];
+        for (ty, function) in overloads {
+            let mut overload_body = call_op(function);
+            overload_body.push(Bc::ExitBlock(1));
+            dispatch_body.push(Bc::Compare(Condition::Match(lhs_value.clone(), vm::Pattern::Ty(ty))));
+            dispatch_body.push(Bc::ConditionBlock(overload_body));
+        }
+        dispatch_body.append(&mut call_op(op_function_symbol));
+        Ok(vec![Bc::Block(dispatch_body)])
+    }
+
+    /// Compiles a condition into a value, which is `true` when the condition is met and `false`
+    /// otherwise.
+    fn compile_comparison_value(&mut self, value: &Value, context: ValueContext) -> Result<Vec<Bc>> {
+        let mut body = self.compile_nested(1, |this| this.compile_comparison(value))?;
+        let mut then_body = context.clone().with_value_to_bytecode(vm::Value::Bool(true));
+        then_body.push(Bc::ExitBlock(1));
+        body.push(Bc::ConditionBlock(then_body));
+        body.append(&mut context.with_value_to_bytecode(vm::Value::Bool(false)));
+        Ok(vec![Bc::Block(body)])
+    }
+
+    /// Compiles `$a // $b`, which only evaluates `$b` when `$a` is unset.
+    fn compile_defined_or(&mut self, lhs: &Value, rhs: &Value, context: ValueContext) -> Result<Vec<Bc>> {
+        let mut body = vec![];
+        let lhs_value = if lhs.is_immediate() {
+            self.convert_immediate_value(lhs)?
+        } else {
+            let lhs_sym = self.variable_scope.push_anonymous_symbol()
+                .symbol();
+            body.append(&mut self.compile_value(lhs, ValueContext::StoreInto(lhs_sym))?);
+            vm::Value::Ref(lhs_sym)
+        };
+        let mut defined_or_body = vec![Bc::Compare(Condition::Unset(lhs_value.clone()))];
+        let mut rhs_body = self.compile_nested(1, |this| this.compile_value(rhs, context.clone()))?;
+        rhs_body.push(Bc::ExitBlock(1));
+        defined_or_body.push(Bc::ConditionBlock(rhs_body));
+        defined_or_body.append(&mut context.with_value_to_bytecode(lhs_value));
+        body.push(Bc::Block(defined_or_body));
+        Ok(body)
+    }
+
+    /// Compiles a chain of comparisons into a condition.
+    ///
+    /// Each operand is evaluated once, and only if every comparison before it was true, so that
+    /// `$a < f() < $c` calls `f` at most once.
+    fn compile_chain_comparison(&mut self, first: &Value, rest: &[(Op, Value)]) -> Result<Vec<Bc>> {
+        let lhs_sym = self.variable_scope.push_anonymous_symbol()
+            .symbol();
+        let mut body = self.compile_value(first, ValueContext::StoreInto(lhs_sym))?;
+        // each comparison after the first runs inside of the condition block of the one before it
+        let mut comparisons = vec![];
+        let mut lhs_sym = lhs_sym;
+        for (depth, (op, rhs)) in rest.iter().enumerate() {
+            let rhs_sym = self.variable_scope.push_anonymous_symbol()
+                .symbol();
+            let mut comparison = self.compile_nested(depth, |this| this.compile_value(rhs, ValueContext::StoreInto(rhs_sym)))?;
+            let (lhs_value, rhs_value) = (vm::Value::Ref(lhs_sym), vm::Value::Ref(rhs_sym));
+            match CompareOp::from_syntax(op).filter(|_| !self.op_overloads.contains_key(op)) {
+                Some(compare_op) => comparison.push(Bc::Compare(Condition::Compare(lhs_value, compare_op, rhs_value))),
+                None => {
+                    let result_sym = self.variable_scope.push_anonymous_symbol()
+                        .symbol();
+                    comparison.append(&mut self.compile_operator_call(op, lhs_value, rhs_value)?);
+                    comparison.push(Bc::Pop(result_sym));
+                    comparison.push(Bc::Compare(Condition::Truthy(vm::Value::Ref(result_sym))));
                 }
+            }
+            comparisons.push(comparison);
+            lhs_sym = rhs_sym;
+        }
+        let nested = comparisons.into_iter()
+            .rev()
+            .fold(None, |inner, mut comparison| {
+                if let Some(inner) = inner {
+                    comparison.push(Bc::ConditionBlock(inner));
+                }
+                Some(comparison)
+            });
+        body.append(&mut nested.unwrap_or_default());
+        Ok(body)
+    }
 
-                let function_name = match expr.as_ref() {
-                    // if we're dealing with a function call name, we can use that directly
-                    Value::Symbol(range_sym) => if let Symbol::Function(function_name) = range_sym.as_inner() {
-                        Some(function_name)
-                    } else { None },
-                    // otherwise, we have to evaluate into a function ref
-                    _ => None,
-                };
+    /// Compiles a value, making any function call that it is directly made up of in the given
+    /// call context.
+    fn compile_value_wanting(&mut self, value: &Value, context: ValueContext, want: vm::CallContext) -> Result<Vec<Bc>> {
+        match value {
+            Value::FunCall(expr, args) => self.compile_funcall(expr, args, context, want),
+            value => self.compile_value(value, context),
+        }
+    }
 
-                if let Some(function_name) = function_name {
-                    if let Some(stub) = self.function_scope.get_stub_by_params(function_name, args.len()) {
-                        funcall_body.push(Bc::Call(stub.symbol));
-                        if context != ValueContext::Push {
-                            if stub.return_ty == TyExpr::None {
-                                return Err(self.err(format!("function `{}` doesn't return a value", stub.name)));
-                            }
-                            match context {
-                                ValueContext::StoreInto(sym) => funcall_body.push(Bc::Pop(sym)),
-                                // ValueContext::Ret means that we're just returning the returned value
-                                ValueContext::Ret => funcall_body.push(Bc::Ret(None)),
-                                ValueContext::Push => unreachable!(),
-                            }
-                        }
-                    } else {
-                        return Err(self.err(format!("no such function `{}`", function_name)));
-                    }
+    /// Compiles a call to a function, which is made in the given call context.
+    fn compile_funcall(&mut self, expr: &Value, args: &[Value], context: ValueContext, want: vm::CallContext) -> Result<Vec<Bc>> {
+        let splats = args.iter()
+            .filter(|arg| matches!(arg, Value::UnaryExpr(Op::Splat, _)))
+            .count();
+        let named = args.iter()
+            .filter(|arg| matches!(arg, Value::NamedArg(_, _)))
+            .count();
+        let mut funcall_body = vec![];
+
+        // some builtins fall back on the topic when they're called without arguments,
+        // e.g. `println()`
+        let topic_args;
+        let args = match expr {
+            Value::Symbol(Ranged(range, Symbol::Function(name)))
+                if args.is_empty()
+                    && vm::TOPIC_BUILTINS.contains(&name.as_str())
+                    && self.function_scope.get_stub_by_params(name, 0).is_none() => {
+                topic_args = vec![Value::Symbol(Ranged(*range, Symbol::topic()))];
+                &topic_args
+            }
+            _ => args,
+        };
+
+        // `eval` compiles its code when it's called, unless it has been defined by the program
+        if let Value::Symbol(Ranged(_, Symbol::Function(name))) = expr {
+            if name == "eval" && args.len() == 1 && self.function_scope.get_stub_by_params(name, 1).is_none() {
+                let error = self.variable_scope
+                    .get_global_value_by_name(EVAL_ERROR)
+                    .expect("missing eval error variable")
+                    .symbol();
+                funcall_body.append(&mut self.compile_value(&args[0], ValueContext::Push)?);
+                funcall_body.push(Bc::Eval(error));
+                funcall_body.append(&mut context.with_stack_top_to_bytecode());
+                return Ok(funcall_body);
+            }
+        }
+
+        let function_name = match expr {
+            // if we're dealing with a function call name, we can use that directly
+            Value::Symbol(range_sym) => if let Symbol::Function(function_name) = range_sym.as_inner() {
+                Some(function_name)
+            } else { None },
+            // otherwise, we have to evaluate into a function ref
+            _ => None,
+        };
+
+        if let Some(function_name) = function_name {
+            self.used_functions.insert(function_name.clone());
+            let resolved = if named > 0 {
+                if splats > 0 {
+                    return Err(self.err("arguments may not be passed by name and splatted in the same call".to_string()));
+                }
+                self.resolve_named_call(function_name, args)?
+            } else if splats == 0 {
+                self.function_scope
+                    .get_stub_by_params(function_name, args.len())
+                    .cloned()
+                    .map(|stub| {
+                        // parameters that are left out take their default values
+                        let mut slots: Vec<_> = args.iter().map(Some).collect();
+                        slots.resize(slots.len().max(stub.params), None);
+                        (stub, slots)
+                    })
+            } else {
+                self.get_splat_call_stub(function_name, args.len() - splats)?
+                    .map(|stub| (stub, args.iter().map(Some).collect()))
+            };
+            if let Some((stub, args)) = resolved {
+                // the number of arguments is only known at runtime for variadic functions
+                // and calls with splatted arguments
+                let counted = stub.variadic || splats > 0;
+                if counted {
+                    funcall_body.push(Bc::PushValue(vm::Value::ArgsCanary));
+                }
+                funcall_body.append(&mut self.compile_funcall_args(&args)?);
+                if counted {
+                    funcall_body.push(Bc::CallArgs(stub.symbol, want));
                 } else {
-                    funcall_body.push(Bc::PushValue(vm::Value::FunctionRefCanary));
-                    funcall_body.append(&mut self.compile_value(expr, ValueContext::Push)?);
-                    funcall_body.push(Bc::PopFunctionRefAndCall);
+                    funcall_body.push(Bc::Call(stub.symbol, want));
                 }
-                Ok(funcall_body)
+                // only a call whose value is thrown away may be made to a function without one
+                let consumed = context != ValueContext::Push || want != vm::CallContext::Void;
+                if consumed && stub.return_ty == TyExpr::None {
+                    return Err(self.err(format!("function `{}` doesn't return a value", stub.name)));
+                }
+                match context {
+                    ValueContext::Push => {}
+                    ValueContext::StoreInto(sym) => funcall_body.push(Bc::Pop(sym)),
+                    // ValueContext::Ret means that we're just returning the returned value
+                    ValueContext::Ret => funcall_body.push(Bc::Ret(None)),
+                }
+            } else if let Some(owner) = self.private_functions.get(function_name.as_str()) {
+                return Err(self.err(format!("function `{}` is private to {}", function_name, owner)));
+            } else if let (Some(module), item) = compile::split_qualified_name(function_name) {
+                if self.ty_scope.get_value_by_name(module).is_some() {
+                    return Err(self.err(format!("type `{}` has no public function `{}` that takes {} argument(s)",
+                                                module, item, args.len())));
+                } else if self.modules.contains_key(module) {
+                    return Err(self.err(format!("module `{}` does not export a function `{}` that takes {} argument(s)",
+                                                module, item, args.len())));
+                } else {
+                    return Err(self.err(format!("no such function `{}` (has module `{}` been imported with `use`?)",
+                                                function_name, module)));
+                }
+            } else {
+                return Err(self.err(format!("no such function `{}`", function_name)));
             }
+        } else if named > 0 {
+            return Err(self.err("arguments may not be passed by name to a call through a function reference".to_string()));
+        } else {
+            // the function isn't known until runtime, so its arguments are always counted
+            let args: Vec<_> = args.iter().map(Some).collect();
+            funcall_body.push(Bc::PushValue(vm::Value::ArgsCanary));
+            funcall_body.append(&mut self.compile_funcall_args(&args)?);
+            funcall_body.push(Bc::PushValue(vm::Value::FunctionRefCanary));
+            funcall_body.append(&mut self.compile_value(expr, ValueContext::Push)?);
+            funcall_body.push(Bc::PopFunctionRefAndCall(want));
+            funcall_body.append(&mut context.with_stack_top_to_bytecode());
         }
+        Ok(funcall_body)
     }
 
-    fn compile_comparison(&mut self, value: &Value) -> Result<Vec<Bc>> {
-        let comparison = match value {
-            Value::BinaryExpr(lhs, op, rhs) => {
-                match op {
-                    | Op::Or
-                    | Op::And
-                    | Op::DoubleEquals
-                    | Op::DoublePercent
-                    | Op::DoubleTilde
-                    | Op::NotEquals
-                    | Op::LessEquals
-                    | Op::GreaterEquals
-                    | Op::Less
-                    | Op::Greater => {
-                        let lhs_sym = self.variable_scope.push_anonymous_symbol()
-                            .symbol();
-                        let rhs_sym = self.variable_scope.push_anonymous_symbol()
-                            .symbol();
-                        // TODO : short-circuiting?
-                        let mut body = self.compile_value(lhs, ValueContext::StoreInto(lhs_sym.clone()))?;
-                        body.append(&mut self.compile_value(rhs, ValueContext::StoreInto(rhs_sym.clone()))?);
-                        let lhs_sym = vm::Value::Ref(lhs_sym);
-                        let rhs_sym = vm::Value::Ref(rhs_sym);
-                        vec![Bc::Compare(Condition::Compare(lhs_sym, CompareOp::from_syntax(&op).unwrap(), rhs_sym))]
-                    }
-                    _ => {
-                        let result_sym = self.variable_scope.push_anonymous_symbol()
-                            .symbol();
-                        let mut value_body = self.compile_value(value, ValueContext::StoreInto(result_sym.clone()))?;
-                        let result_sym = vm::Value::Ref(result_sym);
-                        value_body.push(Bc::Compare(Condition::Truthy(result_sym)));
-                        value_body
-                    }
+    /// Compiles the arguments of a function call, pushing them onto the stack in order.
+    ///
+    /// Splatted arguments push every item of their array, and arguments that were left out push an
+    /// omitted placeholder so the function uses its default.
+    fn compile_funcall_args(&mut self, args: &[Option<&Value>]) -> Result<Vec<Bc>> {
+        let mut body = vec![];
+        for arg in args {
+            match arg {
+                Some(Value::UnaryExpr(Op::Splat, array)) => {
+                    body.append(&mut self.compile_value_wanting(array, ValueContext::Push, vm::CallContext::List)?);
+                    body.push(Bc::Splat);
                 }
+                Some(arg) => body.append(&mut self.compile_value_wanting(arg, ValueContext::Push, vm::CallContext::List)?),
+                None => body.push(Bc::PushValue(vm::Value::Omitted)),
             }
-            _ => {
-                let result_sym = self.variable_scope.push_anonymous_symbol()
-                    .symbol();
-                let mut value_body = self.compile_value(value, ValueContext::StoreInto(result_sym.clone()))?;
-                let result_sym = vm::Value::Ref(result_sym);
-                value_body.push(Bc::Compare(Condition::Truthy(result_sym)));
-                value_body
+        }
+        Ok(body)
+    }
+
+    /// Resolves a call that passes some of its arguments by name.
+    ///
+    /// If the function is overloaded, the call must only fit one of its overloads.
+    ///
+    /// # Returns
+    /// The function being called, along with the argument for each of its parameters in order.
+    /// `None` is returned if no function has the given name.
+    fn resolve_named_call<'v, 'n>(&self, name: &str, args: &'v [Value<'n>])
+        -> Result<Option<(FunctionStub, ArgSlots<'v, 'n>)>>
+    {
+        let candidates: Vec<&FunctionStub> = self.function_scope
+            .iter()
+            .filter(|function| function.name == name)
+            .collect();
+        if let [stub] = candidates.as_slice() {
+            let slots = self.order_named_args(stub, args)?;
+            return Ok(Some(((*stub).clone(), slots)));
+        }
+        let mut fits: Vec<_> = candidates.iter()
+            .filter_map(|stub| self.order_named_args(stub, args)
+                        .ok()
+                        .map(|slots| ((*stub).clone(), slots)))
+            .collect();
+        match fits.len() {
+            0 if candidates.is_empty() => Ok(None),
+            0 => Err(self.err(format!("none of the {} overloads of `{}` accept the given arguments",
+                                      candidates.len(), name))),
+            1 => Ok(fits.pop()),
+            _ => Err(self.err(format!("call to `{}` with named arguments could refer to any of {} overloads",
+                                      name, fits.len()))),
+        }
+    }
+
+    /// Matches the arguments of a call against the parameters of the given function.
+    ///
+    /// Positional arguments must come before named arguments. Each parameter may only be given one
+    /// argument, and every parameter without a default must be given one.
+    fn order_named_args<'v, 'n>(&self, stub: &FunctionStub, args: &'v [Value<'n>]) -> Result<ArgSlots<'v, 'n>> {
+        let mut slots = vec![None; stub.params];
+        let mut seen_named = false;
+        for (position, arg) in args.iter().enumerate() {
+            if let Value::NamedArg(param_name, value) = arg {
+                seen_named = true;
+                let index = stub.param_names
+                    .iter()
+                    .position(|name| name == param_name)
+                    .ok_or_else(|| self.err(format!("function `{}` has no parameter named `{}`", stub.name, param_name)))?;
+                if slots[index].is_some() {
+                    return Err(self.err(format!("parameter `{}` of function `{}` was given more than one argument",
+                                                param_name, stub.name)));
+                }
+                slots[index] = Some(value.as_ref());
+            } else if seen_named {
+                return Err(self.err(format!("positional arguments to `{}` must come before named arguments", stub.name)));
+            } else if position < stub.params {
+                slots[position] = Some(arg);
+            } else if stub.variadic {
+                slots.push(Some(arg));
+            } else {
+                return Err(self.err(format!("function `{}` takes at most {} argument(s), but got {}",
+                                            stub.name, stub.params, args.len())));
             }
+        }
+        let required = stub.params - stub.defaults;
+        if let Some(index) = slots[.. required].iter().position(Option::is_none) {
+            return Err(self.err(format!("missing argument for parameter `{}` of function `{}`",
+                                        stub.param_names[index], stub.name)));
+        }
+        Ok(slots)
+    }
+
+    /// Looks up the function that a reference to the given function name points at.
+    ///
+    /// Functions that are overloaded by their number of parameters can't be referred to, since
+    /// the number of arguments that a reference is called with isn't known until runtime.
+    fn get_function_ref(&mut self, name: &str) -> Result<vm::FunctionSymbol> {
+        self.used_functions.insert(name.to_string());
+        let candidates: Vec<&FunctionStub> = self.function_scope
+            .iter()
+            .filter(|function| function.name == name)
+            .collect();
+        match candidates.as_slice() {
+            [stub] => Ok(stub.symbol),
+            [] => if let Some(owner) = self.private_functions.get(name) {
+                Err(self.err(format!("function `{}` is private to {}", name, owner)))
+            } else {
+                Err(self.err(format!("no such function `{}`", name)))
+            },
+            _ => Err(self.err(format!("cannot take a reference to function `{}`, since it has {} overloads with different numbers of parameters",
+                                      name, candidates.len()))),
+        }
+    }
+
+    /// Looks up the function that a call with splatted arguments resolves to.
+    ///
+    /// Since the number of arguments isn't known until runtime, the call must not be ambiguous
+    /// between any overloads of the function.
+    fn get_splat_call_stub(&self, name: &str, fixed_args: usize) -> Result<Option<FunctionStub>> {
+        let candidates: Vec<&FunctionStub> = self.function_scope
+            .iter()
+            .filter(|function| function.name == name && (function.variadic || function.params >= fixed_args))
+            .collect();
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [stub] => Ok(Some((*stub).clone())),
+            _ => Err(self.err(format!("call to `{}` with a splatted argument could refer to any of its {} overloads",
+                                      name, candidates.len()))),
+        }
+    }
+
+    /// Compiles a match, using the given function to compile the body of each arm.
+    ///
+    /// The matched value is evaluated exactly once. Each arm's pattern is then tested in order,
+    /// and the first arm that matches exits the match when it's finished. If no arm matches, the
+    /// `fallback` bytecode is run.
+    fn compile_match<'n, T, F>(&mut self, m: &Match<'n, T>, mut fallback: Vec<Bc>, mut compile_arm: F)
+        -> Result<Vec<Bc>>
+        where F: FnMut(&mut Self, &T) -> Result<Vec<Bc>>
+    {
+        let mut match_body = vec![];
+        let value = if m.value.is_immediate() {
+            self.convert_immediate_value(&m.value)?
+        } else {
+            let value_sym = self.variable_scope.push_anonymous_symbol()
+                .symbol();
+            match_body.append(&mut self.compile_value(&m.value, ValueContext::StoreInto(value_sym))?);
+            vm::Value::Ref(value_sym)
+        };
+
+        let mut patterns = vec![];
+        for arm in &m.arms {
+            let pattern = self.compile_pattern(&arm.pattern)?;
+            self.block_depth += 2;
+            let arm_body = compile_arm(self, &arm.body);
+            self.block_depth -= 2;
+            let mut arm_body = arm_body?;
+            arm_body.push(Bc::ExitBlock(1));
+            match_body.push(Bc::Compare(Condition::Match(value.clone(), pattern.clone())));
+            match_body.push(Bc::ConditionBlock(arm_body));
+            patterns.push(pattern);
+        }
+        match_body.append(&mut fallback);
+        self.check_match_exhaustive(m.range, &patterns);
+        Ok(vec![Bc::Block(match_body)])
+    }
+
+    /// Compiles an IR pattern into a VM pattern.
+    fn compile_pattern(&mut self, pattern: &Pattern) -> Result<vm::Pattern> {
+        match pattern {
+            Pattern::Wildcard => Ok(vm::Pattern::Any),
+            Pattern::Variable(sym) => {
+                let symbol = self.lookup_or_insert_local_variable(sym);
+                self.note_variable_use(symbol, false, true);
+                Ok(vm::Pattern::Bind(symbol))
+            }
+            Pattern::Const(value) => Ok(vm::Pattern::Value(self.literal_value(value.as_inner()))),
+            Pattern::Bareword(sym) => {
+                let name = sym.name();
+                if self.ty_scope.get_variant_by_name(name).is_some() {
+                    self.compile_variant_pattern(name, &[])
+                } else if let Some(ty) = self.ty_scope.get_value_by_name(name) {
+                    Ok(vm::Pattern::Ty(ty.symbol))
+                } else {
+                    Err(self.err(format!("unknown type or enum variant `{}` in pattern", name)))
+                }
+            }
+            Pattern::Variant(sym, fields) => self.compile_variant_pattern(sym.name(), fields),
+            Pattern::Array(items) => {
+                let mut item_patterns = vec![];
+                for item in items {
+                    item_patterns.push(self.compile_pattern(item)?);
+                }
+                Ok(vm::Pattern::Array(item_patterns))
+            }
+        }
+    }
+
+    /// Compiles an enum variant pattern, ensuring that the variant exists and that the correct
+    /// number of fields are being matched against.
+    fn compile_variant_pattern(&mut self, name: &str, fields: &[Pattern]) -> Result<vm::Pattern> {
+        let (ty, tag, field_count) = if let Some((ty, tag)) = self.ty_scope.get_variant_by_name(name) {
+            (ty.symbol, tag, ty.variants[tag].fields)
+        } else {
+            return Err(self.err(format!("unknown enum variant `{}` in pattern", name)));
+        };
+        if fields.len() != field_count {
+            return Err(self.err(format!("enum variant `{}` has {} field(s), but the pattern has {}",
+                                        name, field_count, fields.len())));
+        }
+        let mut field_patterns = vec![];
+        for field in fields {
+            field_patterns.push(self.compile_pattern(field)?);
+        }
+        Ok(vm::Pattern::Variant { ty, tag, fields: field_patterns })
+    }
+
+    /// Emits a warning if the given match patterns test for some, but not all, of the variants of
+    /// an enum without a catch-all pattern.
+    fn check_match_exhaustive(&mut self, range: Range, patterns: &[vm::Pattern]) {
+        let enum_ty = patterns.iter()
+            .filter_map(|pattern| if let vm::Pattern::Variant { ty, .. } = pattern { Some(*ty) } else { None })
+            .next();
+        let enum_ty = if let Some(ty) = enum_ty { ty } else { return; };
+        let mut handled = HashSet::new();
+        for pattern in patterns {
+            match pattern {
+                vm::Pattern::Variant { ty, tag, fields }
+                    if *ty == enum_ty && fields.iter().all(vm::Pattern::is_irrefutable) => {
+                    handled.insert(*tag);
+                }
+                vm::Pattern::Ty(ty) if *ty == enum_ty => return,
+                pattern if pattern.is_irrefutable() => return,
+                _ => {}
+            }
+        }
+        let ty = self.ty_scope.get_value_by_symbol(enum_ty)
+            .expect("enum type in match pattern does not exist");
+        let unhandled: Vec<_> = ty.variants
+            .iter()
+            .enumerate()
+            .filter(|(tag, _)| !handled.contains(tag))
+            .map(|(_, variant)| variant.name.as_str())
+            .collect();
+        if !unhandled.is_empty() {
+            let message = format!("match over `{}` does not handle every variant (missing: {})",
+                                  ty.name, unhandled.join(", "));
+            self.warn(WarningCategory::IncompleteMatch, range, message);
+        }
+    }
+
+    fn compile_comparison(&mut self, value: &Value) -> Result<Vec<Bc>> {
+        // a bare regex is matched against the topic, e.g. `grep { !m/^#/ } $lines`
+        match value {
+            Value::UnaryExpr(Op::Bang, regex) => if let Some(matched) = regex.match_topic() {
+                return self.compile_comparison(&Value::UnaryExpr(Op::Bang, Box::new(matched)));
+            },
+            regex => if let Some(matched) = regex.match_topic() {
+                return self.compile_comparison(&matched);
+            },
+        }
+        match value {
+            Value::Chain(first, rest) => return self.compile_chain_comparison(first, rest),
+            // `$a && $b` only checks `$b` if `$a` is true, and `$a || $b` only checks `$b` if `$a`
+            // is false
+            Value::BinaryExpr(lhs, Op::And, rhs) if !self.op_overloads.contains_key(&Op::And) => {
+                let mut body = self.compile_comparison(lhs)?;
+                body.push(Bc::ConditionBlock(self.compile_nested(1, |this| this.compile_comparison(rhs))?));
+                return Ok(body);
+            }
+            Value::BinaryExpr(lhs, Op::Or, rhs) if !self.op_overloads.contains_key(&Op::Or) => {
+                let mut body = self.compile_nested(1, |this| this.compile_comparison(lhs))?;
+                body.push(Bc::ConditionBlock(vec![Bc::ExitBlock(1)]));
+                body.append(&mut self.compile_nested(1, |this| this.compile_comparison(rhs))?);
+                return Ok(vec![Bc::Block(body)]);
+            }
+            _ => {}
+        }
+        let compare_op = match value {
+            // overloaded operators must be called in order to compare their operands
+            Value::BinaryExpr(_, op, _) if !self.op_overloads.contains_key(op) => CompareOp::from_syntax(op),
+            _ => None,
+        };
+        let comparison = if let (Value::BinaryExpr(lhs, _, rhs), Some(compare_op)) = (value, compare_op) {
+            let lhs_sym = self.variable_scope.push_anonymous_symbol()
+                .symbol();
+            let rhs_sym = self.variable_scope.push_anonymous_symbol()
+                .symbol();
+            // TODO : short-circuiting?
+            let mut body = self.compile_value(lhs, ValueContext::StoreInto(lhs_sym))?;
+            body.append(&mut self.compile_value(rhs, ValueContext::StoreInto(rhs_sym))?);
+            let lhs_sym = vm::Value::Ref(lhs_sym);
+            let rhs_sym = vm::Value::Ref(rhs_sym);
+            body.push(Bc::Compare(Condition::Compare(lhs_sym, compare_op, rhs_sym)));
+            body
+        } else {
+            let result_sym = self.variable_scope.push_anonymous_symbol()
+                .symbol();
+            let mut value_body = self.compile_value(value, ValueContext::StoreInto(result_sym))?;
+            let result_sym = vm::Value::Ref(result_sym);
+            value_body.push(Bc::Compare(Condition::Truthy(result_sym)));
+            value_body
         };
         Ok(comparison)
     }
 
+    /// Looks up the variable that a symbol refers to.
+    ///
+    /// Under `use strict vars`, the variable must have been declared already. Otherwise, a new
+    /// local variable is inserted if necessary.
+    fn lookup_variable(&mut self, symbol: &RangeSymbol) -> Result<vm::VariableSymbol> {
+        let name = symbol.as_inner().name();
+        if !self.pragmas.strict_vars {
+            Ok(self.lookup_or_insert_local_variable(symbol))
+        } else if let Some(variable) = self.variable_scope.get_value_by_name(name) {
+            Ok(variable.symbol())
+        } else {
+            Err(self.err(format!("{}: variable `${}` must be declared with `my` or `our` before it is used, since `use strict vars` is on",
+                                 SourceRange::from(symbol.0), name)))
+        }
+    }
+
+    /// Declares the `our` variables at the top level of a program ahead of time, so that
+    /// functions may use them before the top level has been compiled.
+    fn declare_globals(&mut self, actions: &[Action]) -> Result<()> {
+        for action in actions {
+            if let Some(range) = action.range() {
+                self.compiling_at(range);
+            }
+            match action {
+                Action::Eval(Value::Declare(DeclKind::Our, target))
+                | Action::Assign(Value::Declare(DeclKind::Our, target), _, _) => {
+                    self.declare_variables(DeclKind::Our, target)?;
+                }
+                // files that use pragmas are kept in their own block
+                Action::Block(block) | Action::Begin(_, block) => self.declare_globals(block)?,
+                Action::Const(range, name, _) => self.reserve_constant(*range, name)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Declares each of the variables in a `my`, `our`, or `local` declaration.
+    ///
+    /// # Returns
+    /// The bytecode that saves the values of variables declared with `local`.
+    fn declare_variables(&mut self, kind: DeclKind, target: &Value) -> Result<Vec<Bc>> {
+        match target {
+            Value::Symbol(symbol) => {
+                let name = symbol.as_inner().name();
+                match kind {
+                    // redeclaring a variable in the same scope reuses it
+                    DeclKind::My => if self.variable_scope.get_local_value_by_name(name).is_none() {
+                        self.insert_local_variable(symbol);
+                    },
+                    DeclKind::Our => if self.variable_scope.get_global_value_by_name(name).is_none() {
+                        if !self.loading.is_empty() {
+                            return Err(self.err(format!("global variable `${}` may not be declared inside of a module", name)));
+                        }
+                        self.variable_scope.push_global_value(name);
+                    },
+                    DeclKind::Local => {
+                        // the global must be what the name refers to here, so that it's what
+                        // gets assigned to
+                        let global = self.variable_scope.get_global_value_by_name(name).map(Variable::symbol);
+                        let visible = self.variable_scope.get_value_by_name(name).map(Variable::symbol);
+                        return match (global, visible) {
+                            (Some(global), Some(visible)) if global == visible => Ok(vec![Bc::Local(global)]),
+                            (Some(_), _) => Err(self.err(format!("{}: `local` cannot be used on global variable `${}` here, since it is hidden by a `my` variable",
+                                                                 symbol.0.start(), name))),
+                            (None, _) => Err(self.err(format!("{}: `local` may only be used on global variables declared with `our`, but `${}` is not",
+                                                              symbol.0.start(), name))),
+                        };
+                    }
+                }
+                Ok(vec![])
+            }
+            Value::Array(targets) => {
+                let mut body = vec![];
+                for target in targets {
+                    body.append(&mut self.declare_variables(kind, target)?);
+                }
+                Ok(body)
+            }
+            _ => Err(self.err(format!("only variables and lists of variables may be declared with `{}`", kind))),
+        }
+    }
+
     /// Looks up a local symbol, or inserts it if necessary.
-    fn lookup_or_insert_local_variable(&mut self, symbol_name: &str) -> vm::VariableSymbol {
-        if let Some(sym) = self.variable_scope.get_value_by_name(symbol_name).map(Variable::symbol) {
+    fn lookup_or_insert_local_variable(&mut self, symbol: &RangeSymbol) -> vm::VariableSymbol {
+        if let Some(sym) = self.variable_scope.get_value_by_name(symbol.name()).map(Variable::symbol) {
             sym
         } else {
-            let sym = self.variable_scope.reserve_symbol();
-            let var = Variable(symbol_name.to_string(), sym);
-            self.variable_scope.push_value(var);
-            sym
+            self.insert_local_variable(symbol)
+        }
+    }
+
+    /// Inserts a new local variable, keeping track of how it's used from here on.
+    fn insert_local_variable(&mut self, symbol: &RangeSymbol) -> vm::VariableSymbol {
+        let name = symbol.name();
+        let sym = self.variable_scope.reserve_symbol();
+        self.variable_scope.push_value(Variable(name.to_string(), sym));
+        self.variable_uses.insert(sym, VariableUse {
+            name: name.to_string(),
+            range: symbol.0.into(),
+            warnings: self.pragmas.warnings,
+            read: false,
+            written: false,
+        });
+        sym
+    }
+
+    /// Records that a variable has been read from or written to.
+    fn note_variable_use(&mut self, symbol: vm::VariableSymbol, read: bool, written: bool) {
+        // only local variables are kept track of
+        if let Some(variable_use) = self.variable_uses.get_mut(&symbol) {
+            variable_use.read |= read;
+            variable_use.written |= written;
+        }
+    }
+
+    /// Stops keeping track of the variables that a loop assigns each item to, since it's normal
+    /// for a loop not to read them.
+    fn exempt_loop_target(&mut self, target: &Value) {
+        match target {
+            Value::Symbol(symbol) => if let Symbol::Variable(name) = symbol.as_inner() {
+                if let Some(variable) = self.variable_scope.get_value_by_name(name) {
+                    let symbol = variable.symbol();
+                    self.variable_uses.remove(&symbol);
+                }
+            },
+            Value::Declare(_, target) => self.exempt_loop_target(target),
+            Value::Array(targets) | Value::List(targets) => for target in targets {
+                self.exempt_loop_target(target);
+            },
+            _ => {}
+        }
+    }
+
+    /// Warns about the local variables that have been declared, but never read.
+    ///
+    /// Variables whose names start with an underscore are never warned about.
+    fn check_unused_variables(&mut self) {
+        let mut variable_uses: Vec<_> = mem::take(&mut self.variable_uses)
+            .into_values()
+            .collect();
+        variable_uses.sort_by_key(|variable_use| {
+            let start = variable_use.range.range.start();
+            (start.line, start.col)
+        });
+        for variable_use in variable_uses {
+            let VariableUse { name, range, warnings, read, written } = variable_use;
+            if read || name.starts_with('_') {
+                continue;
+            }
+            let (category, message) = if written {
+                (WarningCategory::UnreadVariable, format!("variable `${}` is assigned to, but never read", name))
+            } else {
+                (WarningCategory::UnusedVariable, format!("variable `${}` is never used", name))
+            };
+            self.warn_in(warnings, category, range, message);
+        }
+    }
+
+    /// Warns about the given functions, and the functions inside of them, that are never called or
+    /// referred to.
+    fn check_unused_functions(&mut self, functions: &[Function]) -> Result<()> {
+        for function in functions {
+            if let (false, Some(range)) = (self.used_functions.contains(function.name()), function.range) {
+                if !function.name().starts_with('_') {
+                    let warnings = self.function_pragmas(function)?.warnings;
+                    self.warn_in(warnings, WarningCategory::UnusedFunction, range,
+                                 format!("function `{}` is never used", function.name()));
+                }
+            }
+            self.check_unused_functions(&function.inner_functions)?;
+        }
+        Ok(())
+    }
+
+    /// Warns if any actions come after an action that always leaves the block that they're in.
+    fn check_unreachable(&mut self, action: &Action, rest: &[Action]) {
+        let (range, keyword) = match action {
+            Action::Return(range, _) => (range, "return"),
+            Action::Break(range, _) => (range, "break"),
+            Action::Continue(range, _) => (range, "continue"),
+            _ => return,
+        };
+        // the warning points at the first statement that can't be reached
+        if let Some(unreachable) = rest.iter().find(|action| !matches!(action, Action::Pragma(_))) {
+            self.warn(WarningCategory::Unreachable, unreachable.range().unwrap_or(*range),
+                      format!("code after `{}` is unreachable", keyword));
+        }
+    }
+
+    /// Checks a `return` against the return type that its function declares.
+    fn check_return(&mut self, range: Range, value: Option<&Value>) {
+        let frame = if let Some(frame) = self.function.as_mut() { frame } else { return; };
+        frame.returns_value |= value.is_some();
+        let message = match (&frame.return_ty, value) {
+            (TyExpr::Definite(ty_name), None) => {
+                frame.warned_bare_return = true;
+                format!("function `{}` declares that it returns `{}`, but returns without a value", frame.name, ty_name)
+            }
+            (TyExpr::None, Some(_)) =>
+                format!("function `{}` returns a value, but does not declare a return type", frame.name),
+            (TyExpr::Definite(ty_name), Some(Value::Const(value))) => {
+                let value_ty = match value.as_inner() {
+                    Const::Str(_) => vm::BuiltinTy::Str,
+                    Const::Int(_) => vm::BuiltinTy::Int,
+                    Const::Float(_) => vm::BuiltinTy::Float,
+                    Const::Bool(_) => vm::BuiltinTy::Bool,
+                    Const::Regex(_) | Const::Undef => return,
+                };
+                if ty_name == "Any" || *ty_name == value_ty.to_string() {
+                    return;
+                }
+                format!("function `{}` declares that it returns `{}`, but returns a `{}`", frame.name, ty_name, value_ty)
+            }
+            _ => return,
+        };
+        self.warn(WarningCategory::ReturnTy, range, message);
+    }
+
+    /// Gets the pragmas that a function is compiled with: the pragmas that each function starts
+    /// out with, followed by the pragmas used before the function was declared.
+    fn function_pragmas(&self, function: &Function) -> Result<Pragmas> {
+        let mut pragmas = self.default_pragmas;
+        for pragma in &function.pragmas {
+            pragmas.apply(pragma)
+                .map_err(|e| self.err(format!("{}: {}", SourceRange::from(pragma.range), e)))?;
+        }
+        Ok(pragmas)
+    }
+
+    /// Emits a warning, if its category is on where the code being compiled is.
+    fn warn(&mut self, category: WarningCategory, range: Range, message: String) {
+        let warnings = self.pragmas.warnings;
+        self.warn_in(warnings, category, range, message);
+    }
+
+    /// Emits a warning, if its category is in the given set of warnings that are on.
+    fn warn_in(&mut self, warnings: WarningSet, category: WarningCategory, range: impl Into<SourceRange>,
+               message: String) {
+        if warnings.contains(category) {
+            self.warnings.push(Warning::new(category, range, message));
         }
     }
 
@@ -641,6 +2547,15 @@ impl ValueContext {
     fn with_symbol_to_bytecode(self, sym: vm::VariableSymbol) -> Vec<Bc> {
         self.with_value_to_bytecode(vm::Value::Ref(sym))
     }
+
+    /// Uses the value that has been pushed to the top of the stack in this context.
+    fn with_stack_top_to_bytecode(self) -> Vec<Bc> {
+        match self {
+            ValueContext::Push => vec![],
+            ValueContext::StoreInto(sym) => vec![Bc::Pop(sym)],
+            ValueContext::Ret => vec![Bc::Ret(None)],
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -650,4 +2565,164 @@ pub struct CompileUnit {
     pub tys: Vec<vm::Ty>,
     pub variables: Vec<Variable>,
     pub globals: Vec<vm::VariableSymbol>,
+    /// The value that each global starts out with, which is unset unless a `BEGIN` block set it.
+    pub global_values: Vec<vm::Value>,
+    /// The constant pool, which `vm::Value::Constant` refers to by index.
+    pub constants: Vec<vm::Value>,
+    /// The pragmas that the body starts out with.
+    pub pragmas: vm::Pragmas,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{env, fs, process};
+
+    /// Compiles a program, giving the category and location of each warning that it emits.
+    macro_rules! warnings {
+        ($compiler:expr, $src:expr) => {{
+            let mut compiler = $compiler;
+            compiler.begin();
+            compiler.feed_str("test", $src)
+                .map(|warnings| warnings.into_iter()
+                     .map(|warning| (warning.category, warning.range.to_string()))
+                     .collect::<Vec<_>>())
+        }};
+        ($src:expr) => { warnings!(CompileState::new(), $src) };
+    }
+
+    #[test]
+    fn test_compile_warnings() {
+        let cases = [
+            ("my $x", vec![(WarningCategory::UnusedVariable, "test:1:4")]),
+            ("my $x = 1", vec![(WarningCategory::UnreadVariable, "test:1:4")]),
+            ("fun f() {}", vec![(WarningCategory::UnusedFunction, "test:1:5")]),
+            ("fun println($a, $b) {}\nprintln(1, 2)", vec![(WarningCategory::ShadowedBuiltin, "test:1:5")]),
+            ("fun f() {\n    return\n    f()\n}\nf()", vec![(WarningCategory::Unreachable, "test:3:5")]),
+            ("fun f(): Int {\n    return \"x\"\n}\nf()", vec![(WarningCategory::ReturnTy, "test:2:5")]),
+            // a bare return is the only warning about a function that never returns a value
+            ("fun f(): Int {\n    return\n}\nf()", vec![(WarningCategory::ReturnTy, "test:2:5")]),
+            ("fun f(): Int {\n}\nf()", vec![(WarningCategory::ReturnTy, "test:1:5")]),
+            ("enum Color { Red, Green }\nmatch Red { Red => 1 }", vec![(WarningCategory::IncompleteMatch, "test:2:1")]),
+        ];
+        for (src, expected) in cases.iter() {
+            let expected: Vec<_> = expected.iter()
+                .map(|(category, range)| (*category, range.to_string()))
+                .collect();
+            assert_eq!(warnings!(src), Ok(expected), "warnings of {:?}", src);
+        }
+        // warnings are in source order, regardless of the pass that emitted them
+        assert_eq!(warnings!("my $x\nfun f() {\n    return\n    f()\n}\nf()"),
+                   Ok(vec![(WarningCategory::UnusedVariable, "test:1:4".to_string()),
+                           (WarningCategory::Unreachable, "test:4:5".to_string())]));
+        // loops aren't expected to read the variables that they assign each item to
+        assert_eq!(warnings!("for $i in [1, 2] {}\nfor my ($k, $v) in [[1, 2]] {}"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_compile_warning_levels() {
+        let src = "fun f() {\n    return\n    f()\n}\nf()";
+        // -Wno-unreachable
+        let compiler = CompileState::new().with_warning_level(WarningCategory::Unreachable, WarningLevel::Silent);
+        assert_eq!(warnings!(compiler, src), Ok(vec![]));
+        // -Werror=unreachable
+        let compiler = CompileState::new().with_warning_level(WarningCategory::Unreachable, WarningLevel::Error);
+        assert_eq!(warnings!(compiler, src), Err("test:3:5: code after `return` is unreachable [unreachable]".to_string()));
+        // other categories are still only warnings
+        let compiler = CompileState::new().with_warning_level(WarningCategory::UnusedVariable, WarningLevel::Error);
+        assert_eq!(warnings!(compiler, src), Ok(vec![(WarningCategory::Unreachable, "test:3:5".to_string())]));
+        // `no warnings`
+        assert_eq!(warnings!(&format!("no warnings unreachable\n{}", src)), Ok(vec![]));
+        assert_eq!(warnings!(&format!("no warnings\n{}", src)), Ok(vec![]));
+        let scoped = "fun f() {\n    no warnings\n    return\n    f()\n}\nfun g() {\n    return\n    g()\n}\nf()\ng()";
+        assert_eq!(warnings!(scoped), Ok(vec![(WarningCategory::Unreachable, "test:8:5".to_string())]));
+    }
+
+    #[test]
+    fn test_compile_strict_vars() {
+        let mut compiler = CompileState::new();
+        compiler.begin();
+        assert_eq!(compiler.feed_str("lib.npl", "use strict vars\nmy $x = 1\n$x = $y"),
+                   Err("lib.npl:3:6: variable `$y` must be declared with `my` or `our` before it is used, since `use strict vars` is on".to_string()));
+    }
+
+    #[test]
+    fn test_compile_pragma_errors() {
+        let mut compiler = CompileState::new();
+        compiler.begin();
+        assert_eq!(compiler.feed_str("test", "my $_x = 1\nuse bogus"),
+                   Err("test:2:5: unknown pragma `bogus`".to_string()));
+        assert_eq!(compiler.feed_str("test", "if true {\n    no strict nothing\n}"),
+                   Err("test:2:8: unknown argument `nothing` in `no strict nothing`".to_string()));
+    }
+
+    #[test]
+    fn test_compile_constant_pool() {
+        // literals are interned once, including the ones in match patterns
+        let mut compiler = CompileState::new();
+        compiler.begin();
+        assert!(compiler.feed_str("test", "my $x = match 1.5 { 2.5 => 2, \"s\" => 3, _ => 4 }\n[$x, 2.5, 2]").is_ok());
+        let count = |value: vm::Value| compiler.constants.iter().filter(|constant| **constant == value).count();
+        assert_eq!(count(vm::Value::Float(2.5)), 1);
+        assert_eq!(count(vm::Value::Str("s".to_string())), 1);
+        assert_eq!(count(vm::Value::Int(2)), 1);
+    }
+
+    #[test]
+    fn test_compile_error_source() {
+        let mut compiler = CompileState::new();
+        compiler.begin();
+        let mut ir_tree = CompileState::parse_str("a", "println(helper(1))").unwrap();
+        ir_tree.append(CompileState::parse_str("b", "fun helper($x): Any { return nosuch($x) }").unwrap());
+        ir_tree.append(CompileState::parse_str("c", "fun other(): Any { return 1 }\nother()").unwrap());
+        assert_eq!(compiler.feed(&ir_tree), Err("no such function `nosuch`".to_string()));
+        assert_eq!(compiler.error_source(), Some("b"));
+        // a successful feed forgets where the last error came from
+        assert!(compiler.feed_str("d", "my $_ok = 1").is_ok());
+        assert_eq!(compiler.error_source(), None);
+
+        // errors in a module name it themselves, and don't change where later errors are blamed
+        let dir = env::temp_dir().join(format!("not-perl-test-source-{}", process::id()));
+        fs::create_dir_all(dir.join("Test")).unwrap();
+        fs::write(dir.join("Test").join("Broken.npl"), "fun broken(: Any {}\n").unwrap();
+        fs::write(dir.join("Test").join("Fine.npl"), "pub fun fine(): Any { return 1 }\n").unwrap();
+        let mut compiler = CompileState::new();
+        compiler.add_search_path(&dir);
+        compiler.begin();
+        let broken = compiler.feed_str("a", "use Test::Broken");
+        let broken_source = compiler.error_source().map(str::to_string);
+        let mut ir_tree = compiler.parse("a", "use Test::Fine\nTest::Fine::fine()").unwrap();
+        ir_tree.append(compiler.parse("b", "println(nosuch())").unwrap());
+        let fine = compiler.feed(&ir_tree);
+        let fine_source = compiler.error_source().map(str::to_string);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(broken.unwrap_err().starts_with("in module `Test::Broken`: parse error: "));
+        assert_eq!(broken_source, None);
+        assert_eq!(fine, Err("no such function `nosuch`".to_string()));
+        assert_eq!(fine_source, Some("b".to_string()));
+    }
+
+    #[test]
+    fn test_compile_shared_operators() {
+        // operators declared in one file may be used in the files parsed after it
+        let mut compiler = CompileState::new();
+        compiler.begin();
+        let mut ir_tree = compiler.parse("a", "op <=> ($a, $b) precedence(comparison) { return $a - $b }").unwrap();
+        ir_tree.append(compiler.parse("b", "println(3 <=> 1)").unwrap());
+        assert!(compiler.feed(&ir_tree).is_ok());
+        assert!(CompileState::new().parse("b", "println(3 <=> 1)").is_err());
+
+        // operators declared in a module may be used by the code that uses it
+        let dir = env::temp_dir().join(format!("not-perl-test-{}", process::id()));
+        fs::create_dir_all(dir.join("Test")).unwrap();
+        fs::write(dir.join("Test").join("Ops.npl"), "op <+> ($a, $b) precedence(additive) { return $a + $b }\n").unwrap();
+        let mut compiler = CompileState::new();
+        compiler.add_search_path(&dir);
+        compiler.begin();
+        let result = compiler.feed_str("test", "use Test::Ops\nprintln(1 <+> 2)");
+        let without_module = CompileState::new().feed_str("test", "println(1 <+> 2)");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_ok());
+        assert!(without_module.is_err());
+    }
 }
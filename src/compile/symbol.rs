@@ -164,6 +164,20 @@ impl VariableSymbolAlloc {
             locals: vec![],
         }
     }
+
+    /// Reserves a symbol in the outermost scope, even while inside of a nested scope.
+    pub fn reserve_global_symbol(&mut self) -> VariableSymbol {
+        // the outermost scope's next local is saved when the scope after it is entered
+        let local = if self.locals.len() > 1 {
+            &mut self.locals[1]
+        } else {
+            &mut self.reserve_next.local
+        };
+        let symbol = VariableSymbol { global: self.reserve_next.global, local: *local };
+        *local += 1;
+        self.reserve_next.global += 1;
+        symbol
+    }
 }
 
 /// A symbol allocator for VM types.
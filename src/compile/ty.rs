@@ -0,0 +1,28 @@
+use vm;
+
+/// An intermediate type stub collected during compile-time.
+///
+/// This is used so that type declaration is order-agnostic.
+#[derive(Debug, Clone)]
+pub struct TyStub {
+    /// Symbol of this type.
+    pub symbol: vm::TySymbol,
+
+    /// Name of this type.
+    pub name: String,
+
+    /// Variants of this type, if it is an enum.
+    pub variants: Vec<vm::VariantDef>,
+}
+
+impl vm::Symbolic for TyStub {
+    type Symbol = vm::TySymbol;
+
+    fn symbol(&self) -> Self::Symbol {
+        self.symbol
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::{self, Formatter, Display};
+use syntax::{Range, Pos};
+
+/// A category of warnings that the compiler emits, which may be turned on or off with `use
+/// warnings` and `no warnings`, and silenced or turned into errors from the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningCategory {
+    /// A variable that is declared with `my`, but never used.
+    UnusedVariable,
+    /// A private function that is never called or referred to.
+    UnusedFunction,
+    /// A variable that is assigned to, but never read.
+    UnreadVariable,
+    /// A function with the same name as a builtin function.
+    ShadowedBuiltin,
+    /// Code after a `return`, `break`, or `continue` that can never run.
+    Unreachable,
+    /// A `return` that doesn't match the return type that its function declares.
+    ReturnTy,
+    /// A match over an enum that doesn't handle every variant.
+    IncompleteMatch,
+}
+
+impl WarningCategory {
+    /// Every warning category.
+    pub const ALL: [WarningCategory; 7] = [
+        WarningCategory::UnusedVariable,
+        WarningCategory::UnusedFunction,
+        WarningCategory::UnreadVariable,
+        WarningCategory::ShadowedBuiltin,
+        WarningCategory::Unreachable,
+        WarningCategory::ReturnTy,
+        WarningCategory::IncompleteMatch,
+    ];
+
+    /// Gets the name that this category is known by in pragmas and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningCategory::UnusedVariable => "unused_variable",
+            WarningCategory::UnusedFunction => "unused_function",
+            WarningCategory::UnreadVariable => "unread_variable",
+            WarningCategory::ShadowedBuiltin => "shadowed_builtin",
+            WarningCategory::Unreachable => "unreachable",
+            WarningCategory::ReturnTy => "return_type",
+            WarningCategory::IncompleteMatch => "incomplete_match",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        WarningCategory::ALL
+            .iter()
+            .cloned()
+            .find(|category| category.name() == name)
+    }
+}
+
+impl Display for WarningCategory {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.name())
+    }
+}
+
+/// A set of warning categories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WarningSet(u32);
+
+impl WarningSet {
+    /// Gets the set of every warning category.
+    pub fn all() -> Self {
+        let mut set = WarningSet::default();
+        for category in WarningCategory::ALL.iter() {
+            set.insert(*category);
+        }
+        set
+    }
+
+    pub fn contains(&self, category: WarningCategory) -> bool {
+        self.0 & WarningSet::bit(category) != 0
+    }
+
+    pub fn insert(&mut self, category: WarningCategory) {
+        self.0 |= WarningSet::bit(category);
+    }
+
+    pub fn remove(&mut self, category: WarningCategory) {
+        self.0 &= !WarningSet::bit(category);
+    }
+
+    fn bit(category: WarningCategory) -> u32 {
+        1 << category as u32
+    }
+}
+
+/// How warnings of a category are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningLevel {
+    /// The warnings are not reported at all.
+    Silent,
+    /// The warnings are reported, and compilation carries on.
+    Warn,
+    /// The warnings stop compilation, as if they were errors.
+    Error,
+}
+
+/// The range of source code that a warning is about.
+///
+/// Warnings outlive the source that they were compiled from, so the name of the source is kept
+/// separately from the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRange {
+    pub source: Option<String>,
+    pub range: Range<'static>,
+}
+
+impl<'n> From<Range<'n>> for SourceRange {
+    fn from(range: Range<'n>) -> Self {
+        let strip = |pos: Pos| Pos { source_name: None, ..pos };
+        SourceRange {
+            source: range.start().source_name.map(str::to_string),
+            range: Range::new(strip(range.start()), strip(range.end())),
+        }
+    }
+}
+
+impl Display for SourceRange {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if let Some(source) = &self.source {
+            write!(fmt, "{}:", source)?;
+        }
+        write!(fmt, "{}", self.range.start())
+    }
+}
+
+/// A problem with a program that doesn't stop it from being compiled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub category: WarningCategory,
+    pub range: SourceRange,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(category: WarningCategory, range: impl Into<SourceRange>, message: String) -> Self {
+        Warning { category, range: range.into(), message }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}: {} [{}]", self.range, self.message, self.category)
+    }
+}
@@ -1,27 +1,48 @@
-use ir::{Ir, Value};
+use ir::{Ir, Value, Match};
 use syntax::{
-    tree::{Stmt, ConditionBlock},
+    tree::{Stmt, Expr, ConditionBlock, Pragma},
     token::AssignOp,
+    Range,
 };
 
 /// An executable action.
 ///
 /// This is something that changes the state of the VM (e.g. assign a value, evaluate an
 /// expression, conditionally execute).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Action<'n> {
     Eval(Value<'n>),
     Assign(Value<'n>, AssignOp, Value<'n>),
-    Loop(Block<'n>),
+    /// A loop, along with the label that `break` and `continue` may refer to it by.
+    Loop {
+        label: Option<String>,
+        /// The condition that is checked before each iteration, for `while` loops.
+        condition: Option<Value<'n>>,
+        block: Block<'n>,
+    },
+    /// A loop over each item of an array, which is assigned to the target before each iteration.
+    For {
+        label: Option<String>,
+        target: Value<'n>,
+        iterable: Value<'n>,
+        block: Block<'n>,
+    },
     Block(Block<'n>),
     ConditionBlock {
         if_block: Box<ConditionAction<'n>>,
         elseif_blocks: Vec<ConditionAction<'n>>,
         else_block: Option<Box<Action<'n>>>,
     },
-    Break,
-    Continue,
-    Return(Option<Value<'n>>),
+    Match(Match<'n, Action<'n>>),
+    Break(Range<'n>, Option<String>),
+    Continue(Range<'n>, Option<String>),
+    Return(Range<'n>, Option<Value<'n>>),
+    /// A compiler pragma, which applies to the rest of the block that it's used in.
+    Pragma(Pragma<'n>),
+    /// A block that is run as soon as it's compiled, before the rest of the program.
+    Begin(Range<'n>, Block<'n>),
+    /// A constant, whose value is computed as soon as it's compiled.
+    Const(Range<'n>, String, Value<'n>),
 }
 
 impl<'n> Action<'n> {
@@ -30,6 +51,31 @@ impl<'n> Action<'n> {
             .map(Action::from_syntax)
             .collect())
     }
+
+    /// Gets the range of the first part of this action that is located in the source, if any part
+    /// of it is.
+    pub fn range(&self) -> Option<Range<'n>> {
+        match self {
+            Action::Eval(value) => value.range(),
+            Action::Assign(lhs, _, rhs) => lhs.range().or_else(|| rhs.range()),
+            Action::Loop { condition, block, .. } => condition.as_ref()
+                .and_then(Value::range)
+                .or_else(|| block.iter().find_map(Action::range)),
+            Action::For { target, iterable, block, .. } => target.range()
+                .or_else(|| iterable.range())
+                .or_else(|| block.iter().find_map(Action::range)),
+            Action::Block(block) => block.iter().find_map(Action::range),
+            Action::ConditionBlock { if_block, .. } => if_block.condition.range()
+                .or_else(|| if_block.action.range()),
+            Action::Match(m) => Some(m.range),
+            | Action::Break(range, _)
+            | Action::Continue(range, _)
+            | Action::Return(range, _)
+            | Action::Begin(range, _)
+            | Action::Const(range, _, _) => Some(*range),
+            Action::Pragma(pragma) => Some(pragma.range),
+        }
+    }
 }
 
 impl<'n> Ir<Stmt<'n>> for Action<'n> {
@@ -37,6 +83,11 @@ impl<'n> Ir<Stmt<'n>> for Action<'n> {
         match stmt {
             Stmt::UserTy(_) => unreachable!(), // user types are covered as non-action types
             Stmt::Function(_) => unreachable!(), // functions are covered as non-action types
+            Stmt::Operator(_) => unreachable!(), // operators are covered as non-action types
+            Stmt::Use(_) | Stmt::Export(_) => unreachable!(), // modules are resolved by the compiler
+            Stmt::Pragma(pragma) => Action::Pragma(pragma.clone()),
+            Stmt::Begin(range, block) => Action::Begin(*range, block.iter().map(Action::from_syntax).collect()),
+            Stmt::Const(range, name, value) => Action::Const(*range, name.clone(), Value::from_syntax(value)),
             Stmt::Expr(expr) => Action::Eval(Value::from_syntax(expr)),
             Stmt::Assign(lhs, op, rhs) => {
                 let lhs = Value::from_syntax(lhs);
@@ -57,23 +108,42 @@ impl<'n> Ir<Stmt<'n>> for Action<'n> {
                     else_block: else_action_block,
                 }
             }
-            Stmt::While(ConditionBlock { ref condition, ref block }) => {
-                let mut loop_block: Vec<_> = block.iter().map(Action::from_syntax).collect();
-                let condition = Action::ConditionBlock {
-                    if_block: Box::new(ConditionAction {
-                        condition: Value::from_syntax(condition),
-                        action: Action::Block(vec![]),
-                    }),
-                    elseif_blocks: vec![],
-                    else_block: Some(Box::new(Action::Break)),
-                };
-                loop_block.push(condition);
-                Action::Loop(loop_block)
+            Stmt::While(ConditionBlock { ref condition, ref block }) => Action::Loop {
+                label: None,
+                condition: Some(Value::from_syntax(condition)),
+                block: block.iter().map(Action::from_syntax).collect(),
+            },
+            Stmt::For { ref target, ref iterable, ref block } => Action::For {
+                label: None,
+                target: Value::from_syntax(target),
+                iterable: Value::from_syntax(iterable),
+                block: block.iter().map(Action::from_syntax).collect(),
+            },
+            Stmt::Loop(block) => Action::Loop {
+                label: None,
+                condition: None,
+                block: block.iter().map(Action::from_syntax).collect(),
+            },
+            Stmt::Labeled(label, stmt) => match Action::from_syntax(stmt) {
+                Action::Loop { condition, block, .. } => Action::Loop { label: Some(label.clone()), condition, block },
+                Action::For { target, iterable, block, .. } => Action::For {
+                    label: Some(label.clone()),
+                    target,
+                    iterable,
+                    block,
+                },
+                _ => unreachable!(), // only loops may be labeled
+            },
+            Stmt::Match(m) => Action::Match(Match::from_match_stmt(m)),
+            Stmt::Return(range, Some(Expr::List(items))) => {
+                let items = items.iter()
+                    .map(Value::from_syntax)
+                    .collect();
+                Action::Return(*range, Some(Value::List(items)))
             }
-            Stmt::Loop(block) => Action::Loop(block.iter().map(Action::from_syntax).collect()),
-            Stmt::Return(expr) => Action::Return(expr.as_ref().map(Value::from_syntax)),
-            Stmt::Break => Action::Break,
-            Stmt::Continue => Action::Continue,
+            Stmt::Return(range, expr) => Action::Return(*range, expr.as_ref().map(Value::from_syntax)),
+            Stmt::Break(range, label) => Action::Break(*range, label.clone()),
+            Stmt::Continue(range, label) => Action::Continue(*range, label.clone()),
         }
     }
 }
@@ -82,7 +152,7 @@ impl<'n> Ir<Stmt<'n>> for Action<'n> {
 pub type Block<'n> = Vec<Action<'n>>;
 
 /// An action that executes as a result of the given condition value.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConditionAction<'n> {
     pub condition: Value<'n>,
     pub action: Action<'n>,
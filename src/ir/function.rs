@@ -1,4 +1,8 @@
-use syntax::tree::{self, Stmt};
+use syntax::{
+    token::Op,
+    tree::{self, Stmt, Visibility, Pragma},
+    Range,
+};
 use ir::{
     Ir,
     Action, Symbol, TyExpr, Value, Block,
@@ -6,47 +10,135 @@ use ir::{
 
 #[derive(Debug)]
 pub struct Function<'n> {
+    /// The range of the function's name, or `None` if the function was generated by the compiler.
+    pub range: Option<Range<'n>>,
+    pub visibility: Visibility,
     pub symbol: Symbol,
     pub params: Vec<FunctionParam<'n>>,
     pub return_ty: TyExpr,
     pub body: Block<'n>,
     pub inner_functions: Vec<Function<'n>>,
+    /// The pragmas used before this function was declared, which it is compiled with.
+    pub pragmas: Vec<Pragma<'n>>,
 }
 
 impl<'n> Function<'n> {
-    pub fn new(symbol: Symbol, params: Vec<FunctionParam<'n>>, return_ty: TyExpr, body: Block<'n>,
-               inner_functions: Vec<Function<'n>>) -> Self {
-        Function { symbol, params, return_ty, body, inner_functions }
+    pub fn new(visibility: Visibility, symbol: Symbol, params: Vec<FunctionParam<'n>>, return_ty: TyExpr,
+               body: Block<'n>, inner_functions: Vec<Function<'n>>) -> Self {
+        Function { range: None, visibility, symbol, params, return_ty, body, inner_functions, pragmas: vec![] }
+    }
+
+    /// Adds the pragmas used before the block that declares this function, which come before the
+    /// pragmas used inside of that block.
+    pub fn inherit_pragmas(&mut self, outer: &[Pragma<'n>]) {
+        self.pragmas.splice(0 .. 0, outer.iter().cloned());
+        for inner in &mut self.inner_functions {
+            inner.inherit_pragmas(outer);
+        }
     }
 
     pub fn name(&self) -> &str { &self.symbol.name() }
+
+    /// Whether this function collects extra arguments into a trailing `*$rest` parameter.
+    pub fn is_variadic(&self) -> bool {
+        matches!(self.params.last(), Some(FunctionParam::Variadic { .. }))
+    }
+
+    /// The number of parameters that may be left out of a call, since they have default values.
+    pub fn default_param_count(&self) -> usize {
+        self.params
+            .iter()
+            .filter(|param| matches!(param, FunctionParam::Variable { default: Some(_), .. }))
+            .count()
+    }
+
+    /// The names of this function's parameters, not including a trailing `*$rest` parameter.
+    pub fn param_names(&self) -> Vec<String> {
+        self.params[.. self.fixed_param_count()]
+            .iter()
+            .map(|param| param.name().to_string())
+            .collect()
+    }
+
+    /// The types of this function's parameters, not including a trailing `*$rest` parameter.
+    pub fn param_tys(&self) -> Vec<TyExpr> {
+        self.params[.. self.fixed_param_count()]
+            .iter()
+            .map(|param| match param {
+                FunctionParam::Variable { ty, .. } => ty.clone(),
+                _ => TyExpr::Any,
+            })
+            .collect()
+    }
+
+    /// The number of parameters that are always passed to this function, not including a
+    /// trailing `*$rest` parameter.
+    pub fn fixed_param_count(&self) -> usize {
+        if self.is_variadic() {
+            self.params.len() - 1
+        } else {
+            self.params.len()
+        }
+    }
 }
 
 impl<'n> Ir<tree::Function<'n>> for Function<'n> {
-    fn from_syntax(tree::Function { name, params, return_ty, body }: &tree::Function<'n>) -> Self {
+    fn from_syntax(tree::Function { range, visibility, name, params, return_ty, body }: &tree::Function<'n>) -> Self {
         let symbol = Symbol::Function(name.clone());
         let params = params.iter()
             .map(FunctionParam::from_syntax)
             .collect();
         let return_ty = if let Some(return_ty) = return_ty {
             TyExpr::Definite(return_ty.to_string())
-        } else if body.iter().any(|stmt| matches!(stmt, Stmt::Return(Some(_)))) {
+        } else if body.iter().any(|stmt| matches!(stmt, Stmt::Return(_, Some(_)))) {
             // search for at least one return statement that has a value
             TyExpr::Any
         } else {
             TyExpr::None
         };
-        let (inner_functions, syntax_body): (Vec<_>, Vec<_>) = body.iter()
-            .partition(|s| matches!(s, Stmt::Function(_)));
-        let body = syntax_body
-            .into_iter()
-            .map(Action::from_syntax)
+        let mut inner_functions = vec![];
+        let mut pragmas = vec![];
+        let body = body.iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Function(f) => {
+                    let mut function = Function::from_syntax(f);
+                    function.inherit_pragmas(&pragmas);
+                    inner_functions.push(function);
+                    None
+                }
+                Stmt::Pragma(pragma) => {
+                    pragmas.push(pragma.clone());
+                    Some(Action::from_syntax(stmt))
+                }
+                stmt => Some(Action::from_syntax(stmt)),
+            })
             .collect();
-        let inner_functions = inner_functions
-            .into_iter()
-            .map(|s| if let Stmt::Function(f) = s { Function::from_syntax(f) } else { unreachable!() })
-            .collect();
-        Function { symbol, params, return_ty, body, inner_functions }
+        Function {
+            range: Some(*range),
+            visibility: *visibility,
+            symbol,
+            params,
+            return_ty,
+            body,
+            inner_functions,
+            pragmas: vec![],
+        }
+    }
+}
+
+/// An intermediate representation of a binary operator definition.
+#[derive(Debug)]
+pub struct Operator<'n> {
+    pub op: Op,
+    pub function: Function<'n>,
+}
+
+impl<'n> Ir<tree::Operator<'n>> for Operator<'n> {
+    fn from_syntax(operator: &tree::Operator<'n>) -> Self {
+        Operator {
+            op: operator.op.clone(),
+            function: Function::from_syntax(&operator.function),
+        }
     }
 }
 
@@ -58,6 +150,9 @@ pub enum FunctionParam<'n> {
         ty: TyExpr,
         default: Option<Value<'n>>,
     },
+    Variadic {
+        symbol: Symbol,
+    },
 }
 
 impl<'n> FunctionParam<'n> {
@@ -65,6 +160,7 @@ impl<'n> FunctionParam<'n> {
         match self {
             FunctionParam::SelfKw => "self",
             FunctionParam::Variable { symbol, ty: _, default: _ } => symbol.name(),
+            FunctionParam::Variadic { symbol } => symbol.name(),
         }
     }
 }
@@ -83,6 +179,9 @@ impl<'n> Ir<tree::FunctionParam<'n>> for FunctionParam<'n> {
                 let default = default.as_ref().map(Value::from_syntax);
                 FunctionParam::Variable { symbol, ty, default }
             }
+            tree::FunctionParam::Variadic { name } => FunctionParam::Variadic {
+                symbol: Symbol::Variable(name.to_string()),
+            },
             tree::FunctionParam::SelfKw => FunctionParam::SelfKw,
         }
     }
@@ -1,16 +1,25 @@
-use syntax::tree::{Ast, SyntaxTree, Stmt};
+use std::mem;
+use syntax::{
+    token::AssignOp,
+    tree::{Ast, SyntaxTree, Stmt},
+    Pos,
+    Range,
+    Ranged,
+};
 
 mod ty;
 mod function;
 mod action;
 mod symbol;
 mod value;
+mod pattern;
 
 pub use self::ty::*;
 pub use self::function::*;
 pub use self::action::*;
 pub use self::symbol::*;
 pub use self::value::*;
+pub use self::pattern::*;
 
 pub trait Ir<A>
     where A: Ast + Sized,
@@ -19,11 +28,16 @@ pub trait Ir<A>
     fn from_syntax(ast: &A) -> Self;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct IrTree<'n> {
     actions: Vec<Action<'n>>,
     functions: Vec<Function<'n>>,
+    operators: Vec<Operator<'n>>,
     user_types: Vec<UserTy<'n>>,
+    /// The modules imported by this tree, in the order they were imported.
+    uses: Vec<String>,
+    /// The names that this tree exports when it is imported as a module.
+    exports: Vec<String>,
 }
 
 impl<'n> IrTree<'n> {
@@ -35,21 +49,103 @@ impl<'n> IrTree<'n> {
         &self.functions
     }
 
+    pub fn operators(&self) -> &[Operator<'n>] {
+        &self.operators
+    }
+
     pub fn user_types(&self) -> &[UserTy<'n>] {
         &self.user_types
     }
+
+    pub fn uses(&self) -> &[String] {
+        &self.uses
+    }
+
+    pub fn exports(&self) -> &[String] {
+        &self.exports
+    }
+
+    /// Appends all actions, functions, operators, types, imports, and exports of the given IR tree
+    /// to this one.
+    pub fn append(&mut self, mut other: IrTree<'n>) {
+        if other.actions.iter().any(|action| matches!(action, Action::Pragma(_))) {
+            // pragmas only last until the end of the file that uses them
+            self.actions.push(Action::Block(other.actions));
+        } else {
+            self.actions.append(&mut other.actions);
+        }
+        self.functions.append(&mut other.functions);
+        self.operators.append(&mut other.operators);
+        self.user_types.append(&mut other.user_types);
+        self.uses.append(&mut other.uses);
+        self.exports.append(&mut other.exports);
+    }
+
+    /// Wraps the actions of this tree in a loop that reads each line of input into `$_`, for the
+    /// `-n` and `-p` command line options.
+    ///
+    /// The loop is labeled `LINE`, and when `print` is set, each line is printed once the actions
+    /// have run for it.
+    pub fn wrap_in_line_loop(&mut self, print: bool) {
+        // the loop doesn't appear anywhere in the source
+        let range = Range::new(Pos::default(), Pos::default());
+        let topic = || Value::Symbol(Ranged(range, Symbol::topic()));
+        let call = |name: &str, args| {
+            let function = Value::Symbol(Ranged(range, Symbol::Function(name.to_string())));
+            Value::FunCall(Box::new(function), args)
+        };
+        // `defined({ $_ = readln(); $_ })`
+        let read_line = Action::Assign(topic(), AssignOp::Equals, call("readln", vec![]));
+        let condition = call("defined", vec![Value::Block(vec![read_line], Some(Box::new(topic())))]);
+        let mut block = vec![Action::Block(mem::take(&mut self.actions))];
+        if print {
+            block.push(Action::Eval(call("println", vec![topic()])));
+        }
+        self.actions.push(Action::Loop {
+            label: Some("LINE".to_string()),
+            condition: Some(condition),
+            block,
+        });
+    }
 }
 
 impl<'n> Ir<SyntaxTree<'n>> for IrTree<'n> {
     fn from_syntax(ast: &SyntaxTree<'n>) -> Self {
         let mut actions = vec![];
         let mut functions = vec![];
+        let mut operators = vec![];
         let mut user_types = vec![];
+        let mut uses = vec![];
+        let mut exports = vec![];
+        // declarations are compiled with the pragmas used before them
+        let mut pragmas = vec![];
 
         for stmt in ast.stmts.iter() {
             match stmt {
-                Stmt::Function(function) => functions.push(Function::from_syntax(function)),
-                Stmt::UserTy(user_ty) => user_types.push(UserTy::from_syntax(user_ty)),
+                Stmt::Function(function) => {
+                    let mut function = Function::from_syntax(function);
+                    function.inherit_pragmas(&pragmas);
+                    functions.push(function);
+                }
+                Stmt::Operator(operator) => {
+                    let mut operator = Operator::from_syntax(operator);
+                    operator.function.inherit_pragmas(&pragmas);
+                    operators.push(operator);
+                }
+                Stmt::UserTy(user_ty) => {
+                    let mut user_ty = UserTy::from_syntax(user_ty);
+                    user_ty.functions
+                        .iter_mut()
+                        .chain(user_ty.operators.iter_mut().map(|operator| &mut operator.function))
+                        .for_each(|function| function.inherit_pragmas(&pragmas));
+                    user_types.push(user_ty);
+                }
+                Stmt::Use(module) => uses.push(module.clone()),
+                Stmt::Export(names) => exports.extend(names.iter().cloned()),
+                Stmt::Pragma(pragma) => {
+                    pragmas.push(pragma.clone());
+                    actions.push(Action::from_syntax(stmt));
+                }
                 _ => actions.push(Action::from_syntax(stmt)),
             }
         }
@@ -57,7 +153,10 @@ impl<'n> Ir<SyntaxTree<'n>> for IrTree<'n> {
         IrTree {
             actions,
             functions,
+            operators,
             user_types,
+            uses,
+            exports,
         }
     }
 }
@@ -0,0 +1,100 @@
+use syntax::{
+    tree,
+    Range,
+};
+use ir::{Ir, Action, Const, RangeConst, Symbol, RangeSymbol, Value};
+
+/// A pattern that a value may be tested against.
+#[derive(Debug, Clone)]
+pub enum Pattern<'n> {
+    /// A pattern that matches anything.
+    Wildcard,
+
+    /// A pattern that matches anything, binding the value to the given variable.
+    Variable(RangeSymbol<'n>),
+
+    /// A constant that the value is smart-matched against.
+    Const(RangeConst<'n>),
+
+    /// A type name or an enum variant without any fields.
+    ///
+    /// Which of these this is gets resolved at compile-time.
+    Bareword(RangeSymbol<'n>),
+
+    /// An enum variant, and the patterns to test its fields against.
+    Variant(RangeSymbol<'n>, Vec<Pattern<'n>>),
+
+    /// An array, and the patterns to test each of its items against.
+    Array(Vec<Pattern<'n>>),
+}
+
+impl<'n> Ir<tree::Pattern<'n>> for Pattern<'n> {
+    fn from_syntax(pattern: &tree::Pattern<'n>) -> Self {
+        match pattern {
+            tree::Pattern::Wildcard => Pattern::Wildcard,
+            tree::Pattern::Variable(token) => Pattern::Variable(token.map(Symbol::from_token)),
+            tree::Pattern::Literal(token) => Pattern::Const(token.map(Const::from_token)),
+            tree::Pattern::Bareword(token) => Pattern::Bareword(token.map(Symbol::from_token)),
+            tree::Pattern::Variant(token, fields) => {
+                let fields = fields.iter()
+                    .map(Pattern::from_syntax)
+                    .collect();
+                Pattern::Variant(token.map(Symbol::from_token), fields)
+            }
+            tree::Pattern::Array(items) => {
+                let items = items.iter()
+                    .map(Pattern::from_syntax)
+                    .collect();
+                Pattern::Array(items)
+            }
+        }
+    }
+}
+
+/// A match over a value, where the arms are either actions or values.
+#[derive(Debug, Clone)]
+pub struct Match<'n, T> {
+    pub range: Range<'n>,
+    pub value: Value<'n>,
+    pub arms: Vec<MatchArm<'n, T>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm<'n, T> {
+    pub pattern: Pattern<'n>,
+    pub body: T,
+}
+
+impl<'n> Match<'n, Action<'n>> {
+    pub fn from_match_stmt(m: &tree::Match<'n>) -> Self {
+        let arms = m.arms.iter()
+            .map(|arm| MatchArm {
+                pattern: Pattern::from_syntax(&arm.pattern),
+                body: Action::from_syntax_block(&arm.body),
+            })
+            .collect();
+        Match {
+            range: m.range,
+            value: Value::from_syntax(&m.value),
+            arms,
+        }
+    }
+}
+
+impl<'n> Match<'n, Value<'n>> {
+    pub fn from_match_expr(m: &tree::Match<'n>) -> Self {
+        let arms = m.arms.iter()
+            .map(|arm| {
+                MatchArm {
+                    pattern: Pattern::from_syntax(&arm.pattern),
+                    body: Value::from_syntax_block(&arm.body),
+                }
+            })
+            .collect();
+        Match {
+            range: m.range,
+            value: Value::from_syntax(&m.value),
+            arms,
+        }
+    }
+}
@@ -3,6 +3,14 @@ use syntax::{
     Ranged,
 };
 
+/// The name of the topic variable, `$_`, which holds the item currently being handled by a
+/// `for` loop without a variable, a `map` or `grep` block, or a `-n`/`-p` line loop.
+pub const TOPIC: &str = "_";
+
+/// The name of the variable that `eval` puts its error message in, `$@`, which is empty when the
+/// code ran without errors.
+pub const EVAL_ERROR: &str = "@";
+
 /// A symbol which is used to point to a value.
 #[derive(Clone, Debug)]
 pub enum Symbol {
@@ -21,10 +29,16 @@ impl Symbol {
         match token {
             Token::Variable(ref s) => Symbol::Variable(s.clone()),
             Token::Bareword(ref s) => Symbol::Bareword(s.clone()),
+            Token::FunctionRef(ref s) => Symbol::Function(s.clone()),
             _ => panic!("invalid conversion from Token {:?} to Symbol", token),
         }
     }
 
+    /// Creates a symbol that refers to the topic variable, `$_`.
+    pub fn topic() -> Self {
+        Symbol::Variable(TOPIC.to_string())
+    }
+
     pub fn name(&self) -> &str {
         match self {
             | Symbol::Function(s)
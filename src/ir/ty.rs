@@ -1,7 +1,10 @@
 use std::fmt::{self, Display, Formatter};
 use vm;
-use syntax::tree;
-use ir::{Function, Ir};
+use syntax::{
+    tree::{self, Visibility},
+    Range,
+};
+use ir::{Function, FunctionParam, Operator, Ir, Symbol};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TyExpr {
@@ -29,20 +32,69 @@ impl Display for TyExpr {
 /// An intermediate representation of a user-defined type.
 #[derive(Debug)]
 pub struct UserTy<'n> {
+    /// The range of the type's name.
+    pub range: Range<'n>,
     pub name: String,
     pub parents: Vec<String>,
     pub functions: Vec<Function<'n>>,
+    pub operators: Vec<Operator<'n>>,
+    pub variants: Vec<Variant<'n>>,
+}
+
+impl<'n> UserTy<'n> {
+    /// Gets whether this type was declared as an enum.
+    pub fn is_enum(&self) -> bool {
+        !self.variants.is_empty()
+    }
 }
 
 impl<'n> Ir<tree::UserTy<'n>> for UserTy<'n> {
     fn from_syntax(ty: &tree::UserTy<'n>) -> Self {
         UserTy {
+            range: ty.range,
             name: ty.name.clone(),
             parents: ty.parents.clone(),
             functions: ty.functions
                 .iter()
                 .map(Function::from_syntax)
                 .collect(),
+            operators: ty.operators
+                .iter()
+                .map(Operator::from_syntax)
+                .collect(),
+            variants: ty.variants
+                .iter()
+                .map(|variant| Variant::from_enum_variant(&ty.name, variant))
+                .collect(),
+        }
+    }
+}
+
+/// An intermediate representation of a single enum variant.
+#[derive(Debug)]
+pub struct Variant<'n> {
+    pub name: String,
+
+    /// The function that constructs this variant; its parameters are the variant's fields.
+    pub constructor: Function<'n>,
+}
+
+impl<'n> Variant<'n> {
+    pub fn from_enum_variant(ty_name: &str, variant: &tree::Variant<'n>) -> Self {
+        let params = variant.fields
+            .iter()
+            .map(FunctionParam::from_syntax)
+            .collect();
+        let constructor = Function::new(Visibility::Public, Symbol::Function(variant.name.clone()), params,
+                                        TyExpr::Definite(ty_name.to_string()), vec![], vec![]);
+        Variant {
+            name: variant.name.clone(),
+            constructor,
         }
     }
+
+    /// The number of fields that this variant holds.
+    pub fn field_count(&self) -> usize {
+        self.constructor.params.len()
+    }
 }
@@ -1,12 +1,17 @@
+use std::{
+    hash::{Hash, Hasher},
+    iter,
+};
 use syntax::{
     token::{Token, Op},
-    tree::Expr,
+    tree::{Expr, Stmt, DeclKind, MapKind},
+    Range,
     Ranged,
 };
-use ir::{Ir, Symbol, RangeSymbol};
+use ir::{Ir, Action, Match, Symbol, RangeSymbol};
 
-// NOTE: not Eq because f64 is not Eq
-#[derive(Debug, PartialEq, Clone)]
+// NOTE: floats are compared and hashed by their bits, so that literals can be used as keys
+#[derive(Debug, Clone)]
 pub enum Const {
     Str(String),
     Int(i64),
@@ -14,10 +19,41 @@ pub enum Const {
     Float(f64),
     // TODO : user-defined structures
     Bool(bool),
+    Regex(String),
+    /// The `undef` keyword, which is an unset value.
+    Undef,
 }
 
 pub type RangeConst<'n> = Ranged<'n, Const>;
 
+impl PartialEq for Const {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Const::Str(l), Const::Str(r)) | (Const::Regex(l), Const::Regex(r)) => l == r,
+            (Const::Int(l), Const::Int(r)) => l == r,
+            (Const::Float(l), Const::Float(r)) => l.to_bits() == r.to_bits(),
+            (Const::Bool(l), Const::Bool(r)) => l == r,
+            (Const::Undef, Const::Undef) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Const {}
+
+impl Hash for Const {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Const::Str(s) | Const::Regex(s) => s.hash(state),
+            Const::Int(n) => n.hash(state),
+            Const::Float(f) => f.to_bits().hash(state),
+            Const::Bool(b) => b.hash(state),
+            Const::Undef => {}
+        }
+    }
+}
+
 impl Const {
     pub fn from_token(other: &Token) -> Self {
         match other {
@@ -29,8 +65,10 @@ impl Const {
                 }
             },
             Token::FloatLit(ref f) => Const::Float(str::parse::<f64>(f.as_str()).expect("invalid float literal")),
+            Token::RegexLit(ref r) => Const::Regex(r.clone()),
             Token::TrueKw => Const::Bool(true),
             Token::FalseKw => Const::Bool(false),
+            Token::UndefKw => Const::Undef,
             _ => panic!("invalid constant value: {:?}", other),
         }
     }
@@ -40,23 +78,112 @@ impl Const {
 pub enum Value<'n> {
     Const(RangeConst<'n>),
     Symbol(RangeSymbol<'n>),
+    Array(Vec<Value<'n>>),
+    /// A list of values returned with `return $a, $b`, which is an array, except when the function
+    /// is called in scalar context, where it's the number of values.
+    List(Vec<Value<'n>>),
     ArrayAccess(Box<Value<'n>>, Box<Value<'n>>),
     BinaryExpr(Box<Value<'n>>, Op, Box<Value<'n>>),
+    /// A chain of comparisons, e.g. `$a < $b <= $c`, where each operand is evaluated at most once.
+    Chain(Box<Value<'n>>, Vec<(Op, Value<'n>)>),
     UnaryExpr(Op, Box<Value<'n>>),
     FunCall(Box<Value<'n>>, Vec<Value<'n>>),
+    Match(Box<Match<'n, Value<'n>>>),
+    /// A value that is one of two values, depending on whether the condition is true.
+    Conditional(Box<Value<'n>>, Box<Value<'n>>, Box<Value<'n>>),
+    /// A block of actions, followed by the value that the block evaluates to, if it ends with one.
+    ///
+    /// A block that doesn't end with a value evaluates to an unset value.
+    Block(Vec<Action<'n>>, Option<Box<Value<'n>>>),
+    /// A function call argument that is passed by its parameter name.
+    NamedArg(String, Box<Value<'n>>),
+    /// A declaration of the variables in the wrapped value, which is then used as normal.
+    Declare(DeclKind, Box<Value<'n>>),
+    /// A `map` or `grep` over a list, which assigns each item to the target before evaluating the
+    /// body.
+    Map {
+        kind: MapKind,
+        target: Box<Value<'n>>,
+        body: Box<Value<'n>>,
+        list: Box<Value<'n>>,
+    },
 }
 
 impl<'n> Value<'n> {
     /// Determines whether this value can be treated as an "immediate".
     pub fn is_immediate(&self) -> bool {
         match self {
+            // function references must be resolved by the compiler
+            Value::Symbol(symbol) if matches!(symbol.as_inner(), Symbol::Function(_)) => false,
             // constants and symbols can immediately be accessed
             | Value::Const(_)
             | Value::Symbol(_) => true,
-            // arrays, binary exprs, unary exprs, and function calls must be evaluated
+            // everything else must be evaluated
             _ => false,
         }
     }
+
+    /// Gets the range of the first part of this value that is located in the source, if any part
+    /// of it is.
+    pub fn range(&self) -> Option<Range<'n>> {
+        match self {
+            Value::Const(c) => Some(c.0),
+            Value::Symbol(s) => Some(s.0),
+            Value::Array(items) | Value::List(items) => items.iter().find_map(Value::range),
+            Value::ArrayAccess(lhs, rhs) | Value::BinaryExpr(lhs, _, rhs) => lhs.range().or_else(|| rhs.range()),
+            Value::Chain(first, rest) => first.range()
+                .or_else(|| rest.iter().find_map(|(_, value)| value.range())),
+            Value::UnaryExpr(_, value) | Value::NamedArg(_, value) | Value::Declare(_, value) => value.range(),
+            Value::FunCall(function, args) => function.range()
+                .or_else(|| args.iter().find_map(Value::range)),
+            Value::Match(m) => Some(m.range),
+            Value::Conditional(condition, then_value, else_value) => condition.range()
+                .or_else(|| then_value.range())
+                .or_else(|| else_value.range()),
+            Value::Block(actions, value) => actions.iter()
+                .find_map(Action::range)
+                .or_else(|| value.as_ref().and_then(|value| value.range())),
+            Value::Map { target, body, list, .. } => target.range()
+                .or_else(|| body.range())
+                .or_else(|| list.range()),
+        }
+    }
+
+    /// Converts a bare regex into a match of the topic, `$_`, against that regex.
+    ///
+    /// Any other value is not converted.
+    pub fn match_topic(&self) -> Option<Self> {
+        match self {
+            Value::Const(Ranged(range, Const::Regex(_))) => {
+                let topic = Value::Symbol(Ranged(*range, Symbol::topic()));
+                Some(Value::BinaryExpr(Box::new(topic), Op::DoubleTilde, Box::new(self.clone())))
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts a block of statements ending in an expression into the value of that expression,
+    /// which is evaluated after the rest of the block.
+    ///
+    /// The parser only allows blocks whose value is used to end with an expression, but any other
+    /// block evaluates to an unset value.
+    pub fn from_syntax_block(block: &[Stmt<'n>]) -> Self {
+        match block.split_last() {
+            Some((Stmt::Expr(expr), [])) => Value::from_syntax(expr),
+            Some((Stmt::Expr(expr), stmts)) => {
+                let actions = stmts.iter()
+                    .map(Action::from_syntax)
+                    .collect();
+                Value::Block(actions, Some(Box::new(Value::from_syntax(expr))))
+            }
+            _ => {
+                let actions = block.iter()
+                    .map(Action::from_syntax)
+                    .collect();
+                Value::Block(actions, None)
+            }
+        }
+    }
 }
 
 impl<'n> Ir<Expr<'n>> for Value<'n> {
@@ -74,6 +201,25 @@ impl<'n> Ir<Expr<'n>> for Value<'n> {
                 }
                 Value::FunCall(Box::new(function), fun_args)
             }
+            // lists are arrays, which are only told apart from arrays by the syntax
+            Expr::Array(ref items) | Expr::List(ref items) => {
+                let items = items.iter()
+                    .map(Value::from_syntax)
+                    .collect();
+                Value::Array(items)
+            }
+            Expr::Match(ref m) => Value::Match(Box::new(Match::from_match_expr(m))),
+            // else-if blocks are nested in the else branch of the block before them
+            Expr::If { ref if_block, ref elseif_blocks, ref else_block } => {
+                iter::once(if_block.as_ref())
+                    .chain(elseif_blocks.iter())
+                    .rev()
+                    .fold(Value::from_syntax_block(else_block), |else_value, block| {
+                        let condition = Value::from_syntax(&block.condition);
+                        let then_value = Value::from_syntax_block(&block.block);
+                        Value::Conditional(Box::new(condition), Box::new(then_value), Box::new(else_value))
+                    })
+            }
             Expr::ArrayAccess { ref array, ref index } => {
                 let array = Value::from_syntax(array);
                 let index = Value::from_syntax(index);
@@ -81,18 +227,58 @@ impl<'n> Ir<Expr<'n>> for Value<'n> {
             }
             Expr::Atom(ref token) => match token.token() {
                 | Token::Variable(_)
-                | Token::Bareword(_) => Value::Symbol(token.map(Symbol::from_token)),
+                | Token::Bareword(_)
+                | Token::FunctionRef(_) => Value::Symbol(token.map(Symbol::from_token)),
                 _ => Value::Const(token.map(Const::from_token))
             },
+            // `$x |> f(a)` is `f($x, a)`, and `$x |> $f` is `$f($x)`
+            Expr::Binary(ref lhs, Op::Pipe, ref rhs) => {
+                let call = match rhs.as_ref() {
+                    Expr::FunCall { function, args } => {
+                        let args = iter::once(lhs.as_ref().clone())
+                            .chain(args.iter().cloned())
+                            .collect();
+                        Expr::FunCall { function: function.clone(), args }
+                    }
+                    _ => Expr::FunCall { function: rhs.clone(), args: vec![lhs.as_ref().clone()] },
+                };
+                Value::from_syntax(&call)
+            }
             Expr::Binary(ref lhs, ref op, ref rhs) => {
                 let lhs = Value::from_syntax(lhs);
                 let rhs = Value::from_syntax(rhs);
                 Value::BinaryExpr(Box::new(lhs), op.clone(), Box::new(rhs))
             }
+            Expr::Chain(ref first, ref rest) => {
+                let rest = rest.iter()
+                    .map(|(op, rhs)| (op.clone(), Value::from_syntax(rhs)))
+                    .collect();
+                Value::Chain(Box::new(Value::from_syntax(first)), rest)
+            }
             Expr::Unary(ref op, ref expr) => {
                 let expr = Value::from_syntax(expr);
                 Value::UnaryExpr(op.clone(), Box::new(expr))
             }
+            Expr::Ternary(ref condition, ref then_expr, ref else_expr) => {
+                let condition = Value::from_syntax(condition);
+                let then_value = Value::from_syntax(then_expr);
+                let else_value = Value::from_syntax(else_expr);
+                Value::Conditional(Box::new(condition), Box::new(then_value), Box::new(else_value))
+            }
+            Expr::NamedArg(ref name, ref value) => {
+                Value::NamedArg(name.clone(), Box::new(Value::from_syntax(value)))
+            }
+            Expr::Declare(kind, ref target) => Value::Declare(*kind, Box::new(Value::from_syntax(target))),
+            // the topic is localized, so it's restored once the map is done
+            Expr::Map { kind, range, ref block, ref list } => {
+                let topic = Value::Symbol(Ranged(*range, Symbol::topic()));
+                Value::Map {
+                    kind: *kind,
+                    target: Box::new(Value::Declare(DeclKind::Local, Box::new(topic))),
+                    body: Box::new(Value::from_syntax_block(block)),
+                    list: Box::new(Value::from_syntax(list)),
+                }
+            }
         }
     }
 }
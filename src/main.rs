@@ -2,6 +2,7 @@
 #[macro_use] extern crate matches;
 #[macro_use] extern crate lazy_static;
 #[macro_use] extern crate enum_methods;
+extern crate regex;
 //#[macro_use] extern crate galvanic_test;
 
 mod common;
@@ -14,30 +15,104 @@ pub mod compile;
 use std::{
     io::{self, Write},
     env::{self, Args},
+    path::Path,
     process,
 };
 use common::read_file;
-use compile::CompileState;
+use compile::{CompileState, WarningCategory, WarningLevel};
+use ir::IrTree;
 use repl::Repl;
 
 fn exec(args: Args) -> Result<(), String> {
+    let (options, filenames): (Vec<String>, Vec<String>) = args.skip(1)
+        .partition(|arg| arg.starts_with('-'));
+    // options apply to everywhere that doesn't turn warnings on or off with `use warnings` or `no
+    // warnings`, and later options override earlier ones
     let mut compiler = CompileState::new();
+    // whether the program runs once for each line of input, and whether each line is printed
+    // afterwards; `-p` wins over `-n`
+    let mut line_loop = None;
+    for option in &options {
+        compiler = match option.as_str() {
+            "-w" => compiler.with_warnings(true),
+            "-n" => {
+                line_loop = line_loop.or(Some(false));
+                compiler
+            }
+            "-p" => {
+                line_loop = Some(true);
+                compiler
+            }
+            "-Werror" => WarningCategory::ALL
+                .iter()
+                .fold(compiler, |compiler, category| compiler.with_warning_level(*category, WarningLevel::Error)),
+            _ => {
+                let (name, level) = if let Some(name) = option.strip_prefix("-Werror=") {
+                    (name, WarningLevel::Error)
+                } else if let Some(name) = option.strip_prefix("-Wno-") {
+                    (name, WarningLevel::Silent)
+                } else if let Some(name) = option.strip_prefix("-W") {
+                    (name, WarningLevel::Warn)
+                } else {
+                    return Err(format!("unknown option: {}", option));
+                };
+                let category = WarningCategory::from_name(name)
+                    .ok_or_else(|| format!("unknown warning category in option {}: {}", option, name))?;
+                compiler.with_warning_level(category, level)
+            }
+        };
+    }
     compiler.begin();
 
-    for filename in args.skip(1) {
-        let contents = match read_file(&filename) {
+    // modules are also searched for next to the scripts that import them
+    for filename in &filenames {
+        let dir = Path::new(filename)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        compiler.add_search_path(dir);
+    }
+    // all files are parsed before any of them are compiled, so that declarations are visible
+    // across files regardless of the order they were passed in. Operators are the exception,
+    // since they have to be known to be parsed: they're visible in the files after the one that
+    // declares them.
+    let mut ir_tree = IrTree::default();
+    for filename in &filenames {
+        let contents = match read_file(filename) {
             Ok(lexer) => lexer,
             Err(e) => {
                 return Err(format!("could not read {}: {}", filename, e));
             }
         };
-        if let Err(e) = compiler.feed_str(&filename, &contents) {
-            return Err(format!("could not compile {}: {}", filename, e));
+        match compiler.parse(filename, &contents) {
+            Ok(file_tree) => ir_tree.append(file_tree),
+            Err(e) => return Err(format!("could not compile {}: {}", filename, e)),
         }
     }
-    let compile_unit = compiler.into_compile_unit();
+    if let Some(print) = line_loop {
+        ir_tree.wrap_in_line_loop(print);
+    }
+    // the error is blamed on the file that it came from, if it's known
+    let warnings = match compiler.feed(&ir_tree) {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            let source = compiler.error_source()
+                .map(str::to_string)
+                .unwrap_or_else(|| filenames.join(", "));
+            return Err(format!("could not compile {}: {}", source, e));
+        }
+    };
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let compile_unit = compiler.to_compile_unit();
     let mut vm = vm::Vm::new();
-    if let Err(e) = vm.launch(compile_unit) {
+    vm.set_compiler(compiler);
+    let result = vm.launch(compile_unit);
+    for warning in vm.take_warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    if let Err(e) = result {
         return Err(format!("VM runtime error: {}", e));
     }
     Ok(())
@@ -11,7 +11,7 @@ pub struct Repl {
 impl Repl {
     pub fn new() -> Self {
         let mut state = CompileState::repl();
-        state.begin();
+        state.add_search_path(".");
         Repl {
             state,
             vm: Vm::new(),
@@ -19,8 +19,18 @@ impl Repl {
     }
 
     pub fn execute_line(&mut self, line: &str) -> vm::Result<Option<Value>> {
-        self.state.feed_str(REPL_NAME, line)?;
+        for warning in self.state.feed_str(REPL_NAME, line)? {
+            eprintln!("warning: {}", warning);
+        }
         let compile_unit = self.state.to_compile_unit();
-        self.vm.repl_launch(compile_unit)
+        // code that the line evals is compiled as part of the repl
+        self.vm.set_compiler(self.state.clone());
+        let result = self.vm.repl_launch(compile_unit);
+        self.state = self.vm.take_compiler()
+            .expect("repl compiler was taken");
+        for warning in self.vm.take_warnings() {
+            eprintln!("warning: {}", warning);
+        }
+        result
     }
 }
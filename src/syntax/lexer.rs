@@ -1,6 +1,8 @@
 use std::{
+    iter::Peekable,
     mem,
 };
+use regex::Regex;
 use syntax::{
     Pos,
     Range,
@@ -42,7 +44,7 @@ macro_rules! char_class {
 
 char_class!(VARIABLE_NAME_CHARS, "variable name", |c| { c.is_alphanumeric() || "_-".contains(c) });
 char_class!(OP_CHARS, "operator", |c| { "|&=+-*/~!@%^&?<>".contains(c) });
-char_class!(BAREWORD_START_CHARS, "bareword", |c| { c.is_alphabetic() });
+char_class!(BAREWORD_START_CHARS, "bareword", |c| { c.is_alphabetic() || c == '_' });
 char_class!(BAREWORD_CHARS, "bareword", |c| { c.is_alphanumeric() || "_-".contains(c) });
 char_class!(STR_LIT_ESCAPE_CHARS, "string escape", |c| { "trn\"\\".contains(c) });
 
@@ -50,24 +52,28 @@ char_class!(STR_LIT_ESCAPE_CHARS, "string escape", |c| { "trn\"\\".contains(c) }
 pub struct Lexer<'n, S>
     where S: Iterator<Item=char>
 {
-    input: S,
+    input: Peekable<S>,
 
     curr: Option<char>,
     next: Option<char>,
     pos: Pos<'n>,
+    /// The position of the first character of the token being lexed.
+    token_start: Pos<'n>,
 }
 
 impl<'n, S> Lexer<'n, S>
     where S: Iterator<Item=char>
 {
     /// Creates a new lexer with the specified input and source name.
-    pub fn new(mut input: S, source_name: &'n str) -> Self {
+    pub fn new(input: S, source_name: &'n str) -> Self {
+        let mut input = input.peekable();
         let next = input.next();
         Lexer {
             input,
             curr: None,
             next,
             pos: Pos::new(Some(source_name)),
+            token_start: Pos::new(Some(source_name)),
         }
     }
 
@@ -79,7 +85,9 @@ impl<'n, S> Lexer<'n, S>
     /// Gets the next token in this stream, resulting in an error if an unexpected character is
     /// encountered.
     fn next_token(&mut self) -> Option<Result<'n, Token>> {
-        match self.next_char()? {
+        let c = self.next_char()?;
+        self.token_start = self.pos;
+        match c {
             '#' => Some(self.next_comment()),
             '$' => Some(self.next_variable_token()),
             '"' => Some(self.next_str_lit()),
@@ -94,6 +102,9 @@ impl<'n, S> Lexer<'n, S>
             ',' => Some(Ok(Token::Comma)),
             ':' => Some(Ok(Token::Colon)),
             '0' ... '9' => Some(self.next_numeric_token()),
+            '\\' => Some(self.next_function_ref_token()),
+            '&' if self.next.map(|c| BAREWORD_START_CHARS.is_match(c)).unwrap_or(false) =>
+                Some(self.next_function_ref_token()),
             e if OP_CHARS.is_match(e) => Some(self.next_op_token()),
             e if BAREWORD_START_CHARS.is_match(e) => Some(self.next_bareword()),
             e if e.is_whitespace() => {
@@ -131,6 +142,11 @@ impl<'n, S> Lexer<'n, S>
     /// `self.curr` must be the variable sigil character `$`.
     fn next_variable_token(&mut self) -> Result<'n, Token> {
         assert_eq!(self.curr, Some('$'), "precondition failed");
+        // `$@` is the only variable whose name isn't made of name characters
+        if self.next == Some('@') {
+            self.next_char();
+            return Ok(Token::Variable("@".to_string()));
+        }
         let mut var_name = String::new();
         var_name.push(self.next_char_expect(&VARIABLE_NAME_CHARS)?);
         while let Some(c) = self.next {
@@ -144,6 +160,21 @@ impl<'n, S> Lexer<'n, S>
         Ok(Token::Variable(var_name))
     }
 
+    /// Gets the next function reference token, e.g. `&name` or `\name`.
+    ///
+    /// # Preconditions
+    /// `self.curr` must be one of the function reference sigils `&` or `\`.
+    fn next_function_ref_token(&mut self) -> Result<'n, Token> {
+        assert!(self.curr == Some('&') || self.curr == Some('\\'), "precondition failed");
+        let sigil = self.curr.unwrap();
+        self.next_char_expect(&BAREWORD_START_CHARS)?;
+        match self.next_bareword()? {
+            Token::Bareword(name) => Ok(Token::FunctionRef(name)),
+            token => Err(SyntaxError::new(format!("expected function name after `{}`, but got {}", sigil, token),
+                                          self.pos)),
+        }
+    }
+
     /// Gets the next operator token.
     ///
     /// # Preconditions
@@ -160,7 +191,9 @@ impl<'n, S> Lexer<'n, S>
                 break;
             }
         }
-        if let Some(assign_op) = AssignOp::from_str(&op) {
+        if op == "=>" {
+            Ok(Token::FatArrow)
+        } else if let Some(assign_op) = AssignOp::from_str(&op) {
             Ok(Token::AssignOp(assign_op))
         } else {
             Ok(Token::Op(op.into()))
@@ -201,15 +234,28 @@ impl<'n, S> Lexer<'n, S>
         assert!(BAREWORD_START_CHARS.is_match(self.curr.expect("precondition failed")), "precondition failed");
         let mut bareword = String::new();
         bareword.push(self.curr.unwrap());
-        while let Some(c) = self.next {
-            if BAREWORD_CHARS.is_match(c) {
-                bareword.push(c);
-                self.next_char();
-            } else {
-                break;
+        loop {
+            match self.next {
+                Some(c) if BAREWORD_CHARS.is_match(c) => {
+                    bareword.push(c);
+                    self.next_char();
+                }
+                // module-qualified names, e.g. `Some::Module::func`
+                Some(':') if self.input.peek() == Some(&':') => {
+                    self.next_char();
+                    self.next_char();
+                    bareword.push_str("::");
+                    bareword.push(self.next_char_expect(&BAREWORD_START_CHARS)?);
+                }
+                _ => break,
             }
         }
 
+        // m/.../ is a regex literal
+        if bareword == "m" && self.next == Some('/') {
+            return self.next_regex_lit();
+        }
+
         // allow barewords to end with a question mark
         if let Some('?') = self.next {
             bareword.push('?');
@@ -218,21 +264,73 @@ impl<'n, S> Lexer<'n, S>
 
         match bareword.as_str() {
             "if" => Ok(Token::IfKw),
+            "unless" => Ok(Token::UnlessKw),
             "else" => Ok(Token::ElseKw),
             "while" => Ok(Token::WhileKw),
+            "until" => Ok(Token::UntilKw),
+            "for" => Ok(Token::ForKw),
+            "in" => Ok(Token::InKw),
             "loop" => Ok(Token::LoopKw),
             "continue" => Ok(Token::ContinueKw),
             "break" => Ok(Token::BreakKw),
             "true" => Ok(Token::TrueKw),
             "false" => Ok(Token::FalseKw),
+            "undef" => Ok(Token::UndefKw),
             "fun" => Ok(Token::FunKw),
             "return" => Ok(Token::ReturnKw),
             "type" => Ok(Token::TypeKw),
+            "enum" => Ok(Token::EnumKw),
             "self" => Ok(Token::SelfKw),
+            "op" => Ok(Token::OpKw),
+            "match" => Ok(Token::MatchKw),
+            "use" => Ok(Token::UseKw),
+            "no" => Ok(Token::NoKw),
+            "export" => Ok(Token::ExportKw),
+            "pub" => Ok(Token::PubKw),
+            "my" => Ok(Token::MyKw),
+            "our" => Ok(Token::OurKw),
+            "local" => Ok(Token::LocalKw),
+            "BEGIN" => Ok(Token::BeginKw),
+            "const" => Ok(Token::ConstKw),
             _ => Ok(Token::Bareword(bareword))
         }
     }
 
+    /// Gets the next regex literal token.
+    ///
+    /// Forward slashes inside of the regex may be escaped with a backslash; all other escapes are
+    /// passed along to the regex itself.
+    ///
+    /// # Preconditions
+    /// `self.curr` must be the regex literal start character `m`, followed by a `/`.
+    fn next_regex_lit(&mut self) -> Result<'n, Token> {
+        assert_eq!(self.curr, Some('m'), "precondition failed");
+        assert_eq!(self.next, Some('/'), "precondition failed");
+        self.next_char();
+        let mut regex = String::new();
+        loop {
+            match self.next_char() {
+                Some('\\') => match self.next_char() {
+                    Some('/') => regex.push('/'),
+                    Some(c) => {
+                        regex.push('\\');
+                        regex.push(c);
+                    }
+                    None => break Err(SyntaxError::new("reached EOF while inside of regex literal".to_string(), self.pos)),
+                }
+                Some('/') => break Ok(()),
+                Some('\n') | Some('\r') =>
+                    break Err(SyntaxError::new("reached newline while inside of regex literal".to_string(), self.pos)),
+                None => break Err(SyntaxError::new("reached EOF while inside of regex literal".to_string(), self.pos)),
+                Some(c) => regex.push(c),
+            }
+        }?;
+        if let Err(e) = Regex::new(&regex) {
+            return Err(SyntaxError::new(format!("invalid regex literal: {}", e), self.pos));
+        }
+        Ok(Token::RegexLit(regex))
+    }
+
     /// Gets the next numeric token.
     ///
     /// # Preconditions
@@ -333,8 +431,8 @@ impl<'n, S> Iterator for Lexer<'n, S>
     type Item = Result<'n, RangeToken<'n>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let start = self.pos;
         let token = self.next_token();
+        let start = self.token_start;
         let end = self.pos;
         // next_token returns Option<Result<Token>>, we need O<R<RangeToken>>
         token.map(|r| r.map(|t| RangeToken::new(Range::new(start, end), t)))
@@ -370,6 +468,12 @@ mod test {
 
         let unicode = first_token!("$中文");
         assert_eq!(unicode, Token::Variable(String::from("中文")));
+
+        let topic = first_token!("$_");
+        assert_eq!(topic, Token::Variable(String::from("_")));
+
+        let eval_error = first_token!("$@");
+        assert_eq!(eval_error, Token::Variable(String::from("@")));
     }
 
     #[test]
@@ -401,14 +505,53 @@ mod test {
         let op = first_token!("%%");
         assert_eq!(op, Token::Op(Op::DoublePercent));
 
+        let op = first_token!("%");
+        assert_eq!(op, Token::Op(Op::Percent));
+
+        let op = first_token!("**");
+        assert_eq!(op, Token::Op(Op::DoubleSplat));
+
+        let op = first_token!("?");
+        assert_eq!(op, Token::Op(Op::Question));
+
 
         let double_tilde = first_token!("~~");
         assert_eq!(double_tilde, Token::Op(Op::DoubleTilde));
 
+        let fat_arrow = first_token!("=>");
+        assert_eq!(fat_arrow, Token::FatArrow);
+
+        let defined_or = first_token!("//");
+        assert_eq!(defined_or, Token::Op(Op::DefinedOr));
+
+        let defined_or_equals = first_token!("//=");
+        assert_eq!(defined_or_equals, Token::AssignOp(AssignOp::DefinedOrEquals));
+
+        let mut lexer = test_lexer!("$a / $b // $c");
+        assert_eq!(lexer.next_token().unwrap().unwrap(), Token::Variable("a".to_string()));
+        assert_eq!(lexer.next_token().unwrap().unwrap(), Token::Op(Op::FSlash));
+        assert_eq!(lexer.next_token().unwrap().unwrap(), Token::Variable("b".to_string()));
+        assert_eq!(lexer.next_token().unwrap().unwrap(), Token::Op(Op::DefinedOr));
+
         let very_long_op = first_token!("/<+~-~+>/");
         assert_eq!(very_long_op, Token::Op(Op::Custom("/<+~-~+>/".to_string())));
     }
 
+    #[test]
+    fn test_lexer_function_ref() {
+        let function_ref = first_token!("&name");
+        assert_eq!(function_ref, Token::FunctionRef("name".to_string()));
+
+        let function_ref = first_token!("\\Some::Module::func");
+        assert_eq!(function_ref, Token::FunctionRef("Some::Module::func".to_string()));
+
+        let op = first_token!("&&");
+        assert_eq!(op, Token::Op(Op::And));
+
+        let mut lexer = test_lexer!("\\if");
+        assert!(lexer.next_token().unwrap().is_err());
+    }
+
     #[test]
     fn test_lexer_str_lit() {
         let africa = first_token!("\"hurry boy, she's waiting there for you\"");
@@ -440,6 +583,70 @@ mod test {
 
         let continuekw = first_token!("continue");
         assert_eq!(continuekw, Token::ContinueKw);
+
+        let enumkw = first_token!("enum");
+        assert_eq!(enumkw, Token::EnumKw);
+
+        let matchkw = first_token!("match");
+        assert_eq!(matchkw, Token::MatchKw);
+
+        let opkw = first_token!("op");
+        assert_eq!(opkw, Token::OpKw);
+
+        let underscore = first_token!("_");
+        assert_eq!(underscore, Token::Bareword("_".to_string()));
+
+        let pubkw = first_token!("pub");
+        assert_eq!(pubkw, Token::PubKw);
+
+        let forkw = first_token!("for");
+        assert_eq!(forkw, Token::ForKw);
+
+        let inkw = first_token!("in");
+        assert_eq!(inkw, Token::InKw);
+
+        let usekw = first_token!("use");
+        assert_eq!(usekw, Token::UseKw);
+
+        let nokw = first_token!("no");
+        assert_eq!(nokw, Token::NoKw);
+
+        let unlesskw = first_token!("unless");
+        assert_eq!(unlesskw, Token::UnlessKw);
+
+        let untilkw = first_token!("until");
+        assert_eq!(untilkw, Token::UntilKw);
+
+        let undefkw = first_token!("undef");
+        assert_eq!(undefkw, Token::UndefKw);
+
+        let mykw = first_token!("my");
+        assert_eq!(mykw, Token::MyKw);
+
+        let ourkw = first_token!("our");
+        assert_eq!(ourkw, Token::OurKw);
+
+        let localkw = first_token!("local");
+        assert_eq!(localkw, Token::LocalKw);
+
+        let beginkw = first_token!("BEGIN");
+        assert_eq!(beginkw, Token::BeginKw);
+
+        let constkw = first_token!("const");
+        assert_eq!(constkw, Token::ConstKw);
+
+        let qualified = first_token!("Some::Module::func");
+        assert_eq!(qualified, Token::Bareword("Some::Module::func".to_string()));
+
+        let mut lexer = test_lexer!("Foo: Bar");
+        assert_eq!(lexer.next_token().unwrap().unwrap(), Token::Bareword("Foo".to_string()));
+        assert_eq!(lexer.next_token().unwrap().unwrap(), Token::Colon);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_lexer_bareword_trailing_separator() {
+        first_token!("Some::");
     }
 
     #[test]
@@ -460,6 +667,24 @@ mod test {
         assert_eq!(float, Token::FloatLit("0.0".to_string()));
     }
 
+    #[test]
+    fn test_lexer_regex_lit() {
+        let regex = first_token!(r"m/^\d+$/");
+        assert_eq!(regex, Token::RegexLit(r"^\d+$".to_string()));
+
+        let slashes = first_token!(r"m/a\/b/");
+        assert_eq!(slashes, Token::RegexLit("a/b".to_string()));
+
+        let m = first_token!("m");
+        assert_eq!(m, Token::Bareword("m".to_string()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_lexer_regex_lit_invalid() {
+        first_token!("m/(/");
+    }
+
     #[test]
     #[should_panic]
     fn test_lexer_str_lit_newline() {
@@ -2,6 +2,7 @@ mod lexer;
 mod parser;
 mod pos;
 mod error;
+mod precedence;
 
 pub mod token;
 pub mod tree;
@@ -11,5 +12,6 @@ pub use self::{
     parser::*,
     pos::*,
     error::*,
+    precedence::*,
 };
 
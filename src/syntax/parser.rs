@@ -1,9 +1,11 @@
-use std::{
-    mem,
-    collections::VecDeque
-};
+use std::mem;
 use syntax::{
     Lexer,
+    Range,
+    Precedence,
+    Assoc,
+    OpInfo,
+    OpTable,
     Result,
     SyntaxError,
     tree::*,
@@ -18,6 +20,8 @@ pub struct Parser<'n, S>
     next: Option<RangeToken<'n>>,
     stmt_level: usize,
     inside_type: bool,
+    /// The binary operators known to this parser, which grows as operators are declared.
+    op_table: OpTable,
 }
 
 impl<'n, S> Parser<'n, S>
@@ -35,6 +39,7 @@ impl<'n, S> Parser<'n, S>
             next: None,
             stmt_level: 0,
             inside_type: false,
+            op_table: OpTable::new(),
         }
     }
 
@@ -47,6 +52,22 @@ impl<'n, S> Parser<'n, S>
         }
     }
 
+    /// Parses a syntax tree with a table of operators that is shared with other parsers.
+    ///
+    /// Operators that are already in the table may be used right away, and operators declared
+    /// while parsing are added to it, even if parsing fails partway through.
+    pub fn into_parse_tree_with(mut self, op_table: &mut OpTable) -> Result<'n, SyntaxTree<'n>> {
+        mem::swap(&mut self.op_table, op_table);
+        let result = self.init()
+            .and_then(|_| if self.curr.is_some() {
+                self.next_tree()
+            } else {
+                Ok(SyntaxTree::default())
+            });
+        mem::swap(&mut self.op_table, op_table);
+        result
+    }
+
     /// Readies this parser by filling in the first two tokens.
     fn init(&mut self) -> Result<'n, ()> {
         assert!(self.curr.is_none());
@@ -83,6 +104,44 @@ impl<'n, S> Parser<'n, S>
     }
 
     fn next_stmt(&mut self) -> Result<'n, Stmt<'n>> {
+        let mut stmt = self.next_bare_stmt()?;
+        if let Stmt::Expr(_) | Stmt::Assign(_, _, _) | Stmt::Return(_, _) | Stmt::Break(_, _) | Stmt::Continue(_, _) = stmt {
+            stmt = self.next_stmt_modifier(stmt)?;
+        }
+        self.next_eol_or_eof()?;
+        Ok(stmt)
+    }
+
+    /// Wraps a simple statement in the condition or loop of a statement modifier, if one follows
+    /// it, e.g. `println("done") if $verbose`.
+    fn next_stmt_modifier(&mut self, stmt: Stmt<'n>) -> Result<'n, Stmt<'n>> {
+        let modifier = match self.curr.as_ref().map(RangeToken::token) {
+            Some(Token::IfKw) | Some(Token::UnlessKw) | Some(Token::WhileKw) | Some(Token::UntilKw) => {
+                Token::from(self.next_token()?.unwrap())
+            }
+            _ => return Ok(stmt),
+        };
+        let condition = self.next_expr()?;
+        let stmt = match modifier {
+            Token::IfKw => Stmt::If {
+                if_block: ConditionBlock::new(condition, vec![stmt]),
+                elseif_blocks: vec![],
+                else_block: None,
+            },
+            Token::UnlessKw => Stmt::If {
+                if_block: ConditionBlock::new(Expr::negate(condition), vec![stmt]),
+                elseif_blocks: vec![],
+                else_block: None,
+            },
+            Token::WhileKw => Stmt::While(ConditionBlock::new(condition, vec![stmt])),
+            Token::UntilKw => Stmt::While(ConditionBlock::new(Expr::negate(condition), vec![stmt])),
+            _ => unreachable!(),
+        };
+        Ok(stmt)
+    }
+
+    /// Gets the next statement, without expecting an end-of-line after it.
+    fn next_bare_stmt(&mut self) -> Result<'n, Stmt<'n>> {
         assert_eq!(self.stmt_level, 0);
         self.skip_whitespace()?;
 
@@ -93,31 +152,95 @@ impl<'n, S> Parser<'n, S>
         };
         let stmt = match curr {
             Token::ReturnKw => {
-                self.next_token_or_newline()?;
-                if self.is_lookahead::<Expr>() {
-                    Stmt::Return(Some(self.next_expr()?))
+                let range = self.next_token_or_newline()?.unwrap().0;
+                if self.is_token_match(&Token::IfKw) {
+                    // `return if $x` is a statement modifier, unless its condition is followed by
+                    // a block, making it an if-expression
+                    self.next_token()?;
+                    let condition = self.next_expr()?;
+                    if self.is_token_match(&Token::LBrace) {
+                        Stmt::Return(range, Some(self.next_if_expr_blocks(condition)?))
+                    } else {
+                        Stmt::If {
+                            if_block: ConditionBlock::new(condition, vec![Stmt::Return(range, None)]),
+                            elseif_blocks: vec![],
+                            else_block: None,
+                        }
+                    }
+                } else if self.is_lookahead::<Expr>() {
+                    // `return $a, $b` returns both values as a list
+                    let value = self.next_expr()?;
+                    if self.is_token_match(&Token::Comma) {
+                        let mut values = vec![value];
+                        while self.is_token_match(&Token::Comma) {
+                            self.next_token()?;
+                            values.push(self.next_expr()?);
+                        }
+                        Stmt::Return(range, Some(Expr::List(values)))
+                    } else {
+                        Stmt::Return(range, Some(value))
+                    }
                 } else {
-                    Stmt::Return(None)
+                    Stmt::Return(range, None)
                 }
             }
             Token::ContinueKw => {
-                self.next_token_or_newline()?;
-                Stmt::Continue
+                let range = self.next_token_or_newline()?.unwrap().0;
+                Stmt::Continue(range, self.next_loop_label()?)
             }
             Token::BreakKw => {
-                self.next_token_or_newline()?;
-                Stmt::Continue
+                let range = self.next_token_or_newline()?.unwrap().0;
+                Stmt::Break(range, self.next_loop_label()?)
+            }
+            Token::Bareword(ref label) if matches!(self.next.as_ref().map(|r| r.token()), Some(Token::Colon)) => {
+                // loop label, e.g. `OUTER: loop { ... }`
+                let label = label.clone();
+                self.next_token()?;
+                self.next_token()?;
+                match self.curr.as_ref().map(RangeToken::token) {
+                    Some(Token::LoopKw) | Some(Token::WhileKw) | Some(Token::ForKw) => {}
+                    _ => return Err(self.err_expected_got(format!("loop after label `{}`", label), self.curr.as_ref())),
+                }
+                Stmt::Labeled(label, Box::new(self.next_bare_stmt()?))
             }
             Token::WhileKw => {
                 self.next_token()?;
                 let condblock = self.next_condition_block()?;
                 Stmt::While(condblock)
             }
+            Token::ForKw => {
+                let range = self.curr_range();
+                self.next_token()?;
+                let first = self.next_expr()?;
+                // `for $list { ... }` puts each item in `$_`, which is restored after the loop
+                let (target, iterable) = if self.is_token_match(&Token::InKw) {
+                    self.next_token()?;
+                    (first, self.next_expr()?)
+                } else {
+                    let topic = Expr::Atom(RangeToken::new(range, Token::Variable("_".to_string())));
+                    (Expr::Declare(DeclKind::Local, Box::new(topic)), first)
+                };
+                let block = self.next_block()?;
+                Stmt::For { target, iterable, block }
+            }
             Token::LoopKw => {
                 self.next_token()?;
                 let block = self.next_block()?;
                 Stmt::Loop(block)
             }
+            Token::BeginKw => {
+                let range = self.curr_range();
+                self.next_token()?;
+                let block = self.next_block()?;
+                Stmt::Begin(range, block)
+            }
+            Token::ConstKw => {
+                let range = self.curr_range();
+                self.next_token()?;
+                let name = self.next_bareword()?;
+                self.match_token(Token::AssignOp(AssignOp::Equals))?;
+                Stmt::Const(range, name, self.next_expr()?)
+            }
             Token::IfKw => {
                 self.next_token()?;
                 let if_block = self.next_condition_block()?;
@@ -141,8 +264,54 @@ impl<'n, S> Parser<'n, S>
                     else_block,
                 }
             }
-            Token::FunKw => Stmt::Function(self.next_function()?),
+            Token::UnlessKw => {
+                self.next_token()?;
+                let ConditionBlock { condition, block } = self.next_condition_block()?;
+                let else_block = if self.is_token_match(&Token::ElseKw) {
+                    self.next_token()?;
+                    Some(self.next_block()?)
+                } else {
+                    None
+                };
+                Stmt::If {
+                    if_block: ConditionBlock::new(Expr::negate(condition), block),
+                    elseif_blocks: vec![],
+                    else_block,
+                }
+            }
+            Token::FunKw | Token::PubKw => Stmt::Function(self.next_function()?),
+            Token::OpKw => Stmt::Operator(self.next_operator()?),
             Token::TypeKw => Stmt::UserTy(self.next_user_type()?),
+            Token::EnumKw => Stmt::UserTy(self.next_enum_type()?),
+            Token::MatchKw => Stmt::Match(self.next_match(false)?),
+            Token::UseKw => {
+                self.next_token()?;
+                let range = self.curr_range();
+                let name = self.next_bareword_preserve_newline()?;
+                if is_pragma_name(&name) {
+                    Stmt::Pragma(self.next_pragma(range, true, name)?)
+                } else {
+                    Stmt::Use(name)
+                }
+            }
+            Token::NoKw => {
+                self.next_token()?;
+                let range = self.curr_range();
+                let name = self.next_bareword_preserve_newline()?;
+                if !is_pragma_name(&name) {
+                    return Err(self.err(format!("`no` may only be used with pragmas, but `{}` is a module", name)));
+                }
+                Stmt::Pragma(self.next_pragma(range, false, name)?)
+            }
+            Token::ExportKw => {
+                self.next_token()?;
+                let mut names = vec![self.next_bareword_preserve_newline()?];
+                while self.is_token_match(&Token::Comma) {
+                    self.next_token()?;
+                    names.push(self.next_bareword_preserve_newline()?);
+                }
+                Stmt::Export(names)
+            }
             ref t if t.is_lookahead::<Expr>() => {
                 // expr, assignment
                 let lhs = self.next_expr()?;
@@ -156,14 +325,37 @@ impl<'n, S> Parser<'n, S>
             }
             _ => return Err(self.err_expected_got("statement", self.curr.as_ref())),
         };
-        self.next_eol_or_eof()?;
         Ok(stmt)
     }
 
+    /// Gets the optional loop label that may follow a `break` or `continue`.
+    fn next_loop_label(&mut self) -> Result<'n, Option<String>> {
+        if let Some(Token::Bareword(_)) = self.curr.as_ref().map(RangeToken::token) {
+            self.next_bareword_preserve_newline().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Gets the arguments of a pragma whose name has already been parsed, e.g. `vars, numbers` in
+    /// `use strict vars, numbers;`.
+    fn next_pragma(&mut self, range: Range<'n>, enabled: bool, name: String) -> Result<'n, Pragma<'n>> {
+        let mut args = vec![];
+        if let Some(Token::Bareword(_)) = self.curr.as_ref().map(RangeToken::token) {
+            args.push(self.next_bareword_preserve_newline()?);
+            while self.is_token_match(&Token::Comma) {
+                self.next_token()?;
+                args.push(self.next_bareword_preserve_newline()?);
+            }
+        }
+        Ok(Pragma { range, enabled, name, args })
+    }
+
     fn next_eol_or_eof(&mut self) -> Result<'n, ()> {
         if self.is_token_match(&Token::LineEnd) || self.is_token_match(&Token::NewLine) || self.is_token_match(&Token::Comment) {
             self.next_token().map(|_| ())
-        } else if self.curr.is_none() {
+        } else if self.curr.is_none() || self.is_token_match(&Token::RBrace) {
+            // the last statement of a block may share a line with its closing brace
             Ok(())
         } else {
             Err(self.err_expected_got("end-of-line (newline or `;`) or EOF", self.curr.as_ref()))
@@ -180,7 +372,16 @@ impl<'n, S> Parser<'n, S>
         self.match_token(Token::LBrace)?;
         let mut stmts = vec![];
         while !self.is_token_match(&Token::RBrace) {
+            if self.is_lookahead::<Operator>() {
+                return Err(self.err("operators may only be declared at the top level or inside of a type".to_string()));
+            }
+            if self.is_token_match(&Token::ExportKw) {
+                return Err(self.err("modules may only be imported or exported at the top level".to_string()));
+            }
             let stmt = self.next_stmt()?;
+            if let Stmt::Use(_) = stmt {
+                return Err(self.err("modules may only be imported or exported at the top level".to_string()));
+            }
             stmts.push(stmt);
         }
         self.match_token_preserve_newline(Token::RBrace)?;
@@ -188,47 +389,69 @@ impl<'n, S> Parser<'n, S>
     }
 
     fn next_expr(&mut self) -> Result<'n, Expr<'n>> {
-        let op_queue = VecDeque::from(vec![
-            vec![Op::DoublePercent, Op::DoubleEquals, Op::DoubleTilde, Op::NotEquals,
-                 Op::LessEquals, Op::GreaterEquals, Op::Less, Op::Greater],
-            vec![Op::Or],
-            vec![Op::And],
-            vec![Op::Tilde],
-            vec![Op::Plus, Op::Minus],
-            vec![Op::Splat, Op::FSlash], ]);
-        self.next_binary_expr(op_queue)
-    }
-
-    fn next_binary_expr(&mut self, mut op_queue: VecDeque<Vec<Op>>) -> Result<'n, Expr<'n>> {
-        if let Some(top) = op_queue.pop_front() {
-            let lhs = self.next_binary_expr(op_queue.clone())?;
-            let op_matches = self.curr.as_ref()
-                .map(|t| if let &Token::Op(ref op) = t.token() {
-                    top.contains(op)
-                } else {
-                    false
-                })
-                .unwrap_or(false);
-            if op_matches {
-                let op = self.next_op()?;
-                op_queue.push_front(top);
-                let rhs = self.next_binary_expr(op_queue)?;
-                Ok(Expr::Binary(Box::new(lhs), op, Box::new(rhs)))
+        self.next_binary_expr(Precedence::loosest())
+    }
+
+    /// Gets the next binary expression, only consuming operators that bind at least as tightly as
+    /// the given precedence.
+    ///
+    /// Chained comparisons such as `$a < $b < $c` are gathered into a single `Expr::Chain`, so that
+    /// the middle operands are only evaluated once.
+    fn next_binary_expr(&mut self, min_precedence: Precedence) -> Result<'n, Expr<'n>> {
+        let mut lhs = self.next_unary_expr()?;
+        // whether `lhs` is a comparison that the next comparison is chained onto
+        let mut chaining = false;
+        while let Some(Token::Op(op)) = self.curr.as_ref().map(RangeToken::token).cloned() {
+            let info = if let Some(info) = self.op_table.get(&op) {
+                info
             } else {
-                Ok(lhs)
+                return Err(self.err(format!("unknown binary operator `{}`", op)));
+            };
+            if info.precedence < min_precedence {
+                break;
+            }
+            self.next_op()?;
+
+            if op == Op::Question {
+                let then_expr = self.next_expr()?;
+                self.match_token(Token::Colon)?;
+                let else_expr = self.next_binary_expr(info.precedence)?;
+                lhs = Expr::Ternary(Box::new(lhs), Box::new(then_expr), Box::new(else_expr));
+                chaining = false;
+                continue;
+            }
+
+            let rhs_precedence = match info.assoc {
+                Assoc::Right => info.precedence,
+                Assoc::Left | Assoc::Chain => info.precedence.tighter(),
+            };
+            let rhs = self.next_binary_expr(rhs_precedence)?;
+            if info.assoc == Assoc::Chain {
+                lhs = match lhs {
+                    Expr::Binary(first, prev_op, middle) if chaining =>
+                        Expr::Chain(first, vec![(prev_op, *middle), (op, rhs)]),
+                    Expr::Chain(first, mut rest) if chaining => {
+                        rest.push((op, rhs));
+                        Expr::Chain(first, rest)
+                    }
+                    lhs => Expr::Binary(Box::new(lhs), op, Box::new(rhs)),
+                };
+                chaining = true;
+            } else {
+                lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+                chaining = false;
             }
-        } else {
-            self.next_unary_expr()
         }
+        Ok(lhs)
     }
 
-
     fn next_unary_expr(&mut self) -> Result<'n, Expr<'n>> {
         if self.is_curr_op() {
             let token = self.next_token()?.unwrap();
             if token.is_lookahead::<Expr>() {
                 let op = Token::from(token).into_op();
-                let expr = self.next_unary_expr()?;
+                // unary operators bind looser than exponentiation, so `-2 ** 2` is `-(2 ** 2)`
+                let expr = self.next_binary_expr(Precedence::Exponent)?;
                 Ok(Expr::Unary(op, Box::new(expr)))
             } else {
                 Err(self.err_expected_got("unary operator", Some(&token)))
@@ -253,7 +476,19 @@ impl<'n, S> Parser<'n, S>
             Token::LParen => {
                 self.next_token()?;
                 self.stmt_level += 1;
-                let inner = self.next_expr()?;
+                let mut inner = self.next_expr()?;
+                // a comma makes this a list, e.g. `($a, $b)`
+                if self.is_token_match(&Token::Comma) {
+                    let mut items = vec![inner];
+                    while self.is_token_match(&Token::Comma) {
+                        self.next_token()?;
+                        if self.is_token_match(&Token::RParen) {
+                            break;
+                        }
+                        items.push(self.next_expr()?);
+                    }
+                    inner = Expr::List(items);
+                }
                 self.stmt_level -= 1;
                 // stmt_level is set to 0 at the start of each stmt rule, so stmts that end in
                 // expressions are *required* to have a newline at the end
@@ -264,38 +499,185 @@ impl<'n, S> Parser<'n, S>
                 }
                 inner
             }
+            Token::LBracket => {
+                self.next_token()?;
+                let mut items = vec![];
+                while !self.is_token_match(&Token::RBracket) {
+                    items.push(self.next_expr()?);
+                    if !self.is_token_match(&Token::RBracket) {
+                        self.match_token(Token::Comma)?;
+                    }
+                }
+                if self.stmt_level == 0 {
+                    self.match_token_preserve_newline(Token::RBracket)?;
+                } else {
+                    self.match_token(Token::RBracket)?;
+                }
+                Expr::Array(items)
+            }
+            Token::MatchKw => Expr::Match(self.next_match(true)?),
+            Token::IfKw => self.next_if_expr()?,
+            Token::MyKw | Token::OurKw | Token::LocalKw => {
+                let kind = match curr {
+                    Token::MyKw => DeclKind::My,
+                    Token::OurKw => DeclKind::Our,
+                    _ => DeclKind::Local,
+                };
+                self.next_token()?;
+                let target = self.next_atom_expr()?;
+                let is_variable = |expr: &Expr| matches!(expr, Expr::Atom(t) if matches!(t.token(), Token::Variable(_)));
+                let is_declarable = match &target {
+                    Expr::List(items) => items.iter().all(is_variable),
+                    target => is_variable(target),
+                };
+                if !is_declarable {
+                    return Err(self.err(format!("only variables and lists of variables may be declared with `{}`", kind)));
+                }
+                Expr::Declare(kind, Box::new(target))
+            }
             _ => {
                 if self.is_token_match(&Token::SelfKw) && !self.inside_type {
                     return Err(self.err("'self' keyword expression may only appear inside of a type declaration".to_string()));
                 }
-                if self.stmt_level == 0 {
-                    Expr::Atom(self.next_token_or_newline()?.unwrap())
+                let atom = if self.stmt_level == 0 {
+                    self.next_token_or_newline()?.unwrap()
                 } else {
-                    Expr::Atom(self.next_token()?.unwrap())
+                    self.next_token()?.unwrap()
+                };
+                let map_kind = match atom.token() {
+                    Token::Bareword(name) if self.is_token_match(&Token::LBrace) => MapKind::from_name(name),
+                    _ => None,
+                };
+                if let Some(kind) = map_kind {
+                    let block = self.next_value_block()?;
+                    let list = self.next_expr()?;
+                    return Ok(Expr::Map { kind, range: atom.0, block, list: Box::new(list) });
                 }
+                Expr::Atom(atom)
             }
         };
 
-        if self.is_token_match(&Token::LParen) {
-            let args = self.next_funcall_args()?;
-            expr = Expr::FunCall { function: Box::new(expr), args }
+        // calls and indices may follow any atom, and each other, e.g. `$handlers[0](4)`
+        loop {
+            if self.is_token_match(&Token::LParen) {
+                let args = self.next_funcall_args()?;
+                expr = Expr::FunCall { function: Box::new(expr), args }
+            } else if self.is_token_match(&Token::LBracket) {
+                self.next_token()?;
+                let index = self.next_expr()?;
+                self.match_token_preserve_newline(Token::RBracket)?;
+                expr = Expr::ArrayAccess { array: Box::new(expr), index: Box::new(index) }
+            } else {
+                return Ok(expr);
+            }
         }
+    }
 
-        if self.is_token_match(&Token::LBracket) {
+    fn next_function(&mut self) -> Result<'n, Function<'n>> {
+        let visibility = if self.is_token_match(&Token::PubKw) {
             self.next_token()?;
-            let index = self.next_expr()?;
-            self.match_token_preserve_newline(Token::RBracket)?;
-            Ok(Expr::ArrayAccess{ array: Box::new(expr), index: Box::new(index) })
+            Visibility::Public
         } else {
-            Ok(expr)
+            Visibility::Private
+        };
+        self.match_token(Token::FunKw)?;
+        let range = self.curr_range();
+        let name = self.next_bareword()?;
+        if name.contains("::") {
+            return Err(self.err(format!("function name `{}` may not be qualified with a module or type", name)));
         }
+        let params = self.next_function_params()?;
+        let return_ty = self.next_return_ty()?;
+        let body = self.next_block()?;
+        Ok(Function {
+            range,
+            visibility,
+            name,
+            params,
+            return_ty,
+            body,
+        })
     }
 
-    fn next_function(&mut self) -> Result<'n, Function<'n>> {
-        self.match_token(Token::FunKw)?;
-        let name = self.next_bareword()?;
+    fn next_operator(&mut self) -> Result<'n, Operator<'n>> {
+        self.match_token(Token::OpKw)?;
+        let range = self.curr_range();
+        let op = match self.curr.as_ref().map(RangeToken::token) {
+            Some(Token::Op(_)) => self.next_op()?,
+            _ => return Err(self.err_expected_got("operator", self.curr.as_ref())),
+        };
+        let params = self.next_function_params()?;
+        if params.len() != 2 {
+            return Err(self.err(format!("operator `{}` must have exactly two parameters", op)));
+        }
+        let return_ty = self.next_return_ty()?;
+
+        let mut precedence = None;
+        let mut assoc = None;
+        while let Some(Token::Bareword(clause)) = self.curr.as_ref().map(RangeToken::token).cloned() {
+            self.next_token()?;
+            self.match_token(Token::LParen)?;
+            let name = self.next_bareword()?;
+            self.match_token(Token::RParen)?;
+            match clause.as_str() {
+                "precedence" => if let Some(p) = Precedence::from_name(&name) {
+                    precedence = Some(p);
+                } else {
+                    return Err(self.err(format!("unknown operator precedence `{}`", name)));
+                },
+                "assoc" => if let Some(a) = Assoc::from_name(&name) {
+                    assoc = Some(a);
+                } else {
+                    return Err(self.err(format!("unknown operator associativity `{}`", name)));
+                },
+                _ => return Err(self.err(format!("unknown operator clause `{}`", clause))),
+            }
+        }
+
+        // new operators are only known after they are declared, for the rest of the file and any
+        // files that share its operator table
+        let declared = precedence.map(|precedence| OpInfo::new(precedence, assoc.unwrap_or(Assoc::Left)));
+        let info = if let Some(existing) = self.op_table.get(&op) {
+            if declared == Some(existing) && !OpTable::is_builtin(&op) {
+                // a shared operator table may already know this operator from an earlier parse
+                declared
+            } else if precedence.is_some() || assoc.is_some() {
+                return Err(self.err(format!("cannot change the precedence or associativity of existing operator `{}`", op)));
+            } else {
+                None
+            }
+        } else if self.inside_type {
+            return Err(self.err(format!("operator `{}` must be declared before a type can overload it", op)));
+        } else if let Some(info) = declared {
+            self.op_table.insert(op.clone(), info);
+            Some(info)
+        } else {
+            return Err(self.err(format!("new operator `{}` must declare its precedence", op)));
+        };
+
+        let body = self.next_block()?;
+        let function = Function {
+            range,
+            visibility: Visibility::Public,
+            name: format!("op {}", op),
+            params,
+            return_ty,
+            body,
+        };
+        Ok(Operator { op, info, function })
+    }
+
+    fn next_return_ty(&mut self) -> Result<'n, Option<String>> {
+        if self.is_token_match(&Token::Colon) {
+            self.next_token()?;
+            Ok(Some(self.next_bareword()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_function_params(&mut self) -> Result<'n, Vec<FunctionParam<'n>>> {
         let mut params = vec![];
-        let mut return_ty = None;
         let mut defaults = false;
         self.match_token(Token::LParen)?;
         while !self.is_token_match(&Token::RParen) {
@@ -307,6 +689,13 @@ impl<'n, S> Parser<'n, S>
                 }
                 self.next_token()?;
                 params.push(FunctionParam::SelfKw);
+            } else if self.is_token_match(&Token::Op(Op::Splat)) {
+                self.next_token()?;
+                let name = self.next_variable()?;
+                if !self.is_token_match(&Token::RParen) {
+                    return Err(self.err(format!("variadic parameter `*${}` must be the last parameter", name)));
+                }
+                params.push(FunctionParam::Variadic { name });
             } else {
                 let param_name = self.next_variable()?;
                 let mut ty = None;
@@ -329,17 +718,7 @@ impl<'n, S> Parser<'n, S>
             }
         }
         self.match_token(Token::RParen)?;
-        if self.is_token_match(&Token::Colon) {
-            self.next_token()?;
-            return_ty = Some(self.next_bareword()?);
-        }
-        let body = self.next_block()?;
-        Ok(Function {
-            name,
-            params,
-            return_ty,
-            body,
-        })
+        Ok(params)
     }
 
     fn next_user_type(&mut self) -> Result<'n, UserTy<'n>> {
@@ -347,6 +726,7 @@ impl<'n, S> Parser<'n, S>
         self.inside_type = true;
 
         self.match_token(Token::TypeKw)?;
+        let range = self.curr_range();
         let name = self.next_bareword()?;
 
         let mut parents = Vec::new();
@@ -364,9 +744,13 @@ impl<'n, S> Parser<'n, S>
 
         self.match_token(Token::LBrace)?;
         let mut functions = Vec::new();
-        while self.is_lookahead::<Function>() {
-            let function = self.next_function()?;
-            functions.push(function);
+        let mut operators = Vec::new();
+        while self.is_lookahead::<Function>() || self.is_lookahead::<Operator>() {
+            if self.is_lookahead::<Operator>() {
+                operators.push(self.next_operator()?);
+            } else {
+                functions.push(self.next_function()?);
+            }
 
             // skip newlines; next_function preserves them
             while self.is_token_match(&Token::NewLine) || self.is_token_match(&Token::Comment) {
@@ -376,17 +760,219 @@ impl<'n, S> Parser<'n, S>
         self.match_token_preserve_newline(Token::RBrace)?;
 
         self.inside_type = old_inside_type;
-        Ok(UserTy { name, parents, functions })
+        Ok(UserTy { range, name, parents, functions, operators, variants: vec![] })
+    }
+
+    fn next_enum_type(&mut self) -> Result<'n, UserTy<'n>> {
+        let old_inside_type = self.inside_type;
+        self.inside_type = true;
+
+        self.match_token(Token::EnumKw)?;
+        let range = self.curr_range();
+        let name = self.next_bareword()?;
+
+        self.match_token(Token::LBrace)?;
+        let mut variants = Vec::new();
+        let mut functions = Vec::new();
+        let mut operators = Vec::new();
+        while !self.is_token_match(&Token::RBrace) {
+            if self.is_lookahead::<Function>() {
+                let function = self.next_function()?;
+                functions.push(function);
+            } else if self.is_lookahead::<Operator>() {
+                operators.push(self.next_operator()?);
+            } else {
+                let variant = self.next_variant()?;
+                variants.push(variant);
+                while self.is_token_match(&Token::NewLine) || self.is_token_match(&Token::Comment) {
+                    self.next_token()?;
+                }
+                // variants are comma-separated, with an optional trailing comma
+                if self.is_token_match(&Token::Comma) {
+                    self.next_token()?;
+                } else if !self.is_token_match(&Token::RBrace) && !self.is_lookahead::<Function>()
+                    && !self.is_lookahead::<Operator>() {
+                    return Err(self.err_expected_got("comma or right brace", self.curr.as_ref()));
+                }
+            }
+
+            // skip newlines; next_function and next_variant preserve them
+            while self.is_token_match(&Token::NewLine) || self.is_token_match(&Token::Comment) {
+                self.next_token()?;
+            }
+        }
+        if variants.is_empty() {
+            return Err(self.err(format!("enum `{}` must have at least one variant", name)));
+        }
+        self.match_token_preserve_newline(Token::RBrace)?;
+
+        self.inside_type = old_inside_type;
+        Ok(UserTy { range, name, parents: vec![], functions, operators, variants })
+    }
+
+    fn next_variant(&mut self) -> Result<'n, Variant<'n>> {
+        let name = if let Some(token) = self.next_token_or_newline()? {
+            match token.as_inner() {
+                Token::Bareword(bareword) => bareword.clone(),
+                _ => return Err(self.err_expected_got(Variant::name(), Some(&token))),
+            }
+        } else {
+            return Err(self.err_expected_got_eof(Variant::name()));
+        };
+        let mut fields = vec![];
+        if self.is_token_match(&Token::LParen) {
+            self.match_token(Token::LParen)?;
+            while !self.is_token_match(&Token::RParen) {
+                let field_name = self.next_variable()?;
+                let mut ty = None;
+                if self.is_token_match(&Token::Colon) {
+                    self.match_token(Token::Colon)?;
+                    ty = Some(self.next_bareword()?);
+                }
+                fields.push(FunctionParam::Variable { name: field_name, ty, default: None });
+
+                if !self.is_token_match(&Token::RParen) {
+                    self.match_token(Token::Comma)?;
+                }
+            }
+            self.match_token_preserve_newline(Token::RParen)?;
+        }
+        Ok(Variant { name, fields })
+    }
+
+    /// Gets the next match statement or expression.
+    ///
+    /// # Arguments
+    /// `is_expr` - whether this match is an expression, requiring that each arm is an expression.
+    fn next_match(&mut self, is_expr: bool) -> Result<'n, Match<'n>> {
+        let keyword = self.match_token(Token::MatchKw)?;
+        let value = self.next_expr()?;
+        self.match_token(Token::LBrace)?;
+        let mut arms = vec![];
+        while !self.is_token_match(&Token::RBrace) {
+            let pattern = self.next_pattern()?;
+            self.match_token(Token::FatArrow)?;
+            let body = if is_expr && self.is_token_match(&Token::LBrace) {
+                self.next_value_block()?
+            } else if is_expr {
+                vec![Stmt::Expr(self.next_expr()?)]
+            } else if self.is_token_match(&Token::LBrace) {
+                self.next_block()?
+            } else {
+                vec![self.next_bare_stmt()?]
+            };
+            arms.push(MatchArm { pattern, body });
+
+            // arms are separated by commas and/or newlines
+            let mut separated = false;
+            while self.is_token_match(&Token::NewLine) || self.is_token_match(&Token::Comment) {
+                self.next_token()?;
+                separated = true;
+            }
+            if self.is_token_match(&Token::Comma) {
+                self.next_token()?;
+            } else if !separated && !self.is_token_match(&Token::RBrace) {
+                return Err(self.err_expected_got("comma, end-of-line, or right brace", self.curr.as_ref()));
+            }
+        }
+        let rbrace = self.match_token_preserve_newline(Token::RBrace)?;
+        let range = Range::new(keyword.0.start(), rbrace.0.end());
+        Ok(Match { range, value: Box::new(value), arms })
+    }
+
+    /// Gets the next if-expression, which must have an else block so that it always has a value.
+    fn next_if_expr(&mut self) -> Result<'n, Expr<'n>> {
+        self.match_token(Token::IfKw)?;
+        let condition = self.next_expr()?;
+        self.next_if_expr_blocks(condition)
+    }
+
+    /// Gets the blocks of an if-expression whose condition has already been parsed.
+    fn next_if_expr_blocks(&mut self, condition: Expr<'n>) -> Result<'n, Expr<'n>> {
+        let if_block = Box::new(ConditionBlock::new(condition, self.next_value_block()?));
+        let mut elseif_blocks = vec![];
+        loop {
+            if !self.is_token_match(&Token::ElseKw) {
+                return Err(self.err_expected_got("else block for if-expression", self.curr.as_ref()));
+            }
+            self.next_token()?;
+            if self.is_token_match(&Token::IfKw) {
+                self.next_token()?;
+                let condition = self.next_expr()?;
+                elseif_blocks.push(ConditionBlock::new(condition, self.next_value_block()?));
+            } else {
+                let else_block = self.next_value_block()?;
+                return Ok(Expr::If { if_block, elseif_blocks, else_block });
+            }
+        }
+    }
+
+    /// Gets the next block whose value is used, which is the value of its final expression.
+    fn next_value_block(&mut self) -> Result<'n, Block<'n>> {
+        // statements inside of the block are parsed on their own, even inside of parens
+        let stmt_level = mem::replace(&mut self.stmt_level, 0);
+        let block = self.next_block();
+        self.stmt_level = stmt_level;
+        let block = block?;
+        if !matches!(block.last(), Some(Stmt::Expr(_))) {
+            return Err(self.err("blocks whose value is used must end with an expression".to_string()));
+        }
+        if self.stmt_level > 0 && self.is_token_match(&Token::NewLine) {
+            self.next_token()?;
+        }
+        Ok(block)
+    }
+
+    fn next_pattern(&mut self) -> Result<'n, Pattern<'n>> {
+        if !self.is_lookahead::<Pattern>() {
+            return Err(self.err_expected_got(Pattern::name(), self.curr.as_ref()));
+        }
+        let token = self.next_token()?.unwrap();
+        let pattern = match token.token().clone() {
+            Token::Bareword(ref b) if b == "_" => Pattern::Wildcard,
+            Token::Bareword(_) if self.is_token_match(&Token::LParen) => {
+                let fields = self.next_pattern_list(Token::LParen, Token::RParen)?;
+                Pattern::Variant(token, fields)
+            }
+            Token::Bareword(_) => Pattern::Bareword(token),
+            Token::Variable(_) => Pattern::Variable(token),
+            Token::LBracket => {
+                let items = self.next_pattern_list(None, Token::RBracket)?;
+                Pattern::Array(items)
+            }
+            _ => Pattern::Literal(token),
+        };
+        Ok(pattern)
+    }
+
+    /// Gets a comma-separated list of patterns, ending with the given token.
+    ///
+    /// # Arguments
+    /// `open` - the token to match before the list, if it hasn't already been matched.
+    /// `close` - the token that ends the list.
+    fn next_pattern_list(&mut self, open: impl Into<Option<Token>>, close: Token) -> Result<'n, Vec<Pattern<'n>>> {
+        if let Some(open) = open.into() {
+            self.match_token(open)?;
+        }
+        let mut patterns = vec![];
+        while !self.is_token_match(&close) {
+            patterns.push(self.next_pattern()?);
+            if !self.is_token_match(&close) {
+                self.match_token(Token::Comma)?;
+            }
+        }
+        self.match_token(close)?;
+        Ok(patterns)
     }
 
     fn next_funcall_args(&mut self) -> Result<'n, Vec<Expr<'n>>> {
         self.match_token(Token::LParen)?;
         let mut args = vec![];
         if !self.is_token_match(&Token::RParen) {
-            args.push(self.next_expr()?);
+            args.push(self.next_funcall_arg()?);
             while self.is_token_match(&Token::Comma) {
                 self.next_token()?;
-                args.push(self.next_expr()?);
+                args.push(self.next_funcall_arg()?);
             }
         }
         if self.stmt_level == 0 {
@@ -397,6 +983,25 @@ impl<'n, S> Parser<'n, S>
         Ok(args)
     }
 
+    /// Gets the next function call argument, which may be an array splatted into several arguments
+    /// with `*`, or an argument passed by name with `name: value`.
+    fn next_funcall_arg(&mut self) -> Result<'n, Expr<'n>> {
+        let is_named = matches!(self.curr.as_ref().map(|r| r.token()), Some(Token::Bareword(_)))
+            && matches!(self.next.as_ref().map(|r| r.token()), Some(Token::Colon));
+        if is_named {
+            let name = self.next_bareword()?;
+            self.match_token(Token::Colon)?;
+            let value = self.next_expr()?;
+            Ok(Expr::NamedArg(name, Box::new(value)))
+        } else if self.is_token_match(&Token::Op(Op::Splat)) {
+            self.next_token()?;
+            let array = self.next_expr()?;
+            Ok(Expr::Unary(Op::Splat, Box::new(array)))
+        } else {
+            self.next_expr()
+        }
+    }
+
     fn next_variable(&mut self) -> Result<'n, String> {
         if let Some(token) = self.next_token()? {
             match token.as_inner() {
@@ -419,6 +1024,16 @@ impl<'n, S> Parser<'n, S>
         }
     }
 
+    /// Gets the next bareword, without skipping over a newline that may end the statement.
+    fn next_bareword_preserve_newline(&mut self) -> Result<'n, String> {
+        if let Some(Token::Bareword(bareword)) = self.curr.as_ref().map(|r| r.token().clone()) {
+            self.next_token_or_newline()?;
+            Ok(bareword)
+        } else {
+            Err(self.err_expected_got("bareword", self.curr.as_ref()))
+        }
+    }
+
     fn next_op(&mut self) -> Result<'n, Op> {
         let matches = if let Some(&Token::Op(_)) = self.curr.as_ref().map(|r| r.token()) { true }
                       else { false };
@@ -511,6 +1126,17 @@ impl<'n, S> Parser<'n, S>
         Ok(token)
     }
 
+    /// Gets the range of the current token, or an empty range at the end of the input if there
+    /// are no more tokens.
+    fn curr_range(&self) -> Range<'n> {
+        if let Some(curr) = &self.curr {
+            curr.0
+        } else {
+            let pos = self.lexer.pos();
+            Range::new(pos, pos)
+        }
+    }
+
     /// Creates a new `SyntaxError` using the supplied expected item.
     /// # Args
     /// `expected` - the expected item.
@@ -551,6 +1177,31 @@ impl<'n, S> Parser<'n, S>
     }
 }
 
+/// Gets whether a name following `use` is a pragma rather than a module.
+///
+/// Modules are capitalized, so lowercase names are pragmas, e.g. `use strict`.
+fn is_pragma_name(name: &str) -> bool {
+    name.starts_with(char::is_lowercase) && !name.contains("::")
+}
+
+/// Finds the names of the modules that some source uses, without parsing it.
+///
+/// The source can't be parsed until the operators declared by its modules are known, so this
+/// only looks at its tokens. Anything after a lexing error is ignored.
+pub fn used_modules(contents: &str, source_name: &str) -> Vec<String> {
+    let tokens: Vec<Token> = Lexer::new(contents.chars(), source_name)
+        .map_while(|result| result.ok())
+        .map(|token| token.token().clone())
+        .filter(|token| !matches!(token, Token::LineEnd | Token::NewLine | Token::Comment))
+        .collect();
+    tokens.windows(2)
+        .filter_map(|pair| match pair {
+            [Token::UseKw, Token::Bareword(name)] if !is_pragma_name(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use syntax::*;
@@ -566,7 +1217,11 @@ mod test {
     }
 
     macro_rules! token {
-        ($($token:tt)+) => { RangeToken::new(Range::new(Pos::default(), Pos::default()), $($token)+) }
+        ($($token:tt)+) => { RangeToken::new(range!(), $($token)+) }
+    }
+
+    macro_rules! range {
+        () => { Range::new(Pos::default(), Pos::default()) }
     }
 
     #[test]
@@ -582,6 +1237,403 @@ mod test {
                   );
     }
 
+    #[test]
+    fn test_parser_chain() {
+        // each operand of a chained comparison appears in the tree once
+        match test_parser!("1 < f() <= 3").next_expr().unwrap() {
+            Expr::Chain(first, rest) => {
+                assert_eq!(first.canonicalize(), "1");
+                let rest: Vec<_> = rest.iter()
+                    .map(|(op, rhs)| format!("{} {}", op, rhs.canonicalize()))
+                    .collect();
+                assert_eq!(rest, vec!["< f()", "<= 3"]);
+            }
+            expr => panic!("expected comparison chain, got {:?}", expr),
+        }
+        assert!(matches!(test_parser!("1 < 2").next_expr().unwrap(), Expr::Binary(_, Op::Less, _)));
+    }
+
+    #[test]
+    fn test_parser_precedence() {
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("1 - 2 - 3"), "((1 - 2) - 3)");
+        assert_eq!(canonical("2 ** 3 ** 2"), "(2 ** (3 ** 2))");
+        assert_eq!(canonical("1 + 2 * 3 % 4"), "(1 + ((2 * 3) % 4))");
+        assert_eq!(canonical("$a || $b < $c && $d"), "($a || (($b < $c) && $d))");
+        assert_eq!(canonical("$a ~ $b + 1"), "($a ~ ($b + 1))");
+        assert_eq!(canonical("-2 ** 2"), "(-(2 ** 2))");
+        assert_eq!(canonical("$a < $b <= $c"), "(($a < $b) && ($b <= $c))");
+        assert_eq!(canonical("$a < $b < $c < $d"), "((($a < $b) && ($b < $c)) && ($c < $d))");
+        assert_eq!(canonical("($a < $b) < $c"), "(($a < $b) < $c)");
+        assert_eq!(canonical("$a ~ $b |> f(1) |> g"), "((($a ~ $b) |> f(1)) |> g)");
+        assert_eq!(canonical("$x |> f == 3"), "(($x |> f) == 3)");
+        assert_eq!(canonical("$a ? 1 : $b ? 2 : 3"), "($a ? 1 : ($b ? 2 : 3))");
+        assert_eq!(canonical("f($a, [1, 2])[0]"), "f($a, [1, 2])[0]");
+        assert_eq!(canonical("$f[0](4)"), "$f[0](4)");
+        assert_eq!(canonical("f()(1)[2][3]"), "f()(1)[2][3]");
+    }
+
+    #[test]
+    fn test_parser_operator() {
+        let mut parser = test_parser!("op <=> ($a, $b) precedence(comparison) assoc(left) { return 0; }\n1 <=> 2 + 3");
+        let operator = parser.next_operator().unwrap();
+        assert_eq!(operator.op, Op::Custom("<=>".to_string()));
+        assert_eq!(operator.info, Some(OpInfo::new(Precedence::Comparison, Assoc::Left)));
+        parser.next_token().unwrap();
+        assert_eq!(parser.next_expr().unwrap().canonicalize(), "(1 <=> (2 + 3))");
+
+        // operators are unknown until they are declared
+        let mut parser = test_parser!("1 <=> 2");
+        assert!(parser.next_expr().is_err());
+        let mut parser = test_parser!("op <=> ($a, $b) { return 0; }");
+        assert!(parser.next_operator().is_err());
+    }
+
+    #[test]
+    fn test_parser_shared_operators() {
+        let mut op_table = OpTable::new();
+        Parser::new("op <=> ($a, $b) precedence(comparison) { return 0; }".chars(), "a")
+            .into_parse_tree_with(&mut op_table)
+            .unwrap();
+        assert_eq!(op_table.get(&Op::Custom("<=>".to_string())), Some(OpInfo::new(Precedence::Comparison, Assoc::Left)));
+        assert!(Parser::new("1 <=> 2".chars(), "b").into_parse_tree_with(&mut op_table).is_ok());
+        assert!(Parser::new("1 <=> 2".chars(), "c").into_parse_tree().is_err());
+
+        // the same declaration may be parsed again, but it may not be changed
+        assert!(Parser::new("op <=> ($a, $b) precedence(comparison) { return 0; }".chars(), "a")
+            .into_parse_tree_with(&mut op_table)
+            .is_ok());
+        assert!(Parser::new("op <=> ($a, $b) precedence(additive) { return 0; }".chars(), "d")
+            .into_parse_tree_with(&mut op_table)
+            .is_err());
+        assert!(Parser::new("op + ($a, $b) precedence(additive) { return 0; }".chars(), "e")
+            .into_parse_tree_with(&mut op_table)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parser_use() {
+        let mut parser = test_parser!("use Some::Module;\nexport foo, Bar;");
+        assert_eq!(parser.next_stmt().unwrap(), Stmt::Use("Some::Module".to_string()));
+        assert_eq!(parser.next_stmt().unwrap(), Stmt::Export(vec!["foo".to_string(), "Bar".to_string()]));
+
+        let mut parser = test_parser!("fun f() { use Some::Module; }");
+        assert!(parser.next_function().is_err());
+
+        assert_eq!(used_modules("use strict\n# use Commented\nuse Some::Module\nfun f() { }\nuse Other", "test"),
+                   vec!["Some::Module".to_string(), "Other".to_string()]);
+    }
+
+    #[test]
+    fn test_parser_visibility() {
+        let mut parser = test_parser!("type Counter {\n    pub fun new() { return 0; }\n    fun helper() { }\n}");
+        let user_ty = parser.next_user_type().unwrap();
+        let visibilities: Vec<_> = user_ty.functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.visibility))
+            .collect();
+        assert_eq!(visibilities, vec![("new", Visibility::Public), ("helper", Visibility::Private)]);
+
+        let mut parser = test_parser!("fun Some::func() { }");
+        assert!(parser.next_function().is_err());
+    }
+
+    #[test]
+    fn test_parser_variadic() {
+        let mut parser = test_parser!("fun f($first, *$rest) { }");
+        let function = parser.next_function().unwrap();
+        assert_eq!(function.params[1], FunctionParam::Variadic { name: "rest".to_string() });
+
+        let mut parser = test_parser!("fun f(*$rest, $last) { }");
+        assert!(parser.next_function().is_err());
+
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("f(1, *$array)"), "f(1, (*$array))");
+    }
+
+    #[test]
+    fn test_parser_named_args() {
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("prompt-line(empty-ok: true)"), "prompt-line(empty-ok: true)");
+        assert_eq!(canonical("f($a, b: 1 + 2, c: g(d: 3))"), "f($a, b: (1 + 2), c: g(d: 3))");
+        assert_eq!(canonical("f($a ? b : c)"), "f(($a ? b : c))");
+    }
+
+    #[test]
+    fn test_parser_destructuring() {
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("($a, [$b, $c])"), "($a, [$b, $c])");
+        assert_eq!(canonical("(1 + 2)"), "(1 + 2)");
+
+        let mut parser = test_parser!("($a, $b) = ($b, $a)");
+        match parser.next_stmt().unwrap() {
+            Stmt::Assign(Expr::List(targets), AssignOp::Equals, Expr::List(values)) => {
+                assert_eq!(targets.len(), 2);
+                assert_eq!(values.len(), 2);
+            }
+            stmt => panic!("expected list assignment, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("return $q, $r");
+        match parser.next_stmt().unwrap() {
+            Stmt::Return(_, Some(list)) => assert_eq!(list.canonicalize(), "($q, $r)"),
+            stmt => panic!("expected return, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("for ($k, $v) in $pairs { }");
+        match parser.next_stmt().unwrap() {
+            Stmt::For { target, iterable, block } => {
+                assert_eq!(target.canonicalize(), "($k, $v)");
+                assert_eq!(iterable.canonicalize(), "$pairs");
+                assert!(block.is_empty());
+            }
+            stmt => panic!("expected for loop, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_parser_begin() {
+        let mut parser = test_parser!("BEGIN {\n    our $config = 1\n}\nprintln($config)");
+        match parser.next_stmt().unwrap() {
+            Stmt::Begin(_, block) => assert_eq!(block.len(), 1),
+            stmt => panic!("expected BEGIN block, got {:?}", stmt),
+        }
+        assert!(matches!(parser.next_stmt().unwrap(), Stmt::Expr(_)));
+
+        assert!(test_parser!("BEGIN").next_stmt().is_err());
+    }
+
+    #[test]
+    fn test_parser_const() {
+        let mut parser = test_parser!("const LIMIT = 2 * 5
+println(LIMIT)");
+        match parser.next_stmt().unwrap() {
+            Stmt::Const(_, name, value) => {
+                assert_eq!(name, "LIMIT");
+                assert_eq!(value.canonicalize(), "(2 * 5)");
+            }
+            stmt => panic!("expected constant, got {:?}", stmt),
+        }
+        assert!(matches!(parser.next_stmt().unwrap(), Stmt::Expr(_)));
+
+        assert!(test_parser!("const $limit = 10").next_stmt().is_err());
+        assert!(test_parser!("const LIMIT += 10").next_stmt().is_err());
+    }
+
+    #[test]
+    fn test_parser_topic() {
+        let mut parser = test_parser!("for $lines { println() }");
+        match parser.next_stmt().unwrap() {
+            Stmt::For { target, iterable, block } => {
+                assert_eq!(target.canonicalize(), "local $_");
+                assert_eq!(iterable.canonicalize(), "$lines");
+                assert_eq!(block.len(), 1);
+            }
+            stmt => panic!("expected for loop, got {:?}", stmt),
+        }
+
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("map { $_ * 2 } $xs"), "(map { ($_ * 2) } $xs)");
+        assert_eq!(canonical("grep { m/^#/ } [$a, $b]"), "(grep { m/^#/ } [$a, $b])");
+        assert_eq!(canonical("map { $_ } grep { $_ } $xs"), "(map { $_ } (grep { $_ } $xs))");
+    }
+
+    #[test]
+    fn test_parser_labeled_loop() {
+        let mut parser = test_parser!("OUTER: loop {\n    loop {\n        break OUTER\n    }\n    continue\n}");
+        match parser.next_stmt().unwrap() {
+            Stmt::Labeled(label, stmt) => {
+                assert_eq!(label, "OUTER");
+                match *stmt {
+                    Stmt::Loop(block) => {
+                        assert_eq!(block[0], Stmt::Loop(vec![Stmt::Break(range!(), Some("OUTER".to_string()))]));
+                        assert_eq!(block[1], Stmt::Continue(range!(), None));
+                    }
+                    stmt => panic!("expected loop, got {:?}", stmt),
+                }
+            }
+            stmt => panic!("expected labeled loop, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("break");
+        assert_eq!(parser.next_stmt().unwrap(), Stmt::Break(range!(), None));
+
+        let mut parser = test_parser!("OUTER: $a = 1");
+        assert!(parser.next_stmt().is_err());
+    }
+
+    #[test]
+    fn test_parser_stmt_modifiers() {
+        let mut parser = test_parser!("println($a) if $verbose");
+        match parser.next_stmt().unwrap() {
+            Stmt::If { if_block, elseif_blocks, else_block } => {
+                assert_eq!(if_block.condition.canonicalize(), "$verbose");
+                assert_eq!(if_block.block.len(), 1);
+                assert!(elseif_blocks.is_empty());
+                assert!(else_block.is_none());
+            }
+            stmt => panic!("expected if, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("return if $x");
+        match parser.next_stmt().unwrap() {
+            Stmt::If { if_block, .. } => assert_eq!(if_block.block, vec![Stmt::Return(range!(), None)]),
+            stmt => panic!("expected if, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("return if $x { 1 } else { 2 }");
+        match parser.next_stmt().unwrap() {
+            Stmt::Return(_, Some(Expr::If { .. })) => {}
+            stmt => panic!("expected return of if-expression, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("return unless $x");
+        match parser.next_stmt().unwrap() {
+            Stmt::If { if_block, .. } => {
+                assert_eq!(if_block.condition.canonicalize(), "(!$x)");
+                assert_eq!(if_block.block, vec![Stmt::Return(range!(), None)]);
+            }
+            stmt => panic!("expected if, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("$i += 1 until $i > 10");
+        match parser.next_stmt().unwrap() {
+            Stmt::While(ConditionBlock { condition, block }) => {
+                assert_eq!(condition.canonicalize(), "(!($i > 10))");
+                assert_eq!(block.len(), 1);
+            }
+            stmt => panic!("expected while, got {:?}", stmt),
+        }
+
+        let mut parser = test_parser!("unless $x {\n    f()\n} else {\n    g()\n}");
+        match parser.next_stmt().unwrap() {
+            Stmt::If { if_block, else_block, .. } => {
+                assert_eq!(if_block.condition.canonicalize(), "(!$x)");
+                assert!(else_block.is_some());
+            }
+            stmt => panic!("expected if, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_parser_if_expr() {
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("if $c { 1 } else { 2 }"), "(if $c { 1 } else { 2 })");
+        assert_eq!(canonical("if $a { 1 } else if $b { 2 } else { 3 }"), "(if $a { 1 } else if $b { 2 } else { 3 })");
+        assert_eq!(canonical("f(if $c {\n    $x = 1\n    $x + 1\n} else { 0 })"), "f((if $c { ...; ($x + 1) } else { 0 }))");
+        assert_eq!(canonical("match $x { 1 => {\n    g()\n    2\n}, _ => 3 }"), "(match $x { 1 => { ...; 2 }, _ => 3 })");
+
+        assert!(test_parser!("if $c { 1 }").next_expr().is_err());
+        assert!(test_parser!("if $c { $x = 1 } else { 2 }").next_expr().is_err());
+
+        let mut parser = test_parser!("$x = if $c { 1 } else { 2 }");
+        match parser.next_stmt().unwrap() {
+            Stmt::Assign(_, AssignOp::Equals, Expr::If { .. }) => {}
+            stmt => panic!("expected if-expression assignment, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_parser_declare() {
+        let canonical = |input: &str| test_parser!(input).next_expr().unwrap().canonicalize();
+        assert_eq!(canonical("my $x"), "my $x");
+        assert_eq!(canonical("our $x"), "our $x");
+        assert_eq!(canonical("my ($a, $b)"), "my ($a, $b)");
+        assert_eq!(canonical("local $x"), "local $x");
+
+        assert!(test_parser!("my 5").next_expr().is_err());
+        assert!(test_parser!("my ($a, f())").next_expr().is_err());
+
+        let mut parser = test_parser!("use strict;\nmy $x = 1");
+        assert!(matches!(parser.next_stmt().unwrap(), Stmt::Pragma(_)));
+        match parser.next_stmt().unwrap() {
+            Stmt::Assign(Expr::Declare(DeclKind::My, _), AssignOp::Equals, _) => {}
+            stmt => panic!("expected declaration assignment, got {:?}", stmt),
+        }
+        match test_parser!("local ($a, $b) = (1, 2)").next_stmt().unwrap() {
+            Stmt::Assign(Expr::Declare(DeclKind::Local, _), AssignOp::Equals, _) => {}
+            stmt => panic!("expected local assignment, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_parser_pragma() {
+        let mut parser = test_parser!("use strict;\nno warnings unset, foo\nuse overflow wrap\nno warnings unused_variable, unreachable\nuse Some::Module");
+        assert_eq!(parser.next_stmt().unwrap(),
+                   Stmt::Pragma(Pragma { range: range!(), enabled: true, name: "strict".to_string(), args: vec![] }));
+        assert_eq!(parser.next_stmt().unwrap(),
+                   Stmt::Pragma(Pragma {
+                       range: range!(),
+                       enabled: false,
+                       name: "warnings".to_string(),
+                       args: vec!["unset".to_string(), "foo".to_string()],
+                   }));
+        assert_eq!(parser.next_stmt().unwrap(),
+                   Stmt::Pragma(Pragma { range: range!(), enabled: true, name: "overflow".to_string(), args: vec!["wrap".to_string()] }));
+        assert_eq!(parser.next_stmt().unwrap(),
+                   Stmt::Pragma(Pragma {
+                       range: range!(),
+                       enabled: false,
+                       name: "warnings".to_string(),
+                       args: vec!["unused_variable".to_string(), "unreachable".to_string()],
+                   }));
+        assert_eq!(parser.next_stmt().unwrap(), Stmt::Use("Some::Module".to_string()));
+
+        // pragmas may be used inside of blocks, but modules may not
+        let mut parser = test_parser!("if $x {\n    no strict vars\n    $y = 1\n}");
+        match parser.next_stmt().unwrap() {
+            Stmt::If { if_block, .. } => assert!(matches!(if_block.block[0], Stmt::Pragma(_))),
+            stmt => panic!("expected if statement, got {:?}", stmt),
+        }
+        assert!(test_parser!("if $x {\n    use Some::Module\n}").next_stmt().is_err());
+        assert!(test_parser!("no Some::Module").next_stmt().is_err());
+    }
+
+    #[test]
+    fn test_parser_enum() {
+        let mut parser = test_parser!("enum State {\n    Pending,\n    Done($result: Int),\n}");
+        let user_ty = parser.next_enum_type().unwrap();
+        assert_eq!(user_ty,
+                   UserTy {
+                       range: range!(),
+                       name: "State".to_string(),
+                       parents: vec![],
+                       functions: vec![],
+                       operators: vec![],
+                       variants: vec![
+                           Variant { name: "Pending".to_string(), fields: vec![] },
+                           Variant {
+                               name: "Done".to_string(),
+                               fields: vec![FunctionParam::Variable {
+                                   name: "result".to_string(),
+                                   ty: Some("Int".to_string()),
+                                   default: None,
+                               }],
+                           },
+                       ],
+                   });
+    }
+
+    #[test]
+    fn test_parser_match() {
+        let mut parser = test_parser!("match $x {\n    Done($v) => $v,\n    [1, _] => 2,\n    Int => 3,\n}");
+        let m = parser.next_match(true).unwrap();
+        assert_eq!(*m.value, Expr::Atom(token!(Token::Variable("x".to_string()))));
+        let patterns: Vec<_> = m.arms.into_iter()
+            .map(|arm| arm.pattern)
+            .collect();
+        assert_eq!(patterns,
+                   vec![
+                       Pattern::Variant(token!(Token::Bareword("Done".to_string())),
+                                        vec![Pattern::Variable(token!(Token::Variable("v".to_string())))]),
+                       Pattern::Array(vec![
+                           Pattern::Literal(token!(Token::IntLit("1".to_string(), 10))),
+                           Pattern::Wildcard,
+                       ]),
+                       Pattern::Bareword(token!(Token::Bareword("Int".to_string()))),
+                   ]);
+    }
+
     #[test]
     fn test_parser_match_token() {
         let mut parser = test_parser!("(1 + 2)");
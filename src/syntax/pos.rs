@@ -69,8 +69,8 @@ impl<'n> PartialEq for Pos<'n> {
     fn eq(&self, _other: &Self) -> bool { true }
 }
 
-#[derive(Clone, Copy, Debug)]
-#[cfg_attr(not(test), derive(PartialEq))]
+// Pos is always equal during testing, so Range is as well
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Range<'n>(Pos<'n>, Pos<'n>);
 
 impl<'n> Range<'n> {
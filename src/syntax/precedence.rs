@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use syntax::token::Op;
+
+/// A precedence level that a binary operator may bind at.
+///
+/// Levels are declared from loosest to tightest, so a greater precedence binds tighter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Precedence {
+    Ternary,
+    Or,
+    And,
+    Comparison,
+    Pipe,
+    Concat,
+    Additive,
+    Multiplicative,
+    Exponent,
+}
+
+impl Precedence {
+    /// The precedence that all expressions are parsed at.
+    pub fn loosest() -> Self {
+        Precedence::Ternary
+    }
+
+    /// Gets the precedence level that is one step tighter than this one.
+    ///
+    /// The tightest level is its own next level.
+    pub fn tighter(self) -> Self {
+        match self {
+            Precedence::Ternary => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Pipe,
+            Precedence::Pipe => Precedence::Concat,
+            Precedence::Concat => Precedence::Additive,
+            Precedence::Additive => Precedence::Multiplicative,
+            Precedence::Multiplicative => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Exponent,
+        }
+    }
+
+    /// Gets the precedence level that user-defined operators may refer to by the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "or" => Some(Precedence::Or),
+            "and" => Some(Precedence::And),
+            "comparison" => Some(Precedence::Comparison),
+            "pipe" => Some(Precedence::Pipe),
+            "concat" => Some(Precedence::Concat),
+            "additive" => Some(Precedence::Additive),
+            "multiplicative" => Some(Precedence::Multiplicative),
+            "exponent" => Some(Precedence::Exponent),
+            _ => None,
+        }
+    }
+}
+
+/// How a chain of operators with the same precedence is grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+
+    /// `a ** b ** c` is `a ** (b ** c)`.
+    Right,
+
+    /// `a < b < c` is `(a < b) && (b < c)`.
+    Chain,
+}
+
+impl Assoc {
+    /// Gets the associativity that user-defined operators may refer to by the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Assoc::Left),
+            "right" => Some(Assoc::Right),
+            _ => None,
+        }
+    }
+}
+
+/// The binding power of a binary operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpInfo {
+    pub precedence: Precedence,
+    pub assoc: Assoc,
+}
+
+impl OpInfo {
+    pub fn new(precedence: Precedence, assoc: Assoc) -> Self {
+        OpInfo { precedence, assoc }
+    }
+}
+
+/// The table of binary operators that a parser knows about.
+#[derive(Debug, Clone)]
+pub struct OpTable {
+    ops: HashMap<Op, OpInfo>,
+}
+
+impl OpTable {
+    /// Creates a new operator table with all of the builtin binary operators.
+    pub fn new() -> Self {
+        use self::Precedence::*;
+        use self::Assoc::*;
+        let builtins = vec![
+            (Op::Question, Ternary, Right),
+            (Op::Or, Or, Left),
+            (Op::DefinedOr, Or, Left),
+            (Op::And, And, Left),
+            (Op::DoubleEquals, Comparison, Chain),
+            (Op::NotEquals, Comparison, Chain),
+            (Op::DoubleTilde, Comparison, Chain),
+            (Op::DoublePercent, Comparison, Chain),
+            (Op::LessEquals, Comparison, Chain),
+            (Op::GreaterEquals, Comparison, Chain),
+            (Op::Less, Comparison, Chain),
+            (Op::Greater, Comparison, Chain),
+            (Op::Pipe, Pipe, Left),
+            (Op::Tilde, Concat, Left),
+            (Op::Plus, Additive, Left),
+            (Op::Minus, Additive, Left),
+            (Op::Splat, Multiplicative, Left),
+            (Op::FSlash, Multiplicative, Left),
+            (Op::Percent, Multiplicative, Left),
+            (Op::DoubleSplat, Exponent, Right),
+        ];
+        let ops = builtins.into_iter()
+            .map(|(op, precedence, assoc)| (op, OpInfo::new(precedence, assoc)))
+            .collect();
+        OpTable { ops }
+    }
+
+    /// Gets whether the given operator is one of the builtin binary operators.
+    pub fn is_builtin(op: &Op) -> bool {
+        OpTable::new().get(op).is_some()
+    }
+
+    pub fn get(&self, op: &Op) -> Option<OpInfo> {
+        self.ops.get(op).cloned()
+    }
+
+    /// Adds a new binary operator to this table.
+    pub fn insert(&mut self, op: Op, info: OpInfo) {
+        self.ops.insert(op, info);
+    }
+}
+
+impl Default for OpTable {
+    fn default() -> Self {
+        OpTable::new()
+    }
+}
@@ -19,7 +19,10 @@ pub enum Op {
     Plus,
     Minus,
     Splat,
+    DoubleSplat,
     FSlash,
+    Percent,
+    Question,
     Tilde,
     Or,
     And,
@@ -31,6 +34,8 @@ pub enum Op {
     GreaterEquals,
     Less,
     Greater,
+    Pipe,
+    DefinedOr,
     Custom(String),
 }
 
@@ -45,7 +50,10 @@ impl<S> From<S> for Op
             "+" => Op::Plus,
             "-" => Op::Minus,
             "*" => Op::Splat,
+            "**" => Op::DoubleSplat,
             "/" => Op::FSlash,
+            "%" => Op::Percent,
+            "?" => Op::Question,
             "~" => Op::Tilde,
             "||" => Op::Or,
             "&&" => Op::And,
@@ -57,6 +65,8 @@ impl<S> From<S> for Op
             ">=" => Op::GreaterEquals,
             "<" => Op::Less,
             ">" => Op::Greater,
+            "|>" => Op::Pipe,
+            "//" => Op::DefinedOr,
             _ => Op::Custom(other),
         }
     }
@@ -69,7 +79,10 @@ impl Display for Op {
             Op::Plus => write!(fmt, "+"),
             Op::Minus => write!(fmt, "-"),
             Op::Splat => write!(fmt, "*"),
+            Op::DoubleSplat => write!(fmt, "**"),
             Op::FSlash => write!(fmt, "/"),
+            Op::Percent => write!(fmt, "%"),
+            Op::Question => write!(fmt, "?"),
             Op::Tilde => write!(fmt, "~"),
             Op::Or => write!(fmt, "||"),
             Op::And => write!(fmt, "&&"),
@@ -81,6 +94,8 @@ impl Display for Op {
             Op::GreaterEquals => write!(fmt, ">="),
             Op::Less => write!(fmt, "<"),
             Op::Greater => write!(fmt, ">"),
+            Op::Pipe => write!(fmt, "|>"),
+            Op::DefinedOr => write!(fmt, "//"),
             Op::Custom(o) => write!(fmt, "{}", o),
         }
     }
@@ -94,6 +109,7 @@ pub enum AssignOp {
     SplatEquals,
     FSlashEquals,
     TildeEquals,
+    DefinedOrEquals,
 }
 
 impl AssignOp {
@@ -105,6 +121,7 @@ impl AssignOp {
             "*=" => Some(AssignOp::SplatEquals),
             "/=" => Some(AssignOp::FSlashEquals),
             "~=" => Some(AssignOp::TildeEquals),
+            "//=" => Some(AssignOp::DefinedOrEquals),
             _ => None,
         }
     }
@@ -119,6 +136,7 @@ impl Display for AssignOp {
             AssignOp::SplatEquals => "*=",
             AssignOp::FSlashEquals => "/=",
             AssignOp::TildeEquals => "~=",
+            AssignOp::DefinedOrEquals => "//=",
         })
     }
 }
@@ -133,6 +151,7 @@ pub enum Token {
     StrLit(String),
     IntLit(String, usize),
     FloatLit(String),
+    RegexLit(String),
 
     //
     // User-defined names n stuff
@@ -141,22 +160,41 @@ pub enum Token {
     Comment,
     Variable(String),
     Bareword(String),
+    /// A reference to a named function, e.g. `&name` or `\name`.
+    FunctionRef(String),
 
     //
     // Keywords
     //
     IfKw,
+    UnlessKw,
     ElseKw,
     WhileKw,
+    UntilKw,
+    ForKw,
+    InKw,
     LoopKw,
     ContinueKw,
     BreakKw,
     ReturnKw,
     TrueKw,
     FalseKw,
+    UndefKw,
     FunKw,
     TypeKw,
+    EnumKw,
     SelfKw,
+    MatchKw,
+    OpKw,
+    UseKw,
+    NoKw,
+    ExportKw,
+    PubKw,
+    MyKw,
+    OurKw,
+    LocalKw,
+    BeginKw,
+    ConstKw,
 
     //
     // Symbols
@@ -166,6 +204,7 @@ pub enum Token {
     Op(Op),
     Comma,
     Colon,
+    FatArrow,
     LParen,
     RParen,
     LBrace,
@@ -231,25 +270,45 @@ impl Token {
                 _ => unreachable!(),
             },
             FloatLit(f) => f.to_string(),
+            RegexLit(r) => format!("m/{}/", r.replace('/', "\\/")),
             Comment => "#".to_string(),
-            Variable(ref s) => s.to_string(),
+            Variable(ref s) => format!("${}", s),
             Bareword(ref s) => s.to_string(),
+            FunctionRef(ref s) => format!("&{}", s),
             IfKw => "if".to_string(),
+            UnlessKw => "unless".to_string(),
             ElseKw => "else".to_string(),
             WhileKw => "while".to_string(),
+            UntilKw => "until".to_string(),
+            ForKw => "for".to_string(),
+            InKw => "in".to_string(),
             LoopKw => "loop".to_string(),
             ContinueKw => "continue".to_string(),
             BreakKw => "break".to_string(),
             ReturnKw => "return".to_string(),
             TrueKw => "true".to_string(),
             FalseKw => "false".to_string(),
+            UndefKw => "undef".to_string(),
             FunKw => "fun".to_string(),
             TypeKw => "type".to_string(),
+            EnumKw => "enum".to_string(),
             SelfKw => "self".to_string(),
+            MatchKw => "match".to_string(),
+            OpKw => "op".to_string(),
+            UseKw => "use".to_string(),
+            NoKw => "no".to_string(),
+            ExportKw => "export".to_string(),
+            PubKw => "pub".to_string(),
+            MyKw => "my".to_string(),
+            OurKw => "our".to_string(),
+            LocalKw => "local".to_string(),
+            BeginKw => "BEGIN".to_string(),
+            ConstKw => "const".to_string(),
             Op(s) => s.to_string(),
             AssignOp(s) => s.to_string(),
             Comma => ",".to_string(),
             Colon => ":".to_string(),
+            FatArrow => "=>".to_string(),
             LParen => "(".to_string(),
             RParen => ")".to_string(),
             LBrace => "{".to_string(),
@@ -269,25 +328,45 @@ impl Display for Token {
             StrLit(_) => write!(fmt, "string literal"),
             IntLit(_, _) => write!(fmt, "int literal"),
             FloatLit(_) => write!(fmt, "float literal"),
+            RegexLit(_) => write!(fmt, "regex literal"),
             Comment => write!(fmt, "comment"),
             Variable(ref s) => write!(fmt, "variable ${}", s),
             Bareword(ref s) => write!(fmt, "bareword {}", s),
+            FunctionRef(ref s) => write!(fmt, "function reference &{}", s),
             IfKw => write!(fmt, "if keyword"),
+            UnlessKw => write!(fmt, "unless keyword"),
             ElseKw => write!(fmt, "else keyword"),
             WhileKw => write!(fmt, "while keyword"),
+            UntilKw => write!(fmt, "until keyword"),
+            ForKw => write!(fmt, "for keyword"),
+            InKw => write!(fmt, "in keyword"),
             LoopKw => write!(fmt, "loop keyword"),
             ContinueKw => write!(fmt, "continue keyword"),
             BreakKw => write!(fmt, "break keyword"),
             ReturnKw => write!(fmt, "return keyword"),
             TrueKw => write!(fmt, "true keyword"),
             FalseKw => write!(fmt, "false keyword"),
+            UndefKw => write!(fmt, "undef keyword"),
             FunKw => write!(fmt, "fun keyword"),
             TypeKw => write!(fmt, "type keyword"),
+            EnumKw => write!(fmt, "enum keyword"),
             SelfKw => write!(fmt, "self keyword"),
+            MatchKw => write!(fmt, "match keyword"),
+            OpKw => write!(fmt, "op keyword"),
+            UseKw => write!(fmt, "use keyword"),
+            NoKw => write!(fmt, "no keyword"),
+            ExportKw => write!(fmt, "export keyword"),
+            PubKw => write!(fmt, "pub keyword"),
+            MyKw => write!(fmt, "my keyword"),
+            OurKw => write!(fmt, "our keyword"),
+            LocalKw => write!(fmt, "local keyword"),
+            BeginKw => write!(fmt, "BEGIN keyword"),
+            ConstKw => write!(fmt, "const keyword"),
             Op(s) =>  write!(fmt, "operator {}", s),
             AssignOp(s) =>  write!(fmt, "assignment operator {}", s),
             Comma => write!(fmt, "comma"),
             Colon => write!(fmt, "colon"),
+            FatArrow => write!(fmt, "fat arrow"),
             LParen => write!(fmt, "left paren"),
             RParen => write!(fmt, "right paren"),
             LBrace => write!(fmt, "left brace"),
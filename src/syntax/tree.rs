@@ -1,4 +1,9 @@
-use syntax::token::*;
+use std::fmt::{self, Formatter, Display};
+use syntax::{
+    Range,
+    OpInfo,
+    token::*,
+};
 
 macro_rules! token_is_lookahead {
     ($token:expr, $head:pat $(, $tail:pat)*) => {{
@@ -39,24 +44,53 @@ impl<'n> Default for SyntaxTree<'n> {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Stmt<'n> {
     Function(Function<'n>),
+    Operator(Operator<'n>),
     UserTy(UserTy<'n>),
     Expr(Expr<'n>),
     Assign(Expr<'n>, AssignOp, Expr<'n>),
     While(ConditionBlock<'n>),
+    /// A loop over the items of an array, e.g. `for ($k, $v) in $pairs { ... }`.
+    For {
+        target: Expr<'n>,
+        iterable: Expr<'n>,
+        block: Block<'n>,
+    },
     Loop(Block<'n>),
     If {
         if_block: ConditionBlock<'n>,
         elseif_blocks: Vec<ConditionBlock<'n>>,
         else_block: Option<Block<'n>>,
     },
-    Continue,
-    Break,
-    Return(Option<Expr<'n>>),
+    Match(Match<'n>),
+    /// A loop with a label that `break` and `continue` may refer to, e.g. `OUTER: loop { ... }`.
+    Labeled(String, Box<Stmt<'n>>),
+    /// A `continue` to the next iteration of the innermost loop, or of the loop with the given
+    /// label, along with the range of the `continue` keyword.
+    Continue(Range<'n>, Option<String>),
+    /// A `break` out of the innermost loop, or out of the loop with the given label, along with
+    /// the range of the `break` keyword.
+    Break(Range<'n>, Option<String>),
+    /// A `return` from the current function, along with the range of the `return` keyword.
+    Return(Range<'n>, Option<Expr<'n>>),
+    /// An import of a module, e.g. `use Some::Module;`.
+    Use(String),
+    /// A compiler pragma, e.g. `use strict;` or `no warnings unset;`.
+    Pragma(Pragma<'n>),
+    /// The list of names that a module makes visible to its importers, e.g. `export foo, Bar;`.
+    Export(Vec<String>),
+    /// A block that is run as soon as it's compiled, along with the range of the `BEGIN` keyword.
+    Begin(Range<'n>, Block<'n>),
+    /// A constant whose value is computed when it's compiled, e.g. `const LIMIT = 10`, along with
+    /// the range of the `const` keyword.
+    Const(Range<'n>, String, Expr<'n>),
 }
 
 impl<'n> Ast for Stmt<'n> {
     fn token_is_lookahead(token: &Token) -> bool {
-        Expr::token_is_lookahead(token) || token_is_lookahead!(token, Token::FunKw, Token::ReturnKw, Token::IfKw)
+        Expr::token_is_lookahead(token)
+            || token_is_lookahead!(token, Token::FunKw, Token::PubKw, Token::OpKw, Token::ReturnKw, Token::IfKw, Token::ForKw, Token::MatchKw,
+                                   Token::UnlessKw, Token::UseKw, Token::NoKw, Token::ExportKw, Token::BeginKw,
+                                   Token::ConstKw)
     }
 
     fn name() -> &'static str { "statement" }
@@ -64,23 +98,77 @@ impl<'n> Ast for Stmt<'n> {
 
 pub type Block<'n> = Vec<Stmt<'n>>;
 
+/// A compiler pragma, which changes a compiler setting until the end of the block or file that it
+/// is used in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pragma<'n> {
+    /// The range of the pragma's name.
+    pub range: Range<'n>,
+    /// Whether the setting is turned on with `use`, rather than turned off with `no`.
+    pub enabled: bool,
+    pub name: String,
+    /// The arguments following the name, e.g. `vars` in `use strict vars;`.
+    pub args: Vec<String>,
+}
+
+impl<'n> Display for Pragma<'n> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", if self.enabled { "use" } else { "no" }, self.name)?;
+        if !self.args.is_empty() {
+            write!(fmt, " {}", self.args.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct UserTy<'n> {
+    /// The range of the type's name.
+    pub range: Range<'n>,
     pub name: String,
     pub parents: Vec<String>,
     pub functions: Vec<Function<'n>>,
+    /// The operators that this type overloads.
+    pub operators: Vec<Operator<'n>>,
+    /// The variants of this type, if it was declared as an enum.
+    pub variants: Vec<Variant<'n>>,
 }
 
 impl<'n> Ast for UserTy<'n> {
     fn token_is_lookahead(token: &Token) -> bool {
-        token_is_lookahead!(token, Token::TypeKw)
+        token_is_lookahead!(token, Token::TypeKw, Token::EnumKw)
     }
 
     fn name() -> &'static str { "type definition" }
 }
 
+/// A single variant of an enum type, e.g. `Ok($value)` or `Pending`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant<'n> {
+    pub name: String,
+    pub fields: Vec<FunctionParam<'n>>,
+}
+
+impl<'n> Ast for Variant<'n> {
+    fn token_is_lookahead(token: &Token) -> bool {
+        matches!(token, Token::Bareword(_))
+    }
+
+    fn name() -> &'static str { "enum variant" }
+}
+
+/// Whether a function may be used from outside of the module or type that defines it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Function<'n> {
+    /// The range of the function's name.
+    pub range: Range<'n>,
+    pub visibility: Visibility,
     pub name: String,
     pub params: Vec<FunctionParam<'n>>,
     pub return_ty: Option<String>,
@@ -89,12 +177,31 @@ pub struct Function<'n> {
 
 impl<'n> Ast for Function<'n> {
     fn token_is_lookahead(token: &Token) -> bool {
-        token_is_lookahead!(token, Token::FunKw)
+        token_is_lookahead!(token, Token::FunKw, Token::PubKw)
     }
 
     fn name() -> &'static str { "function definition" }
 }
 
+/// A binary operator definition, e.g. `op <=> ($a, $b) precedence(comparison) assoc(left) { ... }`.
+///
+/// New operators must declare their precedence, and may declare their associativity. Operators that
+/// already exist may only be overloaded, and inherit their binding power.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operator<'n> {
+    pub op: Op,
+    pub info: Option<OpInfo>,
+    pub function: Function<'n>,
+}
+
+impl<'n> Ast for Operator<'n> {
+    fn token_is_lookahead(token: &Token) -> bool {
+        token_is_lookahead!(token, Token::OpKw)
+    }
+
+    fn name() -> &'static str { "operator definition" }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FunctionParam<'n> {
     SelfKw,
@@ -103,11 +210,15 @@ pub enum FunctionParam<'n> {
         ty: Option<String>,
         default: Option<Expr<'n>>,
     },
+    /// A trailing `*$rest` parameter, which collects any extra arguments into an array.
+    Variadic {
+        name: String,
+    },
 }
 
 impl<'n> Ast for FunctionParam<'n> {
     fn token_is_lookahead(token: &Token) -> bool {
-        matches!(token, Token::Variable(_)) || token == &Token::SelfKw
+        matches!(token, Token::Variable(_) | Token::Op(Op::Splat)) || token == &Token::SelfKw
     }
 
     fn name() -> &'static str { "function parameter" }
@@ -127,6 +238,78 @@ impl<'n> ConditionBlock<'n> {
 
 }
 
+/// A match over a value, testing each arm's pattern in order until one matches.
+///
+/// When used as an expression, every arm's body is a single expression statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'n> {
+    pub range: Range<'n>,
+    pub value: Box<Expr<'n>>,
+    pub arms: Vec<MatchArm<'n>>,
+}
+
+impl<'n> Ast for Match<'n> {
+    fn token_is_lookahead(token: &Token) -> bool {
+        token_is_lookahead!(token, Token::MatchKw)
+    }
+
+    fn name() -> &'static str { "match" }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm<'n> {
+    pub pattern: Pattern<'n>,
+    pub body: Block<'n>,
+}
+
+/// A pattern that a value may be tested against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern<'n> {
+    /// `_`, which matches anything.
+    Wildcard,
+
+    /// A variable, which matches anything and binds the matched value.
+    Variable(RangeToken<'n>),
+
+    /// A literal, which is smart-matched against the value.
+    Literal(RangeToken<'n>),
+
+    /// A type name or an enum variant without fields.
+    Bareword(RangeToken<'n>),
+
+    /// An enum variant with a pattern for each of its fields, e.g. `Ok($value)`.
+    Variant(RangeToken<'n>, Vec<Pattern<'n>>),
+
+    /// An array with a pattern for each of its items, e.g. `[1, $x]`.
+    Array(Vec<Pattern<'n>>),
+}
+
+impl<'n> Pattern<'n> {
+    pub fn canonicalize(&self) -> String {
+        match self {
+            Pattern::Wildcard => "_".to_string(),
+            | Pattern::Variable(t)
+            | Pattern::Literal(t)
+            | Pattern::Bareword(t) => t.token().canonicalize(),
+            Pattern::Variant(t, fields) => format!("{}({})", t.token().canonicalize(), canonicalize_list(fields, Pattern::canonicalize)),
+            Pattern::Array(items) => format!("[{}]", canonicalize_list(items, Pattern::canonicalize)),
+        }
+    }
+}
+
+impl<'n> Ast for Pattern<'n> {
+    fn token_is_lookahead(token: &Token) -> bool {
+        token_is_lookahead!(
+            token,
+            Token::StrLit(_), Token::IntLit(_, _), Token::FloatLit(_), Token::RegexLit(_),
+            Token::TrueKw, Token::FalseKw, Token::UndefKw,
+            Token::Variable(_), Token::Bareword(_), Token::LBracket
+        )
+    }
+
+    fn name() -> &'static str { "pattern" }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr<'n> {
     FunCall {
@@ -137,24 +320,153 @@ pub enum Expr<'n> {
         array: Box<Expr<'n>>,
         index: Box<Expr<'n>>,
     },
+    Array(Vec<Expr<'n>>),
+    /// `($a, $b)`, which is evaluated as an array, and may be assigned to item-by-item.
+    List(Vec<Expr<'n>>),
+    Match(Match<'n>),
+    /// `if $c { 1 } else { 2 }`, which evaluates to the final expression of the block that runs.
+    If {
+        if_block: Box<ConditionBlock<'n>>,
+        elseif_blocks: Vec<ConditionBlock<'n>>,
+        else_block: Block<'n>,
+    },
     Atom(RangeToken<'n>),
     Unary(Op, Box<Expr<'n>>),
     Binary(Box<Expr<'n>>, Op, Box<Expr<'n>>),
+    /// A chain of two or more comparisons, e.g. `$a < $b <= $c`, which is true when every
+    /// comparison in it is true.
+    Chain(Box<Expr<'n>>, Vec<(Op, Expr<'n>)>),
+    /// `condition ? then : else`
+    Ternary(Box<Expr<'n>>, Box<Expr<'n>>, Box<Expr<'n>>),
+    /// `name: value`, which passes an argument to a function call by its parameter name.
+    NamedArg(String, Box<Expr<'n>>),
+    /// `my $x`, `our ($a, $b)`, or `local $x`, which declares the variables that it wraps.
+    Declare(DeclKind, Box<Expr<'n>>),
+    /// `map { $_ * 2 } $list` or `grep { $_ > 2 } $list`, which runs the block for each item of
+    /// the list with the item in `$_`.
+    Map {
+        kind: MapKind,
+        range: Range<'n>,
+        block: Block<'n>,
+        list: Box<Expr<'n>>,
+    },
+}
+
+/// The kind of a `map`-style expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind {
+    /// `map`, which evaluates to the values of its block.
+    Map,
+    /// `grep`, which evaluates to the items that its block is true for.
+    Grep,
+}
+
+impl MapKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "map" => Some(MapKind::Map),
+            "grep" => Some(MapKind::Grep),
+            _ => None,
+        }
+    }
+}
+
+impl Display for MapKind {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            MapKind::Map => write!(fmt, "map"),
+            MapKind::Grep => write!(fmt, "grep"),
+        }
+    }
+}
+
+/// The kind of a variable declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclKind {
+    /// `my`, which declares a variable local to the current function, or to the file at the top
+    /// level.
+    My,
+    /// `our`, which declares a global variable that is shared with every function that declares
+    /// it.
+    Our,
+    /// `local`, which gives a global variable a new value until the end of the current block,
+    /// including inside of any functions called from that block.
+    Local,
+}
+
+impl Display for DeclKind {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            DeclKind::My => write!(fmt, "my"),
+            DeclKind::Our => write!(fmt, "our"),
+            DeclKind::Local => write!(fmt, "local"),
+        }
+    }
 }
 
 impl<'n> Expr<'n> {
+    /// Gets the logical negation of this expression, e.g. for the condition of `unless`.
+    pub fn negate(self) -> Self {
+        Expr::Unary(Op::Bang, Box::new(self))
+    }
+
     pub fn canonicalize(&self) -> String {
         match self {
+            Expr::FunCall { function, args } => format!("{}({})", function.canonicalize(), canonicalize_list(args, Expr::canonicalize)),
+            Expr::ArrayAccess { array, index } => format!("{}[{}]", array.canonicalize(), index.canonicalize()),
+            Expr::Array(items) => format!("[{}]", canonicalize_list(items, Expr::canonicalize)),
+            Expr::List(items) => format!("({})", canonicalize_list(items, Expr::canonicalize)),
+            Expr::Match(m) => {
+                let arms: Vec<_> = m.arms
+                    .iter()
+                    .map(|arm| {
+                        let body = match arm.body.as_slice() {
+                            [Stmt::Expr(expr)] => expr.canonicalize(),
+                            block => canonicalize_block(block),
+                        };
+                        format!("{} => {}", arm.pattern.canonicalize(), body)
+                    })
+                    .collect();
+                format!("(match {} {{ {} }})", m.value.canonicalize(), arms.join(", "))
+            }
+            Expr::If { if_block, elseif_blocks, else_block } => {
+                let mut canonical = format!("(if {} {}", if_block.condition.canonicalize(), canonicalize_block(&if_block.block));
+                for block in elseif_blocks {
+                    canonical += &format!(" else if {} {}", block.condition.canonicalize(), canonicalize_block(&block.block));
+                }
+                canonical + &format!(" else {})", canonicalize_block(else_block))
+            }
+            Expr::Atom(e) => e.token().canonicalize(),
+            Expr::Unary(op, expr) => format!("({}{})", op, expr.canonicalize()),
             Expr::Binary(lhs, op, rhs) => format!("({} {} {})", lhs.canonicalize(), op, rhs.canonicalize()),
-            Expr::Atom(e) => format!("{}", e.token()),
-            _ => unreachable!()
+            // `$a < $b <= $c` is canonically `(($a < $b) && ($b <= $c))`
+            Expr::Chain(first, rest) => {
+                let mut lhs = first.canonicalize();
+                let mut canonical = None;
+                for (op, rhs) in rest {
+                    let rhs = rhs.canonicalize();
+                    let comparison = format!("({} {} {})", lhs, op, rhs);
+                    canonical = Some(match canonical {
+                        Some(chain) => format!("({} && {})", chain, comparison),
+                        None => comparison,
+                    });
+                    lhs = rhs;
+                }
+                canonical.unwrap_or(lhs)
+            }
+            Expr::Ternary(condition, then_expr, else_expr) =>
+                format!("({} ? {} : {})", condition.canonicalize(), then_expr.canonicalize(), else_expr.canonicalize()),
+            Expr::NamedArg(name, value) => format!("{}: {}", name, value.canonicalize()),
+            Expr::Declare(kind, target) => format!("{} {}", kind, target.canonicalize()),
+            Expr::Map { kind, range: _, block, list } =>
+                format!("({} {} {})", kind, canonicalize_block(block), list.canonicalize()),
         }
     }
 
     pub fn token_is_atom_lookahead(token: &Token) -> bool {
         token_is_lookahead!(
             token,
-            Token::StrLit(_), Token::IntLit(_, _), Token::FloatLit(_),
+            Token::StrLit(_), Token::IntLit(_, _), Token::FloatLit(_), Token::RegexLit(_),
             Token::Variable(_), Token::Bareword(_), Token::SelfKw
         )
     }
@@ -164,14 +476,33 @@ impl<'n> Ast for Expr<'n> {
     fn token_is_lookahead(token: &Token) -> bool {
         token_is_lookahead!(
             token,
-            Token::StrLit(_), Token::IntLit(_, _), Token::FloatLit(_), Token::TrueKw, Token::FalseKw,
-            Token::Variable(_), Token::Bareword(_),
+            Token::StrLit(_), Token::IntLit(_, _), Token::FloatLit(_), Token::RegexLit(_),
+            Token::TrueKw, Token::FalseKw, Token::UndefKw,
+            Token::Variable(_), Token::Bareword(_), Token::FunctionRef(_),
             Token::Op(Op::Plus),
             Token::Op(Op::Minus),
             Token::Op(Op::Bang),
-            Token::LParen, Token::SelfKw
+            Token::LParen, Token::LBracket, Token::SelfKw, Token::MatchKw, Token::IfKw,
+            Token::MyKw, Token::OurKw, Token::LocalKw
         )
     }
 
     fn name() -> &'static str { "expression" }
 }
+
+/// Canonicalizes a block whose value is used, showing only its final expression.
+fn canonicalize_block(block: &[Stmt]) -> String {
+    match block {
+        [Stmt::Expr(expr)] => format!("{{ {} }}", expr.canonicalize()),
+        [.., Stmt::Expr(expr)] => format!("{{ ...; {} }}", expr.canonicalize()),
+        _ => "{ ... }".to_string(),
+    }
+}
+
+/// Canonicalizes each item of a list, separating them with commas.
+fn canonicalize_list<T>(items: &[T], canonicalize: impl Fn(&T) -> String) -> String {
+    let items: Vec<_> = items.iter()
+        .map(canonicalize)
+        .collect();
+    items.join(", ")
+}
@@ -1,4 +1,18 @@
-use vm::{Value, TySymbol, FunctionSymbol, VariableSymbol, Condition};
+use vm::{Value, TySymbol, FunctionSymbol, VariableSymbol, Condition, Pragmas};
+
+/// What the caller of a function expects it to return, which the function may check with
+/// `wantarray()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallContext {
+    /// The returned value is thrown away, e.g. `f()` on its own.
+    Void,
+    /// A single value is expected, e.g. `$x = f()` or `f() + 1`.
+    Scalar,
+    /// A list of values is expected, e.g. `my ($a, $b) = f()` or `for $x in f() { }`.
+    List,
+    /// The same context that the calling function was called in, e.g. `return f()`.
+    Caller,
+}
 
 #[derive(Debug, Clone)]
 pub enum Bc {
@@ -21,11 +35,44 @@ pub enum Bc {
     /// Stores a value into a variable slot.
     Store(VariableSymbol, Value),
 
-    /// Calls a function in the given slot with the given arguments.
-    Call(FunctionSymbol),
+    /// Saves the value of a global variable and unsets it, restoring the saved value once the
+    /// current block is exited.
+    Local(VariableSymbol),
+
+    /// Calls a function in the given slot with the given arguments, in the given call context.
+    Call(FunctionSymbol, CallContext),
+
+    /// Calls a function in the given slot, with every value pushed since the last args canary as
+    /// its arguments.
+    CallArgs(FunctionSymbol, CallContext),
+
+    /// Pops off a function ref, and calls it with every value pushed since the last args canary
+    /// as its arguments.
+    PopFunctionRefAndCall(CallContext),
+
+    /// Replaces the list on top of the stack with its number of items, if the current function
+    /// was called in scalar context.
+    ScalarList,
 
-    /// Pops off a function ref, and calls it.
-    PopFunctionRefAndCall,
+    /// Pops a value off of the stack, and appends it to the array below it on the stack.
+    AppendItem,
+
+    /// Pops a string off of the stack, then compiles and runs it as code, pushing the value that
+    /// it evaluates to.
+    ///
+    /// If the code can't be compiled or fails while running, its error message is stored in the
+    /// given variable and an unset value is pushed; otherwise, the variable is cleared.
+    Eval(VariableSymbol),
+
+    /// Pops an array off of the stack, and pushes each of its items in order.
+    Splat,
+
+    /// Pops an index and then an array off of the stack, and pushes the item of the array at that
+    /// index.
+    ///
+    /// Negative indices count back from the end of the array, and indices past either end of the
+    /// array push an unset value.
+    Index,
 
     /// Performs a comparison.
     Compare(Condition),
@@ -36,6 +83,10 @@ pub enum Bc {
     /// A block of bytecode to execute
     Block(Vec<Bc>),
 
+    /// A block of bytecode that runs with the given pragmas, which go back to what they were
+    /// once the block is exited.
+    PragmaBlock(Pragmas, Vec<Bc>),
+
     /// A block of bytecode that is only executed when the comparison flag is set.
     ConditionBlock(Vec<Bc>),
 
@@ -49,6 +100,31 @@ pub enum Bc {
     /// If set to 0, this will exit the current block.
     ExitBlock(usize),
 
+    /// Pops the given number of values off of the stack, and pushes a new array holding them.
+    MakeArray(usize),
+
+    /// Pops an array off of the stack, and pushes each of its items in order.
+    ///
+    /// If the value is not an array of exactly the given length, a runtime VM error is thrown.
+    Unpack(usize),
+
+    /// Pushes the next item of the array held in a symbol, advancing the index held in another
+    /// symbol.
+    ///
+    /// The comparison flag is set when there are no items left, and cleared otherwise.
+    NextItem {
+        array: VariableSymbol,
+        index: VariableSymbol,
+    },
+
+    /// Pops the given number of field values off of the stack, and pushes a new instance of the
+    /// given enum variant holding them.
+    MakeVariant {
+        ty: TySymbol,
+        tag: usize,
+        fields: usize,
+    },
+
     /// Checks a given symbol against a given type's predicate.
     CheckSymbolTy {
         symbol: VariableSymbol,
@@ -1,4 +1,5 @@
-use vm::Value;
+use std::cmp::Ordering;
+use vm::{Value, Pattern, Storage, Result, VariableSymbol};
 use syntax::token::Op;
 
 /// A condition that must be met, and can be checked.
@@ -19,6 +20,17 @@ pub enum Condition {
     ///
     /// `value ~~ true`
     Truthy(Value),
+
+    /// A condition that tests a value against a pattern, binding any variables in the pattern
+    /// when it matches.
+    Match(Value, Pattern),
+
+    /// A condition that checks whether a value is unset.
+    Unset(Value),
+
+    /// A condition that checks whether a parameter was left out of the function call, and should
+    /// get its default value.
+    Omitted(VariableSymbol),
 }
 
 /// A comparison for a `Condition`.
@@ -29,6 +41,7 @@ pub enum CompareOp {
     Equals,
     NotEquals,
     FuzzyEquals,
+    Divisible,
     Less,
     Greater,
     LessEquals,
@@ -42,14 +55,62 @@ impl CompareOp {
             Op::Or => Some(CompareOp::Or),
             Op::And => Some(CompareOp::And),
             Op::DoubleEquals => Some(CompareOp::Equals),
-            Op::DoublePercent => unimplemented!("VM: double percent comparison op"),
+            Op::DoublePercent => Some(CompareOp::Divisible),
             Op::DoubleTilde => Some(CompareOp::FuzzyEquals),
             Op::NotEquals => Some(CompareOp::NotEquals),
             Op::LessEquals => Some(CompareOp::LessEquals),
             Op::GreaterEquals => Some(CompareOp::GreaterEquals),
             Op::Less => Some(CompareOp::Less),
             Op::Greater => Some(CompareOp::Greater),
-            _ => panic!("cannot convert IR op {:?} to VM comparison op"),
+            _ => None,
         }
     }
+
+    /// Applies this comparison to the given values.
+    ///
+    /// Equality and ordering is numeric when both values are numeric, and falls back to comparing
+    /// the values themselves otherwise.
+    pub fn apply(&self, lhs: &Value, rhs: &Value, storage: &Storage) -> Result<bool> {
+        let lhs = storage.dereference(lhs)?;
+        let rhs = storage.dereference(rhs)?;
+        let numeric = match (lhs.to_number(storage), rhs.to_number(storage)) {
+            (Some(l), Some(r)) => Some((l, r)),
+            _ => None,
+        };
+        let result = match self {
+            CompareOp::Or => lhs.is_truthy(storage)? || rhs.is_truthy(storage)?,
+            CompareOp::And => lhs.is_truthy(storage)? && rhs.is_truthy(storage)?,
+            CompareOp::FuzzyEquals => lhs.smartmatch(rhs, storage)?,
+            CompareOp::Divisible => match numeric {
+                Some((l, r)) if r != 0.0 => l % r == 0.0,
+                Some(_) => return Err("division by zero".to_string()),
+                None => return Err(format!("cannot check divisibility of {} by {}", lhs.display_string(), rhs.display_string())),
+            },
+            CompareOp::Equals => numeric.map(|(l, r)| l == r).unwrap_or_else(|| lhs == rhs),
+            CompareOp::NotEquals => numeric.map(|(l, r)| l != r).unwrap_or_else(|| lhs != rhs),
+            | CompareOp::Less
+            | CompareOp::Greater
+            | CompareOp::LessEquals
+            | CompareOp::GreaterEquals => {
+                let ordering = match (numeric, lhs, rhs) {
+                    (Some((l, r)), _, _) => l.partial_cmp(&r),
+                    (None, Value::Str(l), Value::Str(r)) => l.partial_cmp(r),
+                    _ => None,
+                };
+                let ordering = if let Some(ordering) = ordering {
+                    ordering
+                } else {
+                    return Err(format!("cannot compare {} and {}", lhs.display_string(), rhs.display_string()));
+                };
+                match self {
+                    CompareOp::Less => ordering == Ordering::Less,
+                    CompareOp::Greater => ordering == Ordering::Greater,
+                    CompareOp::LessEquals => ordering != Ordering::Greater,
+                    CompareOp::GreaterEquals => ordering != Ordering::Less,
+                    _ => unreachable!(),
+                }
+            }
+        };
+        Ok(result)
+    }
 }
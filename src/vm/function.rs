@@ -8,6 +8,7 @@ use vm::{
     Bc,
     Ty,
     BuiltinTy,
+    Pragmas,
 };
 
 #[derive(EnumIsA, Debug, Clone, PartialEq)]
@@ -16,13 +17,15 @@ pub enum Function {
     // similar to how types are handled
     Builtin(BuiltinFunction),
     User(UserFunction),
+    Dispatch(DispatchFunction),
 }
 
 impl Function {
     pub fn symbol(&self) -> &FunctionSymbol {
         match self {
             Function::Builtin(b) => &b.symbol,
-            Function::User(u) => &u.symbol
+            Function::User(u) => &u.symbol,
+            Function::Dispatch(d) => &d.symbol,
         }
     }
 
@@ -30,6 +33,7 @@ impl Function {
         match self {
             Function::Builtin(b) => b.params.len(),
             Function::User(u) => u.params,
+            Function::Dispatch(d) => d.params,
         }
     }
 }
@@ -39,9 +43,15 @@ pub struct UserFunction {
     pub symbol: FunctionSymbol,
     pub name: String,
     pub params: usize,
+    /// Whether extra arguments past `params` are collected into an array in the next local.
+    pub variadic: bool,
+    /// The number of trailing parameters that are unset when they are left out of a call.
+    pub defaults: usize,
     pub return_ty: TySymbol,
     pub locals: Vec<VariableSymbol>,
     pub body: Vec<Bc>,
+    /// The pragmas that this function was compiled with.
+    pub pragmas: Pragmas,
 }
 
 impl PartialEq for UserFunction {
@@ -52,11 +62,37 @@ impl PartialEq for UserFunction {
     fn eq(&self, other: &Self) -> bool {
         self.symbol.eq(&other.symbol)
             && self.params.eq(&other.params)
+            && self.variadic.eq(&other.variadic)
+            && self.defaults.eq(&other.defaults)
             && self.return_ty.eq(&other.return_ty)
             && self.locals.eq(&other.locals)
+            && self.pragmas.eq(&other.pragmas)
     }
 }
 
+/// A function that several functions with the same name and number of parameters are defined as.
+///
+/// Calls are dispatched to the candidate whose parameter types most specifically match the
+/// arguments, which is only known at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatchFunction {
+    pub symbol: FunctionSymbol,
+    pub name: String,
+    pub params: usize,
+    pub variadic: bool,
+    pub defaults: usize,
+    pub candidates: Vec<DispatchCandidate>,
+}
+
+/// A function that a `DispatchFunction` may dispatch a call to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatchCandidate {
+    pub function: FunctionSymbol,
+
+    /// The type of each parameter, or `None` if the parameter accepts any value.
+    pub param_tys: Vec<Option<TySymbol>>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionParam {
     pub symbol: VariableSymbol,
@@ -96,16 +132,19 @@ impl Debug for BuiltinFunction {
 }
 
 mod functions {
-    use vm::{Value, FunctionSymbol, Storage, Result};
+    use std::io;
+    use vm::{Value, FunctionSymbol, Storage, Result, CallContext};
 
     pub fn println(storage: &mut Storage) -> Result<()> {
         let value = storage.value_stack
             .pop()
             .expect("no println stack item");
-        let value_string = match value {
+        let value_string = match storage.dereference(&value)? {
             | Value::FunctionRef(FunctionSymbol(f)) => format!("Function #{}", f),
-            | Value::Ref(s) => storage.load(s)?
-                .display_string(),
+            | Value::Unset => {
+                storage.warn_unset("in println");
+                String::new()
+            }
             | value => value.display_string(),
         };
         // TODO : use VM's stdout pointer
@@ -113,8 +152,50 @@ mod functions {
         Ok(())
     }
 
-    pub fn readln(_: &mut Storage) -> Result<()> {
-        // TODO : builtin function readln
+    pub fn readln(storage: &mut Storage) -> Result<()> {
+        // TODO : use VM's stdin pointer
+        let mut line = String::new();
+        let read = io::stdin()
+            .read_line(&mut line)
+            .map_err(|e| format!("could not read a line of input: {}", e))?;
+        // there's nothing left to read at the end of the input
+        let value = if read == 0 {
+            Value::Unset
+        } else {
+            let len = line.trim_end_matches(&['\r', '\n'][..]).len();
+            line.truncate(len);
+            Value::Str(line)
+        };
+        storage.value_stack
+            .push(value);
+        Ok(())
+    }
+
+    pub fn wantarray(storage: &mut Storage) -> Result<()> {
+        // the innermost context is the call to `wantarray()` itself
+        let context = storage.call_contexts
+            .iter()
+            .rev()
+            .nth(1)
+            .cloned()
+            .unwrap_or(CallContext::Void);
+        let value = match context {
+            CallContext::List => Value::Bool(true),
+            CallContext::Scalar => Value::Bool(false),
+            CallContext::Void | CallContext::Caller => Value::Unset,
+        };
+        storage.value_stack
+            .push(value);
+        Ok(())
+    }
+
+    pub fn defined(storage: &mut Storage) -> Result<()> {
+        let value = storage.value_stack
+            .pop()
+            .expect("no defined stack item");
+        let defined = *storage.dereference(&value)? != Value::Unset;
+        storage.value_stack
+            .push(Value::Bool(defined));
         Ok(())
     }
 
@@ -130,42 +211,75 @@ mod functions {
 }
 
 mod operators {
-    use vm::{Value, Storage, Result};
+    use vm::{Value, Storage, Result, CompareOp, Overflow};
 
     fn apply_arithmetic_pair(lhs: &Value, rhs: &Value, storage: &Storage,
-                  apply_ints: impl Fn(i64, i64) -> Value,
-                  apply_floats: impl Fn(f64, f64) -> Value) -> Result<Value>
+                  apply_ints: impl Fn(i64, i64, Overflow) -> Result<Value>,
+                  apply_floats: impl Fn(f64, f64) -> Result<Value>) -> Result<Value>
     {
+        if storage.pragmas.strict_numbers {
+            if let Some(s) = [lhs, rhs].iter().find(|value| value.is_str()) {
+                return Err(format!("cannot use string \"{}\" as a number, since `use strict numbers` is on",
+                                   s.display_string()));
+            }
+        }
         if let Some(lhs_int) = lhs.cast_to_int_no_float(storage) {
             if let Some(rhs_int) = rhs.cast_to_int_no_float(storage) {
-                Ok(apply_ints(lhs_int, rhs_int))
+                apply_ints(lhs_int, rhs_int, storage.pragmas.overflow)
             } else if let Some(rhs_float) = rhs.cast_to_float(storage) {
-                Ok(apply_floats(lhs_int as f64, rhs_float))
+                apply_floats(lhs_int as f64, rhs_float)
             } else {
-                return Err(format!("cannot cast RHS to an addable value: {}", rhs.display_string()));
+                Err(format!("cannot cast RHS to an arithmetic value: {}", rhs.display_string()))
             }
         } else if let Some(lhs_float) = lhs.cast_to_float(storage) {
-            // we don't need to check if rhs is int because we're going to be doing float addition
+            // we don't need to check if rhs is int because we're going to be doing float arithmetic
             // anyway
             if let Some(rhs_float) = rhs.cast_to_float(storage) {
-                Ok(apply_floats(lhs_float, rhs_float))
+                apply_floats(lhs_float, rhs_float)
             } else {
-                return Err(format!("cannot cast RHS to an addable value: {}", rhs.display_string()));
+                Err(format!("cannot cast RHS to an arithmetic value: {}", rhs.display_string()))
             }
         } else {
-            return Err(format!("cannot cast LHS to an addable value: {}", lhs.display_string()));
+            Err(format!("cannot cast LHS to an arithmetic value: {}", lhs.display_string()))
+        }
+    }
+
+    /// Gets the result of integer arithmetic, which is handled as the `overflow` pragma says if it
+    /// overflowed.
+    ///
+    /// # Arguments
+    /// `checked` - the result, or `None` if it overflowed.
+    /// `wrapped` - the result, wrapped around if it overflowed.
+    /// `float` - the result of doing the same arithmetic with floats.
+    /// `describe` - describes the arithmetic for the overflow error, e.g. "1 + 2".
+    fn overflowing(overflow: Overflow, checked: Option<i64>, wrapped: i64, float: f64,
+                   describe: impl FnOnce() -> String) -> Result<Value>
+    {
+        match (checked, overflow) {
+            (Some(i), _) => Ok(Value::Int(i)),
+            (None, Overflow::Wrap) => Ok(Value::Int(wrapped)),
+            (None, Overflow::Float) => Ok(Value::Float(float)),
+            (None, Overflow::Error) => Err(format!("integer overflow: {}", describe())),
         }
     }
 
+    /// Pops the operands of a binary operator off of the stack, in (LHS, RHS) order.
+    ///
+    /// Operands are pushed in the same order as function arguments, so the RHS is on top.
+    fn pop_operands(storage: &mut Storage) -> (Value, Value) {
+        let rhs = storage.value_stack
+            .pop()
+            .expect("no RHS operator stack item");
+        let lhs = storage.value_stack
+            .pop()
+            .expect("no LHS operator stack item");
+        (lhs, rhs)
+    }
+
     macro_rules! arithmetic_operator {
         ($name:ident, $apply_ints:expr, $apply_floats:expr) => {
             pub fn $name (storage: &mut Storage) -> Result<()> {
-                let lhs_owned = storage.value_stack
-                    .pop()
-                    .unwrap();
-                let rhs_owned = storage.value_stack
-                    .pop()
-                    .unwrap();
+                let (lhs_owned, rhs_owned) = pop_operands(storage);
                 let result_value = {
                     let lhs = storage.dereference(&lhs_owned)?;
                     let rhs = storage.dereference(&rhs_owned)?;
@@ -177,8 +291,147 @@ mod operators {
         }
     }
 
-    arithmetic_operator!(add, |i, j| Value::Int(i + j), |f, h| Value::Float(f + h));
-    arithmetic_operator!(sub, |i, j| Value::Int(i - j), |f, h| Value::Float(f - h));
+    macro_rules! comparison_operator {
+        ($name:ident, $compare_op:expr) => {
+            pub fn $name (storage: &mut Storage) -> Result<()> {
+                let (lhs, rhs) = pop_operands(storage);
+                let result = $compare_op.apply(&lhs, &rhs, storage)?;
+                storage.value_stack.push(Value::Bool(result));
+                Ok(())
+            }
+        }
+    }
+
+    arithmetic_operator!(add,
+        |i: i64, j, overflow| overflowing(overflow, i.checked_add(j), i.wrapping_add(j), i as f64 + j as f64,
+                                          || format!("{} + {}", i, j)),
+        |f, h| Ok(Value::Float(f + h)));
+    arithmetic_operator!(sub,
+        |i: i64, j, overflow| overflowing(overflow, i.checked_sub(j), i.wrapping_sub(j), i as f64 - j as f64,
+                                          || format!("{} - {}", i, j)),
+        |f, h| Ok(Value::Float(f - h)));
+    arithmetic_operator!(mul,
+        |i: i64, j, overflow| overflowing(overflow, i.checked_mul(j), i.wrapping_mul(j), i as f64 * j as f64,
+                                          || format!("{} * {}", i, j)),
+        |f, h| Ok(Value::Float(f * h)));
+    arithmetic_operator!(div,
+        |i: i64, j, overflow| if j == 0 {
+            Err("division by zero".to_string())
+        } else if i.wrapping_rem(j) == 0 {
+            // the smallest integer divided by -1 is the only division that overflows
+            overflowing(overflow, i.checked_div(j), i.wrapping_div(j), i as f64 / j as f64,
+                        || format!("{} / {}", i, j))
+        } else {
+            Ok(Value::Float(i as f64 / j as f64))
+        },
+        |f, h| if h == 0.0 {
+            Err("division by zero".to_string())
+        } else {
+            Ok(Value::Float(f / h))
+        });
+    arithmetic_operator!(rem,
+        |i: i64, j, _| if j == 0 {
+            Err("division by zero".to_string())
+        } else {
+            Ok(Value::Int(i.wrapping_rem(j)))
+        },
+        |f, h| if h == 0.0 {
+            Err("division by zero".to_string())
+        } else {
+            Ok(Value::Float(f % h))
+        });
+    arithmetic_operator!(pow,
+        |i: i64, j, overflow| if (0..=u32::MAX as i64).contains(&j) {
+            overflowing(overflow, i.checked_pow(j as u32), i.wrapping_pow(j as u32), (i as f64).powf(j as f64),
+                        || format!("{} ** {}", i, j))
+        } else {
+            Ok(Value::Float((i as f64).powf(j as f64)))
+        },
+        |f, h| Ok(Value::Float(f.powf(h))));
+
+    // `||` and `&&` are short-circuited by the compiler, so these are only called when a type
+    // overloads them but the LHS isn't of that type
+    comparison_operator!(or, CompareOp::Or);
+    comparison_operator!(and, CompareOp::And);
+    comparison_operator!(equals, CompareOp::Equals);
+    comparison_operator!(not_equals, CompareOp::NotEquals);
+    comparison_operator!(fuzzy_equals, CompareOp::FuzzyEquals);
+    comparison_operator!(divisible, CompareOp::Divisible);
+    comparison_operator!(less, CompareOp::Less);
+    comparison_operator!(greater, CompareOp::Greater);
+    comparison_operator!(less_equals, CompareOp::LessEquals);
+    comparison_operator!(greater_equals, CompareOp::GreaterEquals);
+
+    pub fn negate(storage: &mut Storage) -> Result<()> {
+        let value = storage.value_stack
+            .pop()
+            .expect("no unary operator stack item");
+        let result = match storage.dereference(&value)? {
+            Value::Int(i) => overflowing(storage.pragmas.overflow, i.checked_neg(), i.wrapping_neg(), -(*i as f64),
+                                         || format!("-{}", i))?,
+            Value::Float(f) => Value::Float(-f),
+            value => if let Some(f) = value.to_number(storage) {
+                Value::Float(-f)
+            } else {
+                return Err(format!("cannot negate non-numeric value: {}", value.display_string()));
+            },
+        };
+        storage.value_stack.push(result);
+        Ok(())
+    }
+
+    pub fn numify(storage: &mut Storage) -> Result<()> {
+        let value = storage.value_stack
+            .pop()
+            .expect("no unary operator stack item");
+        let result = match storage.dereference(&value)? {
+            value @ Value::Int(_) | value @ Value::Float(_) => value.clone(),
+            value => if let Some(f) = value.to_number(storage) {
+                Value::Float(f)
+            } else {
+                return Err(format!("cannot convert non-numeric value to a number: {}", value.display_string()));
+            },
+        };
+        storage.value_stack.push(result);
+        Ok(())
+    }
+
+    pub fn not(storage: &mut Storage) -> Result<()> {
+        let value = storage.value_stack
+            .pop()
+            .expect("no unary operator stack item");
+        let result = !value.is_truthy(storage)?;
+        storage.value_stack.push(Value::Bool(result));
+        Ok(())
+    }
+
+    pub fn concat(storage: &mut Storage) -> Result<()> {
+        let (lhs, rhs) = pop_operands(storage);
+        let result = {
+            let lhs = storage.dereference(&lhs)?;
+            let rhs = storage.dereference(&rhs)?;
+            if *lhs == Value::Unset || *rhs == Value::Unset {
+                storage.warn_unset("in concatenation");
+            }
+            format!("{}{}", lhs.display_string(), rhs.display_string())
+        };
+        storage.value_stack.push(Value::Str(result));
+        Ok(())
+    }
+
+    /// `$a // $b`, which is `$a` unless it's unset.
+    ///
+    /// `//` is short-circuited by the compiler, so this is only called when a type overloads it but
+    /// the LHS isn't of that type.
+    pub fn defined_or(storage: &mut Storage) -> Result<()> {
+        let (lhs, rhs) = pop_operands(storage);
+        let result = match storage.dereference(&lhs)? {
+            Value::Unset => storage.dereference(&rhs)?.clone(),
+            value => value.clone(),
+        };
+        storage.value_stack.push(result);
+        Ok(())
+    }
 }
 
 macro_rules! builtin {
@@ -203,12 +456,17 @@ macro_rules! builtin {
     };
 }
 
+/// The builtin functions that are passed the topic, `$_`, when they're called without arguments.
+pub const TOPIC_BUILTINS: &[&str] = &["println", "defined"];
+
 lazy_static! {
     pub static ref BUILTIN_FUNCTIONS: Vec<BuiltinFunction> = {
         vec![
             // BEGIN BUILTINS //////////////////////////////////////////////////
             builtin!(functions::println, println (BuiltinTy::Any) -> BuiltinTy::None),
             builtin!(functions::readln, readln () -> BuiltinTy::Str),
+            builtin!(functions::defined, defined (BuiltinTy::Any) -> BuiltinTy::Bool),
+            builtin!(functions::wantarray, wantarray () -> BuiltinTy::Any),
             builtin!(functions::is_string, "is-string", () -> BuiltinTy::Bool),
             // END BUILTINS ////////////////////////////////////////////////////
         ]
@@ -220,7 +478,32 @@ lazy_static! {
             // BEGIN BUILTIN OPERATORS /////////////////////////////////////////
             (Op::Plus, builtin!(operators::add, "+", (BuiltinTy::Int, BuiltinTy::Int) -> BuiltinTy::Any)),
             (Op::Minus, builtin!(operators::sub, "-", (BuiltinTy::Int, BuiltinTy::Int) -> BuiltinTy::Any)),
+            (Op::Splat, builtin!(operators::mul, "*", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::FSlash, builtin!(operators::div, "/", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::Percent, builtin!(operators::rem, "%", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::DoubleSplat, builtin!(operators::pow, "**", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::Tilde, builtin!(operators::concat, "~", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Str)),
+            (Op::Or, builtin!(operators::or, "||", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::DefinedOr, builtin!(operators::defined_or, "//", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::And, builtin!(operators::and, "&&", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::DoubleEquals, builtin!(operators::equals, "==", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::NotEquals, builtin!(operators::not_equals, "!=", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::DoubleTilde, builtin!(operators::fuzzy_equals, "~~", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::DoublePercent, builtin!(operators::divisible, "%%", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::Less, builtin!(operators::less, "<", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::Greater, builtin!(operators::greater, ">", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::LessEquals, builtin!(operators::less_equals, "<=", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
+            (Op::GreaterEquals, builtin!(operators::greater_equals, ">=", (BuiltinTy::Any, BuiltinTy::Any) -> BuiltinTy::Bool)),
             // END BUILTIN OPERATORS ///////////////////////////////////////////
         ]
     };
+
+    /// The list of built-in unary operator functions.
+    pub static ref BUILTIN_UNARY_OPERATORS: Vec<(Op, BuiltinFunction)> = {
+        vec![
+            (Op::Minus, builtin!(operators::negate, "-", (BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::Plus, builtin!(operators::numify, "+", (BuiltinTy::Any) -> BuiltinTy::Any)),
+            (Op::Bang, builtin!(operators::not, "!", (BuiltinTy::Any) -> BuiltinTy::Bool)),
+        ]
+    };
 }
@@ -1,5 +1,5 @@
 use std::mem;
-use compile::CompileUnit;
+use compile::{CompileState, CompileUnit, Warning};
 
 mod variable;
 mod value;
@@ -9,7 +9,9 @@ mod function;
 mod ty;
 mod bc;
 mod condition;
+mod pattern;
 mod storage;
+mod pragma;
 mod error;
 
 pub use self::variable::*;
@@ -20,7 +22,9 @@ pub use self::function::*;
 pub use self::ty::*;
 pub use self::bc::*;
 pub use self::condition::*;
+pub use self::pattern::*;
 pub use self::storage::*;
+pub use self::pragma::*;
 pub use self::error::*;
 
 pub type StackIndex = usize;
@@ -45,6 +49,17 @@ pub struct Vm {
 
     /// The direction to jump when jumping in a block.
     block_jump_top: bool,
+
+    /// Whether the currently executing function is returning.
+    returning: bool,
+
+    /// The compiler that the running program was compiled with, which compiles code passed to
+    /// `eval`.
+    compiler: Option<Box<CompileState>>,
+
+    /// The warnings emitted while compiling code passed to `eval`, which are reported by whoever
+    /// is running this VM.
+    warnings: Vec<Warning>,
 }
 
 impl Vm {
@@ -55,14 +70,49 @@ impl Vm {
             compare_flag: false,
             block_jump_depth: 0,
             block_jump_top: false,
+            returning: false,
+            compiler: None,
+            warnings: vec![],
         }
     }
 
+    /// Gives this VM the compiler that the program it runs was compiled with, so that the program
+    /// may `eval` code.
+    pub fn set_compiler(&mut self, compiler: CompileState) {
+        self.compiler = Some(Box::new(compiler));
+    }
+
+    /// Takes back the compiler that was given to this VM, along with everything that `eval`
+    /// compiled with it.
+    pub fn take_compiler(&mut self) -> Option<CompileState> {
+        self.compiler
+            .take()
+            .map(|compiler| *compiler)
+    }
+
+    /// Gets the compiler that was given to this VM.
+    pub fn compiler_mut(&mut self) -> Option<&mut CompileState> {
+        self.compiler
+            .as_deref_mut()
+    }
+
+    /// Takes the warnings that have been emitted while compiling code passed to `eval`.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::take(&mut self.warnings)
+    }
+
     pub fn reset(&mut self) {
         self.call_stack.clear();
         self.compare_flag = false;
         self.block_jump_depth = 0;
         self.block_jump_top = false;
+        self.returning = false;
+    }
+
+    /// Drops the program that this VM has run, so that it may run another from scratch.
+    pub fn unload(&mut self) {
+        self.reset();
+        self.storage = Storage::new();
     }
 
     /// Starts this VM a-runnin'.
@@ -73,39 +123,122 @@ impl Vm {
         self.run_block(&body)
     }
 
+    /// Gets the values of the global variables, as they were left by the code that has run.
+    pub fn global_values(&self) -> Vec<(VariableSymbol, Value)> {
+        self.storage
+            .scope_stack
+            .first()
+            .map(|globals| globals.iter()
+                 .map(|(symbol, value)| (symbol, value.clone()))
+                 .collect())
+            .unwrap_or_default()
+    }
+
     /// Feeds a new compile unit into this VM, merging it with the current state.
     pub fn repl_launch(&mut self, compile_unit: CompileUnit) -> Result<Option<Value>> {
-        let global_slots: Vec<_> = compile_unit.globals.clone();
-        let unset_global_slots = vec!(Value::Unset; global_slots.len());
         let mut prev_storage = mem::replace(&mut self.storage, compile_unit.into());
-        let prev_scope = if prev_storage.scope_stack.len() == 0 {
-            Scope::new(vec![], vec![])
-        } else {
-            prev_storage
+        assert!(prev_storage.scope_stack.len() <= 1);
+        // globals that were already defined keep the values they had
+        if let Some(prev_scope) = prev_storage.scope_stack.pop() {
+            self.storage
                 .scope_stack
-                .remove(0)
-        };
-        assert!(prev_storage.scope_stack.is_empty());
-        assert!(self.storage.scope_stack.is_empty());
-        let mut updated_scope = Scope::new(global_slots, unset_global_slots);
-        updated_scope.update(prev_scope);
-        self.storage
-            .scope_stack
-            .push(updated_scope);
+                .first_mut()
+                .expect("missing global scope")
+                .update(prev_scope);
+        }
         let body = self.storage.body.clone();
+        self.returning = false;
         let run_result = self.run_block(&body);
-        let stack_top = self.storage.value_stack.pop();
+        run_result?;
+        // the value may refer to a variable or constant that only this VM knows about
+        let stack_top = match self.storage.value_stack.pop() {
+            Some(value) => Some(self.dereference(&value)?.clone()),
+            None => None,
+        };
         self.storage.value_stack.clear();
-        run_result.map(|_| stack_top)
+        Ok(stack_top)
+    }
+
+    /// Compiles and runs code passed to `eval` in the context of the running program.
+    ///
+    /// Functions, types, and globals that the code defines are kept for code passed to `eval`
+    /// later on; the running program was compiled before them, so it can't refer to them by name.
+    /// If the code fails to compile, nothing that it defines is kept. If it fails while running,
+    /// the stacks are unwound to where they were before the code ran, but like Perl, everything the
+    /// code did before it failed stays done, e.g. globals that it assigned to keep their new values.
+    ///
+    /// # Returns
+    /// The value that the code evaluated to, which is unset if it didn't evaluate to anything.
+    fn eval(&mut self, code: &str) -> Result<Value> {
+        let compiler = self.compiler
+            .as_mut()
+            .ok_or_else(|| "`eval` may not be used here".to_string())?;
+        let (compile_unit, mut warnings) = compiler.compile_eval(code)?;
+        self.warnings.append(&mut warnings);
+
+        let CompileUnit { body, functions, tys, variables, globals, global_values, constants, pragmas, } = compile_unit;
+        self.storage.functions = functions;
+        self.storage.constants = constants;
+        self.storage.tys = tys;
+        self.storage.variables = variables;
+        // globals that were already defined keep the values they have
+        let global_scope = self.storage
+            .scope_stack
+            .first_mut()
+            .expect("missing global scope");
+        let prev_scope = mem::replace(global_scope, Scope::new(globals, global_values));
+        global_scope.update(prev_scope);
+
+        let value_height = self.storage.value_stack.len();
+        let call_height = self.call_stack.len();
+        let context_height = self.storage.call_contexts.len();
+        let scope_height = self.storage.scope_stack.len();
+        let block_jump_top = self.block_jump_top;
+        let caller_pragmas = mem::replace(&mut self.storage.pragmas, pragmas);
+        let result = self.run_block(&body);
+        self.storage.pragmas = caller_pragmas;
+        self.returning = false;
+        self.block_jump_depth = 0;
+        self.block_jump_top = block_jump_top;
+        self.call_stack.truncate(call_height);
+        self.storage.call_contexts.truncate(context_height);
+        self.storage.scope_stack.truncate(scope_height);
+        let value = if self.storage.value_stack.len() > value_height {
+            let value = self.pop_stack();
+            self.dereference(&value)?.clone()
+        } else {
+            Value::Unset
+        };
+        self.storage.value_stack.truncate(value_height);
+        result.map(|_| value)
     }
 
     /// Runs the function on top of the call stack.
-    fn run_current_function(&mut self) -> Result<()> {
+    ///
+    /// If the number of arguments on the stack is not given, it is assumed to be the number of
+    /// parameters that the function takes.
+    fn run_current_function(&mut self, argc: Option<usize>) -> Result<()> {
         let current_function = self.current_function().clone();
-        self.run_function(current_function)
+        self.run_function(current_function, argc)
     }
 
-    fn run_function(&mut self, function: Function) -> Result<()> {
+    fn run_function(&mut self, function: Function, argc: Option<usize>) -> Result<()> {
+        let params = function.param_count();
+        let argc = argc.unwrap_or(params);
+        let (variadic, defaults) = match &function {
+            Function::User(f) => (f.variadic, f.defaults),
+            Function::Dispatch(f) => (f.variadic, f.defaults),
+            Function::Builtin(_) => (false, 0),
+        };
+        if argc < params - defaults || (argc > params && !variadic) {
+            let expected = match (defaults, variadic) {
+                (0, false) => params.to_string(),
+                (_, false) => format!("{} to {}", params - defaults, params),
+                (_, true) => format!("at least {}", params - defaults),
+            };
+            return Err(self.err(format!("function `{}` takes {} argument(s), but got {}",
+                                        function.name(), expected, argc)));
+        }
         match function {
             Function::User(function) => {
                 // TODO : Consider changing params to be just a number, and params are added to the
@@ -122,15 +255,31 @@ impl Vm {
                 //        Params is a number now. Minor changes involved. I remember there was a
                 //        deeper issue with this but I don't recall off the top of my head and I'm
                 //        too tired to read the code.
-                let split_off_at = self.storage.value_stack.len() - function.params;
+                let split_off_at = self.storage.value_stack.len() - argc;
                 let mut args = self.storage
                     .value_stack
                     .split_off(split_off_at);
-                args.append(&mut vec!(Value::Unset; function.locals.len() - function.params));
+                // parameters past the number of arguments were left out, and get their defaults
+                while args.len() < function.params {
+                    args.push(Value::Omitted);
+                }
+                if function.variadic {
+                    let mut rest = vec![];
+                    for value in args.split_off(function.params) {
+                        rest.push(self.dereference(&value)?.clone());
+                    }
+                    args.push(Value::Array(rest));
+                }
+                let unset_locals = function.locals.len() - args.len();
+                args.append(&mut vec!(Value::Unset; unset_locals));
                 self.storage
                     .scope_stack
                     .push(Scope::new(function.locals.clone(), args));
+                // functions run with the pragmas they were declared with, not their caller's
+                let caller_pragmas = mem::replace(&mut self.storage.pragmas, function.pragmas);
                 self.run_block(&function.body)?;
+                self.storage.pragmas = caller_pragmas;
+                self.returning = false;
                 self.storage.scope_stack.pop()
                     .expect("uneven scope stack");
                 Ok(())
@@ -138,25 +287,126 @@ impl Vm {
             Function::Builtin(function) => {
                 (function.function)(&mut self.storage)
             }
+            Function::Dispatch(function) => {
+                let candidate = self.dispatch(&function, argc)?;
+                // the chosen candidate is called just as the dispatch function was
+                self.call_with_args(candidate, Some(argc), CallContext::Caller)
+            }
         }
     }
 
+    /// Picks the function that a call to a dispatch function goes to, by the types of the
+    /// arguments on top of the stack.
+    ///
+    /// Each argument is scored by how closely it matches the type of its parameter. The chosen
+    /// candidate must score at least as well as every other applicable candidate on every
+    /// argument; otherwise, the call is ambiguous.
+    fn dispatch(&mut self, function: &DispatchFunction, argc: usize) -> Result<FunctionSymbol> {
+        let first_arg = self.storage.value_stack.len() - argc;
+        let args: Vec<Value> = (0 .. function.params)
+            .map(|index| self.storage.value_stack.get(first_arg + index)
+                 .filter(|_| index < argc)
+                 .cloned()
+                 .unwrap_or(Value::Omitted))
+            .collect();
+
+        let mut applicable: Vec<(&DispatchCandidate, Vec<usize>)> = vec![];
+        'candidates: for candidate in &function.candidates {
+            let mut scores = vec![];
+            for (arg, ty) in args.iter().zip(candidate.param_tys.iter()) {
+                match self.dispatch_score(arg, *ty)? {
+                    Some(score) => scores.push(score),
+                    None => continue 'candidates,
+                }
+            }
+            applicable.push((candidate, scores));
+        }
+
+        let best: Vec<&DispatchCandidate> = applicable.iter()
+            .filter(|(_, scores)| applicable.iter()
+                    .all(|(_, other)| scores.iter().zip(other.iter()).all(|(score, other)| score >= other)))
+            .map(|(candidate, _)| *candidate)
+            .collect();
+        match best.as_slice() {
+            [candidate] => Ok(candidate.function),
+            [] if applicable.is_empty() => {
+                let mut arg_strings = vec![];
+                for arg in &args {
+                    arg_strings.push(self.dereference(arg)?.display_string());
+                }
+                let candidates: Vec<_> = function.candidates
+                    .iter()
+                    .collect();
+                Err(self.err(format!("no candidate of function `{}` accepts the arguments ({}); candidates are: {}",
+                                     function.name, arg_strings.join(", "),
+                                     self.candidate_list(&function.name, &candidates))))
+            }
+            _ => {
+                let candidates: Vec<_> = applicable.iter()
+                    .map(|(candidate, _)| *candidate)
+                    .collect();
+                Err(self.err(format!("call to function `{}` is ambiguous between candidates: {}",
+                                     function.name, self.candidate_list(&function.name, &candidates))))
+            }
+        }
+    }
+
+    /// Scores how closely an argument matches the type of its parameter when dispatching a call.
+    ///
+    /// # Returns
+    /// `None` if the argument is not of the parameter's type. Otherwise, parameters of any type
+    /// and arguments that were left out score lowest, arguments that can be converted to the
+    /// parameter's type score higher, and arguments that already are the parameter's type score
+    /// highest.
+    fn dispatch_score(&mut self, arg: &Value, ty: Option<TySymbol>) -> Result<Option<usize>> {
+        let ty = match ty {
+            Some(ty) if *arg != Value::Unset && *arg != Value::Omitted => self.storage.get_ty(ty).clone(),
+            _ => return Ok(Some(0)),
+        };
+        let score = match ty {
+            Ty::Builtin(BuiltinTy::Any, _) => Some(0),
+            Ty::Builtin(builtin, _) => match arg.cast_to_builtin(builtin, &self.storage) {
+                CastResult::SelfValid => Some(2),
+                CastResult::Value(_) => Some(1),
+                CastResult::Invalid => None,
+            },
+            ty => if self.run_ty_predicate(ty, arg.clone())? { Some(2) } else { None },
+        };
+        Ok(score)
+    }
+
+    /// Lists the signatures of the given dispatch candidates, e.g. `area(Square), area(Circle)`.
+    fn candidate_list(&self, name: &str, candidates: &[&DispatchCandidate]) -> String {
+        let signatures: Vec<_> = candidates.iter()
+            .map(|candidate| {
+                let tys: Vec<_> = candidate.param_tys
+                    .iter()
+                    .map(|ty| ty.map(|ty| self.storage.ty_name(ty)).unwrap_or("Any"))
+                    .collect();
+                format!("{}({})", name, tys.join(", "))
+            })
+            .collect();
+        signatures.join(", ")
+    }
+
     /// Runs a block of bytecode.
     ///
     /// This is the primary execution loop.
     fn run_block(&mut self, block: &[Bc]) -> Result<()> {
+        // globals that were given new values with `local` are restored however the block is
+        // exited, including by errors
+        let mut saved = vec![];
+        let result = self.run_block_saving(block, &mut saved);
+        let restored = self.restore_locals(saved);
+        result.and(restored)
+    }
+
+    /// Runs a block, keeping track of the values of globals that it saves with `Bc::Local`.
+    fn run_block_saving(&mut self, block: &[Bc], saved: &mut Vec<(VariableSymbol, Value)>) -> Result<()> {
         let mut pc = 0;
         while pc < block.len() {
             let ref bc = block[pc];
             pc += 1;
-            // jump out of the current block if requested
-            if self.block_jump_depth > 0 {
-                self.block_jump_depth -= 1;
-                break;
-            } else if self.block_jump_top {
-                self.block_jump_top = false;
-                pc = 0;
-            }
             // TODO : investigate optimization:
             // * Have a "is_conditional()" check at the very start, and jump if condition is not
             //   matched?
@@ -180,8 +430,52 @@ impl Vm {
                     self.store(*symbol, value)?;
                 }
                 Bc::Store(sym, val) => self.store(*sym, val.clone())?,
-                Bc::Call(sym) => self.call(*sym)?,
-                Bc::PopFunctionRefAndCall => {
+                Bc::Local(sym) => {
+                    let value = self.load(*sym)?
+                        .clone();
+                    saved.push((*sym, value));
+                    self.store(*sym, Value::Unset)?;
+                }
+                Bc::Call(sym, context) => self.call(*sym, *context)?,
+                Bc::CallArgs(sym, context) => {
+                    let argc = self.pop_args_canary();
+                    self.call_with_args(*sym, Some(argc), *context)?;
+                }
+                Bc::Splat => {
+                    let value = self.pop_stack();
+                    let items = if let Value::Array(items) = self.dereference(&value)? {
+                        items.clone()
+                    } else {
+                        return Err(self.err(format!("cannot splat {} into function arguments, since it is not an array",
+                                                    self.value_name(&value))));
+                    };
+                    for item in items {
+                        self.push_stack(item);
+                    }
+                }
+                Bc::Index => {
+                    let index = self.pop_stack();
+                    let array = self.pop_stack();
+                    let index = match self.dereference(&index)? {
+                        Value::Int(index) => *index,
+                        _ => {
+                            return Err(self.err(format!("cannot index an array with {}, since it is not an integer",
+                                                        self.value_name(&index))));
+                        }
+                    };
+                    let items = if let Value::Array(items) = self.dereference(&array)? {
+                        items
+                    } else {
+                        return Err(self.err(format!("cannot index {}, since it is not an array",
+                                                    self.value_name(&array))));
+                    };
+                    let position = if index < 0 { items.len() as i64 + index } else { index };
+                    let item = if position < 0 { None } else { items.get(position as usize) };
+                    let item = item.cloned()
+                        .unwrap_or(Value::Unset);
+                    self.push_stack(item);
+                }
+                Bc::PopFunctionRefAndCall(context) => {
                     let sym = {
                         let value = self.pop_stack();
                         let function_ref = self.dereference(&value)?;
@@ -193,52 +487,178 @@ impl Vm {
                     };
                     let canary = self.pop_stack();
                     assert_eq!(canary, Value::FunctionRefCanary, "function ref canary errror; got {:?} instead", canary);
-                    self.call(sym)?;
+                    let argc = self.pop_args_canary();
+                    self.call_with_args(sym, Some(argc), *context)?;
                 }
+                Bc::ScalarList => if self.storage.call_context() == CallContext::Scalar {
+                    let value = self.pop_stack();
+                    let count = match self.dereference(&value)? {
+                        Value::Array(items) => items.len() as i64,
+                        value => {
+                            return Err(self.err(format!("cannot count {} as a list, since it is not an array",
+                                                        value.display_string())));
+                        }
+                    };
+                    self.push_stack(Value::Int(count));
+                },
                 Bc::Compare(Condition::Always) => { self.compare_flag = true; },
                 Bc::Compare(Condition::Never) => { self.compare_flag = false; },
-                Bc::Compare(Condition::Truthy(_value)) => { unimplemented!("truthy compare bc op") }
-                Bc::Compare(Condition::Compare(_lhs, _op, _rhs)) => { unimplemented!("comparison bc op") }
+                Bc::Compare(Condition::Truthy(value)) => {
+                    self.compare_flag = value.is_truthy(&self.storage)?;
+                }
+                Bc::Compare(Condition::Compare(lhs, op, rhs)) => {
+                    self.compare_flag = op.apply(lhs, rhs, &self.storage)?;
+                }
+                Bc::Compare(Condition::Unset(value)) => {
+                    self.compare_flag = *self.dereference(value)? == Value::Unset;
+                }
+                Bc::Compare(Condition::Omitted(symbol)) => {
+                    let value = self.storage.current_scope().try_get(*symbol);
+                    self.compare_flag = matches!(value, Some(Value::Omitted));
+                }
+                Bc::Compare(Condition::Match(value, pattern)) => {
+                    let mut bindings = vec![];
+                    self.compare_flag = self.match_pattern(value, pattern, &mut bindings)?;
+                    // variables are only bound when the entire pattern matches
+                    if self.compare_flag {
+                        for (symbol, value) in bindings {
+                            self.store(symbol, value)?;
+                        }
+                    }
+                }
                 Bc::Ret(r) => {
                     if let Some(v) = r {
-                        self.push_stack(v.clone());
+                        // returned values must be dereferenced, since they may point at locals
+                        let v = self.dereference(v)?
+                            .clone();
+                        self.push_stack(v);
                     }
-                    return Ok(());
+                    self.returning = true;
                 },
                 Bc::ConditionBlock(b) => {
-                    if !self.compare_flag {
-                        continue;
-                    }
-                    // FIXME: reduce repetition without multiple if-statements
-                    let start_depth = self.call_stack.len();
-                    self.run_block(b)?;
-                    let end_depth = self.call_stack.len();
-                    if start_depth != end_depth {
-                        return Ok(());
+                    if self.compare_flag {
+                        self.run_block(b)?;
                     }
                 }
-                Bc::Block(b) => {
-                    let start_depth = self.call_stack.len();
-                    self.run_block(b)?;
-                    let end_depth = self.call_stack.len();
-                    // if the start and end call stack depth are not equal, then the inner block
-                    // jumped out and returned
-                    if start_depth != end_depth {
-                        return Ok(());
-                    }
+                Bc::Block(b) => self.run_block(b)?,
+                Bc::PragmaBlock(pragmas, b) => {
+                    let outer_pragmas = mem::replace(&mut self.storage.pragmas, *pragmas);
+                    let result = self.run_block(b);
+                    self.storage.pragmas = outer_pragmas;
+                    result?;
                 }
                 Bc::JumpBlockTop(n) => {
                     self.block_jump_top = true;
-                    self.block_jump_depth += n;
+                    self.block_jump_depth = n + 1;
                 }
                 Bc::ExitBlock(n) => {
                     self.block_jump_top = false;
-                    self.block_jump_depth += n;
+                    self.block_jump_depth = n + 1;
+                }
+                Bc::MakeArray(len) => {
+                    let split_off_at = self.storage.value_stack.len() - len;
+                    let item_values = self.storage
+                        .value_stack
+                        .split_off(split_off_at);
+                    let mut items = vec![];
+                    for value in item_values {
+                        items.push(self.dereference(&value)?.clone());
+                    }
+                    self.push_stack(Value::Array(items));
+                }
+                Bc::Eval(error) => {
+                    let value = self.pop_stack();
+                    let code = match self.dereference(&value)? {
+                        Value::Str(code) => code.clone(),
+                        value => return Err(self.err(format!("cannot eval {}, since it is not a string",
+                                                             value.display_string()))),
+                    };
+                    let value = match self.eval(&code) {
+                        Ok(value) => {
+                            self.store(*error, Value::Str(String::new()))?;
+                            value
+                        }
+                        Err(e) => {
+                            self.store(*error, Value::Str(e))?;
+                            Value::Unset
+                        }
+                    };
+                    self.push_stack(value);
+                }
+                Bc::AppendItem => {
+                    let value = self.pop_stack();
+                    let item = self.dereference(&value)?
+                        .clone();
+                    match self.storage.value_stack.last_mut() {
+                        Some(Value::Array(items)) => items.push(item),
+                        top => panic!("non-array value below appended item: {:?}", top),
+                    }
+                }
+                Bc::Unpack(len) => {
+                    let value = self.pop_stack();
+                    let items = match self.dereference(&value)? {
+                        Value::Array(items) if items.len() == *len => items.clone(),
+                        Value::Array(items) => {
+                            return Err(self.err(format!("cannot assign an array of {} item(s) to a list of {} item(s)",
+                                                        items.len(), len)));
+                        }
+                        _ => {
+                            return Err(self.err(format!("cannot assign {} to a list of {} item(s), since it is not an array",
+                                                        self.value_name(&value), len)));
+                        }
+                    };
+                    for item in items {
+                        self.push_stack(item);
+                    }
+                }
+                Bc::NextItem { array, index } => {
+                    let position = match self.load(*index)? {
+                        Value::Int(position) => *position as usize,
+                        value => {
+                            return Err(self.err(format!("loop index {} is not an integer",
+                                                        value.display_string())));
+                        }
+                    };
+                    let item = match self.load(*array)? {
+                        Value::Array(items) => items.get(position).cloned(),
+                        value => {
+                            return Err(self.err(format!("cannot loop over {}, since it is not an array",
+                                                        value.display_string())));
+                        }
+                    };
+                    self.compare_flag = item.is_none();
+                    if let Some(item) = item {
+                        self.store(*index, Value::Int(position as i64 + 1))?;
+                        self.push_stack(item);
+                    }
+                }
+                Bc::MakeVariant { ty, tag, fields } => {
+                    let split_off_at = self.storage.value_stack.len() - fields;
+                    let field_values = self.storage
+                        .value_stack
+                        .split_off(split_off_at);
+                    // fields must be dereferenced, since they usually point at the constructor's
+                    // locals
+                    let mut deref_fields = vec![];
+                    for value in field_values {
+                        deref_fields.push(self.dereference(&value)?.clone());
+                    }
+                    let name = if let Ty::User(user_ty) = self.storage.get_ty(*ty) {
+                        user_ty.variants[*tag].name.clone()
+                    } else {
+                        panic!("attempted to construct a variant of non-user type {:?}", ty)
+                    };
+                    self.push_stack(Value::Variant(VariantValue {
+                        ty: *ty,
+                        tag: *tag,
+                        name,
+                        fields: deref_fields,
+                    }));
                 }
                 Bc::CheckSymbolTy { symbol, ty: ty_symbol } => {
                     let ty = self.storage.get_ty(*ty_symbol)
                         .clone();
-                    let predicate_matches = self.run_ty_predicate(ty, *symbol)?;
+                    let predicate_matches = self.run_ty_predicate(ty, Value::Ref(*symbol))?;
                     if !predicate_matches {
                         return Err(self.err(format!("predicate error: `${}` (value: {}) is not a `{}`",
                             self.storage.variable_name(*symbol),
@@ -248,22 +668,97 @@ impl Vm {
                     // if everything's okay, continue
                 }
             }
+
+            // a function return unwinds every block in the function
+            if self.returning {
+                return Ok(());
+            }
+            // jump out of this block (and possibly back to the top of an outer block) if requested
+            if self.block_jump_depth > 0 {
+                self.block_jump_depth -= 1;
+                if self.block_jump_depth == 0 && self.block_jump_top {
+                    // each time a block is restarted, e.g. for a loop, is its own scope for `local`
+                    self.restore_locals(mem::take(saved))?;
+                    self.block_jump_top = false;
+                    pc = 0;
+                } else {
+                    return Ok(());
+                }
+            }
         }
         Ok(())
     }
 
-    fn run_ty_predicate(&mut self, ty: Ty, symbol: VariableSymbol) -> Result<bool> {
-        let val = Value::Ref(symbol);
+    /// Restores the values of globals that were saved by `Bc::Local`, in the reverse order that
+    /// they were saved.
+    fn restore_locals(&mut self, saved: Vec<(VariableSymbol, Value)>) -> Result<()> {
+        for (symbol, value) in saved.into_iter().rev() {
+            self.store(symbol, value)?;
+        }
+        Ok(())
+    }
+
+    /// Tests a value against a pattern, collecting any variables that the pattern binds.
+    fn match_pattern(&mut self, value: &Value, pattern: &Pattern, bindings: &mut Vec<(VariableSymbol, Value)>)
+        -> Result<bool>
+    {
+        let value = self.dereference(value)?
+            .clone();
+        match pattern {
+            Pattern::Any => Ok(true),
+            Pattern::Bind(symbol) => {
+                bindings.push((*symbol, value));
+                Ok(true)
+            }
+            Pattern::Value(expected) => value.smartmatch(expected, &self.storage),
+            Pattern::Ty(ty_symbol) => {
+                let ty = self.storage.get_ty(*ty_symbol)
+                    .clone();
+                self.run_ty_predicate(ty, value)
+            }
+            Pattern::Variant { ty, tag, fields } => match value {
+                Value::Variant(ref variant) if variant.ty == *ty && variant.tag == *tag => {
+                    self.match_pattern_list(&variant.fields, fields, bindings)
+                }
+                _ => Ok(false),
+            },
+            Pattern::Array(items) => match value {
+                Value::Array(ref values) => self.match_pattern_list(values, items, bindings),
+                _ => Ok(false),
+            },
+        }
+    }
+
+    /// Tests a list of values against a list of patterns, item-by-item.
+    fn match_pattern_list(&mut self, values: &[Value], patterns: &[Pattern],
+                          bindings: &mut Vec<(VariableSymbol, Value)>) -> Result<bool>
+    {
+        if values.len() != patterns.len() {
+            return Ok(false);
+        }
+        for (value, pattern) in values.iter().zip(patterns.iter()) {
+            if !self.match_pattern(value, pattern, bindings)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn run_ty_predicate(&mut self, ty: Ty, val: Value) -> Result<bool> {
         match ty {
             Ty::Builtin(builtin, _) => {
                 let cast = val.cast_to_builtin(builtin, &self.storage);
                 Ok(cast.is_valid())
             }
+            Ty::User(ref user_ty) if user_ty.is_enum() => {
+                let value = self.dereference(&val)?;
+                Ok(matches!(value, Value::Variant(ref v) if v.ty == user_ty.symbol))
+            }
             Ty::User(user_ty) => {
                 let pred = self.get_function(user_ty.predicate)
                     .clone();
                 self.push_stack(val);
-                self.run_function(pred)?;
+                self.run_function(pred, None)?;
                 self.pop_stack().is_truthy(&self.storage)
             }
         }
@@ -289,15 +784,43 @@ impl Vm {
             .push(value);
     }
 
-    fn call(&mut self, FunctionSymbol(index): FunctionSymbol) -> Result<()> {
+    /// Removes the last args canary from the stack.
+    ///
+    /// # Returns
+    /// The number of arguments that were pushed after the canary.
+    fn pop_args_canary(&mut self) -> usize {
+        let canary_index = self.storage
+            .value_stack
+            .iter()
+            .rposition(|value| matches!(value, Value::ArgsCanary))
+            .expect("args canary error; no canary found on the stack");
+        self.storage.value_stack.remove(canary_index);
+        self.storage.value_stack.len() - canary_index
+    }
+
+    fn call(&mut self, symbol: FunctionSymbol, context: CallContext) -> Result<()> {
+        self.call_with_args(symbol, None, context)
+    }
+
+    /// Calls a function in the given call context, with the given number of arguments on top of
+    /// the stack.
+    fn call_with_args(&mut self, FunctionSymbol(index): FunctionSymbol, argc: Option<usize>, context: CallContext)
+        -> Result<()>
+    {
         // store current state
         let start_depth = self.call_stack.len();
         let block_depth = self.block_jump_depth;
         let jump_top = self.block_jump_top;
 
+        let context = match context {
+            CallContext::Caller => self.storage.call_context(),
+            context => context,
+        };
+        self.storage.call_contexts.push(context);
         self.call_stack.push(index);
-        self.run_current_function()?;
+        self.run_current_function(argc)?;
         let popped = self.call_stack.pop().expect("empty call stack at end of function call");
+        self.storage.call_contexts.pop();
 
         assert!(self.block_jump_depth == 0, "block jump depth from called function was > 0");
         self.block_jump_depth = block_depth;
@@ -349,6 +872,9 @@ impl Vm {
         match value {
             Value::Ref(s) => format!("variable `${}`", self.storage.variable_name(*s)),
             Value::FunctionRef(s) => format!("function `{}`", self.storage.function_name(*s)),
+            Value::Constant(_) => self.dereference(value)
+                .map(Value::display_string)
+                .unwrap_or_else(|_| value.display_string()),
             _ => value.display_string(),
         }
     }
@@ -357,3 +883,262 @@ impl Vm {
         message
     }
 }
+
+#[cfg(test)]
+mod test {
+    use compile::CompileState;
+    use vm::*;
+
+    /// Compiles and runs the given program, evaluating to the value of its final expression.
+    macro_rules! run {
+        ($input:expr) => {{
+            let mut compiler = CompileState::new();
+            compiler.begin();
+            compiler.feed_str("test", $input)
+                .and_then(|_| {
+                    let compile_unit = compiler.to_compile_unit();
+                    let mut vm = Vm::new();
+                    vm.set_compiler(compiler);
+                    vm.repl_launch(compile_unit)
+                })
+        }};
+    }
+
+    #[test]
+    fn test_vm_chain() {
+        let counted = "our $calls = 0
+                       fun f(): Any { $calls += 1; return 2 }";
+        // the middle operand is evaluated once, whether the chain is a condition or a value
+        let program = format!("{}\nmy $result = 0\nif 1 < f() < 3 {{ $result = 1 }}\n[$result, $calls]", counted);
+        assert_eq!(run!(&program), Ok(Some(Value::Array(vec![Value::Int(1), Value::Int(1)]))));
+        let program = format!("{}\nmy $result = 1 < f() <= 2\n[$result, $calls]", counted);
+        assert_eq!(run!(&program), Ok(Some(Value::Array(vec![Value::Bool(true), Value::Int(1)]))));
+        // operands after a false comparison aren't evaluated
+        let program = format!("{}\nmy $result = 3 < 2 < f()\n[$result, $calls]", counted);
+        assert_eq!(run!(&program), Ok(Some(Value::Array(vec![Value::Bool(false), Value::Int(0)]))));
+        assert_eq!(run!("1 < 2 < 3 > 1"), Ok(Some(Value::Bool(true))));
+    }
+
+    #[test]
+    fn test_vm_short_circuit() {
+        let counted = "our $calls = 0
+                       fun f(): Any { $calls += 1; return true }";
+        let cases = [
+            ("true || f()", true, 0),
+            ("false || f()", true, 1),
+            ("false && f()", false, 0),
+            ("true && f()", true, 1),
+            ("false || false && f()", false, 0),
+        ];
+        for (expr, result, calls) in cases.iter() {
+            // as values
+            let program = format!("{}\nmy $result = {}\n[$result, $calls]", counted, expr);
+            assert_eq!(run!(&program), Ok(Some(Value::Array(vec![Value::Bool(*result), Value::Int(*calls)]))),
+                       "value of {}", expr);
+            // as conditions
+            let program = format!("{}\nmy $result = false\nif {} {{ $result = true }}\n[$result, $calls]", counted, expr);
+            assert_eq!(run!(&program), Ok(Some(Value::Array(vec![Value::Bool(*result), Value::Int(*calls)]))),
+                       "condition {}", expr);
+        }
+    }
+
+    #[test]
+    fn test_vm_default_params() {
+        let program = "fun f($x = 5): Any { return $x }
+                       fun g($a, $b = 2, $c = 3): Any { return [$a, $b, $c] }";
+        assert_eq!(run!(&format!("{}\nf()", program)), Ok(Some(Value::Int(5))));
+        assert_eq!(run!(&format!("{}\nf(1)", program)), Ok(Some(Value::Int(1))));
+        assert_eq!(run!(&format!("{}\ndefined(f(undef))", program)), Ok(Some(Value::Bool(false))));
+        assert_eq!(run!(&format!("{}\ng(1, c: 4)", program)),
+                   Ok(Some(Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(4)]))));
+        assert_eq!(run!(&format!("{}\ng(1, undef)", program)),
+                   Ok(Some(Value::Array(vec![Value::Int(1), Value::Unset, Value::Int(3)]))));
+    }
+
+    #[test]
+    fn test_vm_defined_or() {
+        let counted = "our $calls = 0
+                       fun f(): Any { $calls += 1; return 7 }";
+        let cases = [
+            ("5 // f()", Value::Int(5), 0),
+            ("undef // f()", Value::Int(7), 1),
+            ("false // f()", Value::Bool(false), 0),
+            ("undef // undef // f()", Value::Int(7), 1),
+        ];
+        for (expr, result, calls) in cases.iter() {
+            let program = format!("{}\nmy $result = {}\n[$result, $calls]", counted, expr);
+            assert_eq!(run!(&program), Ok(Some(Value::Array(vec![result.clone(), Value::Int(*calls)]))),
+                       "value of {}", expr);
+        }
+        let program = format!("{}\nmy $x = 5\n$x //= f()\nmy $y\n$y //= f()\n[$x, $y, $calls]", counted);
+        assert_eq!(run!(&program), Ok(Some(Value::Array(vec![Value::Int(5), Value::Int(7), Value::Int(1)]))));
+    }
+
+    #[test]
+    fn test_vm_wantarray() {
+        let program = "our $want = 0
+                       fun f(): Any { return wantarray() }
+                       fun g() { $want = wantarray() }
+                       fun h(): Any { return f() }";
+        // scalar
+        assert_eq!(run!(&format!("{}\nmy $x = f()\n$x", program)), Ok(Some(Value::Bool(false))));
+        // list
+        assert_eq!(run!(&format!("{}\n[f()]", program)), Ok(Some(Value::Array(vec![Value::Bool(true)]))));
+        // void
+        assert_eq!(run!(&format!("{}\ng()\n$want", program)), Ok(Some(Value::Unset)));
+        // `return f()` passes on the context of its own caller
+        assert_eq!(run!(&format!("{}\nmy $x = h()\n$x", program)), Ok(Some(Value::Bool(false))));
+        assert_eq!(run!(&format!("{}\n[h()]", program)), Ok(Some(Value::Array(vec![Value::Bool(true)]))));
+    }
+
+    #[test]
+    fn test_vm_return_list() {
+        let program = "fun two(): Any { return 1, 2 }";
+        // a returned list is counted in scalar context
+        assert_eq!(run!(&format!("{}\nmy $n = two()\n$n", program)), Ok(Some(Value::Int(2))));
+        assert_eq!(run!(&format!("{}\nmy ($a, $b) = two()\n[$b, $a]", program)),
+                   Ok(Some(Value::Array(vec![Value::Int(2), Value::Int(1)]))));
+        assert_eq!(run!(&format!("{}\n[two()]", program)),
+                   Ok(Some(Value::Array(vec![Value::Array(vec![Value::Int(1), Value::Int(2)])]))));
+    }
+
+    #[test]
+    fn test_vm_void_functions() {
+        let program = "fun g() { }";
+        assert_eq!(run!(&format!("{}\ng()\n1", program)), Ok(Some(Value::Int(1))));
+        assert_eq!(run!(&format!("{}\nmy $w = g()", program)), Err("function `g` doesn't return a value".to_string()));
+        assert_eq!(run!(&format!("{}\nmy $w = [g()]", program)), Err("function `g` doesn't return a value".to_string()));
+        // lists are unpacked from the value, so there must be one
+        assert_eq!(run!(&format!("{}\nmy ($a, $b) = g()", program)), Err("function `g` doesn't return a value".to_string()));
+        assert_eq!(run!(&format!("{}\nmy $a\nmy $b\n($a, $b) = g()", program)),
+                   Err("function `g` doesn't return a value".to_string()));
+    }
+
+    #[test]
+    fn test_vm_local() {
+        let program = "our $sep = \",\"
+                       our $seen = \"\"
+                       fun see() { $seen = $seen ~ $sep }";
+        // normal block exit
+        assert_eq!(run!(&format!("{}\nif true {{ local $sep = \"|\"\nsee() }}\nsee()\n$seen", program)),
+                   Ok(Some(Value::Str("|,".to_string()))));
+        assert_eq!(run!(&format!("{}\nif true {{ local $sep = \"|\" }}\n$sep", program)),
+                   Ok(Some(Value::Str(",".to_string()))));
+        // early return
+        let early = "fun early(): Any { local $sep = \"|\"\nreturn $sep }";
+        assert_eq!(run!(&format!("{}\n{}\n[early(), $sep]", program, early)),
+                   Ok(Some(Value::Array(vec![Value::Str("|".to_string()), Value::Str(",".to_string())]))));
+        // break out of a loop
+        let looped = "my $i = 0\nwhile true { $i += 1\nlocal $sep = $i\nif $i == 2 { break } }";
+        assert_eq!(run!(&format!("{}\n{}\n$sep", program, looped)), Ok(Some(Value::Str(",".to_string()))));
+        // an error caught by eval
+        let failed = "fun fail() { local $sep = \"|\"\n1 / 0 }\neval(\"fail()\")";
+        assert_eq!(run!(&format!("{}\n{}\n[$sep, $@ != \"\"]", program, failed)),
+                   Ok(Some(Value::Array(vec![Value::Str(",".to_string()), Value::Bool(true)]))));
+    }
+
+    #[test]
+    fn test_vm_eval() {
+        assert_eq!(run!("my $x = eval(\"1 + 2\")\n[$x, $@]"),
+                   Ok(Some(Value::Array(vec![Value::Int(3), Value::Str(String::new())]))));
+        // syntax errors
+        assert_eq!(run!("my $x = eval(\"1 +\")\n[defined($x), $@ != \"\"]"),
+                   Ok(Some(Value::Array(vec![Value::Bool(false), Value::Bool(true)]))));
+        // runtime errors keep what the code did before it failed
+        assert_eq!(run!("our $g = 1\nmy $x = eval(\"$g = 99; 1 / 0\")\n[defined($x), $@ != \"\", $g]"),
+                   Ok(Some(Value::Array(vec![Value::Bool(false), Value::Bool(true), Value::Int(99)]))));
+        // functions defined by eval
+        assert_eq!(run!("eval(\"fun twice($a): Any { return $a * 2 }\")\neval(\"twice(4)\")"),
+                   Ok(Some(Value::Int(8))));
+        // ...but not by the program that was compiled before them
+        assert_eq!(run!("eval(\"fun twice($a): Any { return $a * 2 }\")\ntwice(4)"),
+                   Err("no such function `twice`".to_string()));
+    }
+
+    #[test]
+    fn test_vm_const() {
+        assert_eq!(run!("const LIMIT = 2 * 5\n[LIMIT, 1.5, 1.5, 10]"),
+                   Ok(Some(Value::Array(vec![Value::Int(10), Value::Float(1.5), Value::Float(1.5), Value::Int(10)]))));
+        assert_eq!(run!("const X = 1\nX = 2"), Err("test:2:1: cannot assign to constant `X`".to_string()));
+    }
+
+    #[test]
+    fn test_vm_begin() {
+        assert_eq!(run!("my $g\nBEGIN { $g = 5 }\n$g"), Ok(Some(Value::Int(5))));
+        // `BEGIN` blocks and constants are run by the same compiler, so what one evals, the other sees
+        assert_eq!(run!("BEGIN { eval(\"fun twice($x): Int { return $x * 2 }\") }\nconst T = twice(21)\n[T, twice(5)]"),
+                   Ok(Some(Value::Array(vec![Value::Int(42), Value::Int(10)]))));
+        assert_eq!(run!("BEGIN { 1 / 0 }"), Err("test:1:1: error in `BEGIN` block: division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_vm_pipe() {
+        let program = "fun add($a, $b): Any { return $a + $b }
+                       fun double($x): Any { return $x * 2 }";
+        // the piped value becomes the first argument
+        assert_eq!(run!(&format!("{}\n3 |> add(4)", program)), Ok(Some(Value::Int(7))));
+        assert_eq!(run!(&format!("{}\n3 |> double", program)), Ok(Some(Value::Int(6))));
+        assert_eq!(run!(&format!("{}\n3 |> add(1) |> double", program)), Ok(Some(Value::Int(8))));
+        // builtins
+        assert_eq!(run!("undef |> defined"), Ok(Some(Value::Bool(false))));
+        assert_eq!(run!("\"hi\" |> println\n1"), Ok(Some(Value::Int(1))));
+        // function refs
+        assert_eq!(run!(&format!("{}\nmy $f = &double\n5 |> $f", program)), Ok(Some(Value::Int(10))));
+    }
+
+    #[test]
+    fn test_vm_function_refs() {
+        let program = "fun double($x): Any { return $x * 2 }
+                       fun add($a, $b): Any { return $a + $b }
+                       fun pick(): Any { return &add }
+                       my $handlers = [&double, &add]";
+        assert_eq!(run!(&format!("{}\nmy $f = $handlers[0]\n$f(3)", program)), Ok(Some(Value::Int(6))));
+        assert_eq!(run!(&format!("{}\n$handlers[1](4, 5)", program)), Ok(Some(Value::Int(9))));
+        assert_eq!(run!(&format!("{}\n$handlers[-2](4)", program)), Ok(Some(Value::Int(8))));
+        assert_eq!(run!(&format!("{}\npick()(2, 3)", program)), Ok(Some(Value::Int(5))));
+        assert_eq!(run!(&format!("{}\ndefined($handlers[2])", program)), Ok(Some(Value::Bool(false))));
+        assert_eq!(run!(&format!("{}\n$handlers[0](1, 2)", program)),
+                   Err("function `double` takes 1 argument(s), but got 2".to_string()));
+        assert_eq!(run!("my $x = 5\n$x[0]"), Err("cannot index variable `$x`, since it is not an array".to_string()));
+        assert_eq!(run!("my $x = [1]\n$x[0] = 2"), Err("test:2:1: cannot assign to an item of an array".to_string()));
+    }
+
+    #[test]
+    fn test_vm_dispatch() {
+        // the candidate whose parameter types match the arguments most closely wins
+        let program = "fun describe($x: Int): Any { return \"int\" }
+                       fun describe($x: Str): Any { return \"str\" }
+                       fun describe($x): Any { return \"any\" }";
+        assert_eq!(run!(&format!("{}\n[describe(1), describe(\"a\"), describe([1])]", program)),
+                   Ok(Some(Value::Array(vec![Value::Str("int".to_string()), Value::Str("str".to_string()),
+                                             Value::Str("any".to_string())]))));
+        // no candidate is at least as close as every other on every argument
+        let program = "fun pair($a: Int, $b): Any { return 1 }
+                       fun pair($a, $b: Int): Any { return 2 }";
+        assert_eq!(run!(&format!("{}\n[pair(1, \"x\"), pair(\"x\", 1)]", program)),
+                   Ok(Some(Value::Array(vec![Value::Int(1), Value::Int(2)]))));
+        assert_eq!(run!(&format!("{}\npair(1, 2)", program)),
+                   Err("call to function `pair` is ambiguous between candidates: pair(Int, Any), pair(Any, Int)".to_string()));
+        // no candidate accepts the arguments
+        let program = "fun only($x: Int): Any { return 1 }
+                       fun only($x: Bool): Any { return 2 }";
+        assert_eq!(run!(&format!("{}\nonly([1])", program)),
+                   Err("no candidate of function `only` accepts the arguments ([1]); candidates are: only(Int), only(Bool)".to_string()));
+    }
+
+    #[test]
+    fn test_vm_forward_types() {
+        // types and functions may be used before they're declared, and may refer to each other
+        let program = "fun describe($n: Even): Any { return \"even\" }
+                       fun describe($n: Odd): Any { return \"odd\" }
+                       [describe(2), describe(3)]
+                       type Even {
+                           fun is?($x): Bool { return is-even($x) }
+                       }
+                       type Odd {
+                           fun is?($x): Bool { return !is-even($x) }
+                       }
+                       fun is-even($x): Bool { return $x % 2 == 0 }";
+        assert_eq!(run!(program), Ok(Some(Value::Array(vec![Value::Str("even".to_string()), Value::Str("odd".to_string())]))));
+    }
+}
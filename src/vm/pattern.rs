@@ -0,0 +1,34 @@
+use vm::{Value, TySymbol, VariableSymbol};
+
+/// A pattern that a value can be tested against, binding variables along the way.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// Matches any value.
+    Any,
+
+    /// Matches any value, and stores it in the given variable.
+    Bind(VariableSymbol),
+
+    /// Matches a value that smart-matches against the given value.
+    Value(Value),
+
+    /// Matches a value that passes the given type's predicate.
+    Ty(TySymbol),
+
+    /// Matches an enum variant of the given type and tag, whose fields match the given patterns.
+    Variant {
+        ty: TySymbol,
+        tag: usize,
+        fields: Vec<Pattern>,
+    },
+
+    /// Matches an array whose items match the given patterns.
+    Array(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Gets whether this pattern will match any value.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Any | Pattern::Bind(_))
+    }
+}
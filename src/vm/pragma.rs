@@ -0,0 +1,52 @@
+use std::fmt::{self, Formatter, Display};
+
+/// The settings that pragmas change while a program is running.
+///
+/// These are the pragmas in effect where the running bytecode was compiled, so they change when a
+/// function is called, or when a block that uses a pragma is entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pragmas {
+    /// What happens when integer arithmetic overflows, set by `use overflow`.
+    pub overflow: Overflow,
+
+    /// Whether strings are never used as numbers, set by `use strict numbers`.
+    pub strict_numbers: bool,
+
+    /// Whether using an unset value prints a warning, set by `use warnings unset`.
+    pub warn_unset: bool,
+}
+
+/// What happens when integer arithmetic overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Overflowing is a runtime error.
+    #[default]
+    Error,
+
+    /// The result wraps around, e.g. adding 1 to the largest integer gives the smallest integer.
+    Wrap,
+
+    /// The arithmetic is done with floats instead.
+    Float,
+}
+
+impl Overflow {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Overflow::Error),
+            "wrap" => Some(Overflow::Wrap),
+            "float" => Some(Overflow::Float),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Overflow {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Overflow::Error => write!(fmt, "error"),
+            Overflow::Wrap => write!(fmt, "wrap"),
+            Overflow::Float => write!(fmt, "float"),
+        }
+    }
+}
@@ -55,6 +55,14 @@ impl Scope {
         self.symbols.is_empty()
     }
 
+    /// Gets each symbol defined by this scope, along with its value.
+    pub fn iter(&self) -> impl Iterator<Item=(VariableSymbol, &Value)> {
+        self.symbols
+            .iter()
+            .cloned()
+            .zip(self.values.iter())
+    }
+
     /// Updates this scope's values with values from another, possibly overwriting values.
     pub fn update(&mut self, other: Self) {
         let items = other.symbols.into_iter().zip(other.values.into_iter());
@@ -17,26 +17,33 @@ pub struct Storage {
     /// The script body.
     pub body: Vec<Bc>,
 
-    /// A list of read-only constants.
+    /// A list of read-only constants, which `Value::Constant` refers to by index.
     pub constants: Vec<Value>,
 
     /// All types in this VM.
     pub tys: Vec<Ty>,
 
     pub variables: Vec<Variable>,
+
+    /// The pragmas that the currently running bytecode was compiled with.
+    pub pragmas: Pragmas,
+
+    /// The context that each function on the call stack was called in.
+    pub call_contexts: Vec<CallContext>,
 }
 
 impl From<CompileUnit> for Storage {
-    fn from(CompileUnit { body, functions, tys, variables, globals, }: CompileUnit) -> Self {
-        let unset_globals = vec!(Value::Unset; globals.len());
+    fn from(CompileUnit { body, functions, tys, variables, globals, global_values, constants, pragmas, }: CompileUnit) -> Self {
         Storage {
-            scope_stack: vec![Scope::new(globals, unset_globals)],
+            scope_stack: vec![Scope::new(globals, global_values)],
             value_stack: vec![],
             functions: functions,
             body,
-            constants: vec![/* TODO: constants */],
+            constants,
             tys,
             variables,
+            pragmas,
+            call_contexts: vec![],
         }
     }
 }
@@ -51,6 +58,28 @@ impl Storage {
             constants: vec![],
             tys: vec![],
             variables: vec![],
+            pragmas: Pragmas::default(),
+            call_contexts: vec![],
+        }
+    }
+
+    /// Gets the context that the innermost running function was called in.
+    ///
+    /// The script body is run in void context.
+    pub fn call_context(&self) -> CallContext {
+        self.call_contexts
+            .last()
+            .cloned()
+            .unwrap_or(CallContext::Void)
+    }
+
+    /// Prints a warning about an unset value being used, if `use warnings unset` is on.
+    ///
+    /// # Arguments
+    /// `usage` - how the value was used, e.g. "in a condition".
+    pub fn warn_unset(&self, usage: &str) {
+        if self.pragmas.warn_unset {
+            eprintln!("warning: use of unset value {}", usage);
         }
     }
 
@@ -82,12 +111,14 @@ impl Storage {
                 let value = self.load(*sym)?;
                 self.dereference(&value)
             }
+            Value::Constant(sym) => Ok(&self.constants[sym.index()]),
             _ => Ok(value),
         }
     }
 
     pub fn store(&mut self, symbol: VariableSymbol, value: Value) -> Result<()> {
-        if self.current_scope_mut().try_set(symbol, value.clone()) {
+        if self.current_scope_mut().try_set(symbol, value.clone())
+            || self.scope_stack.iter_mut().any(|scope| scope.try_set(symbol, value.clone())) {
             Ok(())
         } else {
             Err(self.err(format!("could not set symbol: {:?} to value: {:?}", symbol, value)))
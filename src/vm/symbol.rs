@@ -80,7 +80,7 @@ pub trait Symbol: Clone + Copy + PartialOrd + PartialEq + Ord + Default {
 symbol!(FunctionSymbol);
 symbol!(TySymbol);
 symbol_impl!(VariableSymbol, global);
-//symbol!(ConstantSymbol);
+symbol!(ConstantSymbol);
 
 /// A type that has a name and a symbol.
 pub trait Symbolic {
@@ -114,6 +114,7 @@ impl Symbolic for Function {
         match self {
             | Function::Builtin(b) => b.symbol,
             | Function::User(u) => u.symbol,
+            | Function::Dispatch(d) => d.symbol,
         }
     }
 
@@ -121,6 +122,7 @@ impl Symbolic for Function {
         match self {
             | Function::Builtin(b) => &b.name,
             | Function::User(u) => &u.name,
+            | Function::Dispatch(d) => &d.name,
         }
     }
 }
@@ -52,6 +52,19 @@ pub struct UserTy {
     pub symbol: TySymbol,
     pub predicate: FunctionSymbol,
     pub functions: Vec<FunctionSymbol>,
+
+    /// The variants of this type, if it is an enum.
+    pub variants: Vec<VariantDef>,
+}
+
+impl UserTy {
+    /// Gets whether this type is an enum.
+    ///
+    /// Values of an enum type are checked by their variant's type, rather than by the type's
+    /// predicate.
+    pub fn is_enum(&self) -> bool {
+        !self.variants.is_empty()
+    }
 }
 
 impl Display for UserTy {
@@ -59,3 +72,10 @@ impl Display for UserTy {
         write!(fmt, "{}", self.name)
     }
 }
+
+/// The definition of a single variant of an enum type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantDef {
+    pub name: String,
+    pub fields: usize,
+}
@@ -3,11 +3,14 @@ use vm::{
     Symbol,
     VariableSymbol,
     FunctionSymbol,
+    TySymbol,
+    ConstantSymbol,
     Storage,
     Ty,
     BuiltinTy,
 };
 use ir::Const;
+use regex::Regex;
 
 /// The index type for a value.
 ///
@@ -21,6 +24,7 @@ pub enum Value {
     Str(String),
     Bool(bool),
     Array(Vec<Value>),
+    Regex(RegexValue),
 
     /// A canary placed before an expected symbol ref.
     ///
@@ -35,6 +39,10 @@ pub enum Value {
     /// A reference to something.
     Ref(VariableSymbol),
 
+    /// A reference to a value in the constant pool, which literals and `const` declarations are
+    /// compiled into.
+    Constant(ConstantSymbol),
+
     /// A canary placed before an expected function ref.
     ///
     /// This is very similar to the `RefCanary`, except that it expects a function ref on top of the
@@ -43,9 +51,25 @@ pub enum Value {
 
     FunctionRef(FunctionSymbol),
 
-    /// An unset value.
+    /// A canary placed before the arguments of a function call, when the number of arguments is
+    /// only known at runtime.
+    ///
+    /// This is used to call variadic functions, and to call functions with splatted arguments.
+    ArgsCanary,
+
+    /// A placeholder for an argument that was left out of a function call, so that its parameter
+    /// gets its default value.
+    ///
+    /// This is different from an unset value, which may be passed on purpose with `undef`.
+    Omitted,
+
+    /// An instance of an enum variant.
+    Variant(VariantValue),
+
+    /// An unset value, e.g. a variable that has not been assigned or the `undef` keyword.
     ///
-    /// Attempting to access a value that is unset will result in a run-time exception.
+    /// Unset values are false, zero, and the empty string when they are used, which may print a
+    /// warning.
     Unset,
 }
 
@@ -56,12 +80,22 @@ impl Value {
             Value::Float(f) => format!("{}", f),
             Value::Str(s) => s.clone(),
             Value::Bool(b) => format!("{}", b),
-            Value::Array(_) => format!("TODO : vm::Value array display string"),
+            Value::Array(items) => {
+                let items: Vec<_> = items.iter()
+                    .map(Value::display_string)
+                    .collect();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Regex(r) => format!("m/{}/", r.as_str()),
             Value::RefCanary => "<Ref Canary>".to_string(),
             Value::Ref(s) => format!("<Reference to symbol {:#x}>", s.index()),
+            Value::Constant(c) => format!("<Reference to constant {:#x}>", c.index()),
             Value::FunctionRefCanary => "<Function Ref Canary>".to_string(),
             Value::FunctionRef(c) => format!("<Reference to Function {:#x}>", c.index()),
-            Value::Unset => "<Unset Value>".to_string(),
+            Value::ArgsCanary => "<Args Canary>".to_string(),
+            Value::Omitted => "<Omitted>".to_string(),
+            Value::Variant(v) => v.display_string(),
+            Value::Unset => String::new(),
         }
     }
 
@@ -72,10 +106,15 @@ impl Value {
             | Value::Str(_) 
             | Value::Bool(_) 
             | Value::Array(_) 
+            | Value::Regex(_)
+            | Value::Variant(_)
             | Value::RefCanary 
             | Value::FunctionRefCanary 
+            | Value::ArgsCanary
+            | Value::Omitted
             | Value::Unset => true,
             | Value::Ref(_)
+            | Value::Constant(_)
             | Value::FunctionRef(_) => false,
         }
     }
@@ -98,8 +137,8 @@ impl Value {
                 BuiltinTy::Float => CastResult::Value(Value::Float((*i) as f64)),
                 BuiltinTy::Str => CastResult::Value(Value::Str(i.to_string())),
                 BuiltinTy::Bool => CastResult::Value(Value::Bool(*i != 0)),
-                BuiltinTy::Array => unimplemented!("TODO : cast int to array value"),
-                BuiltinTy::Any => unimplemented!("TODO : cast int to Any value"),
+                BuiltinTy::Array => CastResult::Invalid,
+                BuiltinTy::Any => CastResult::SelfValid,
                 BuiltinTy::None => CastResult::Invalid,
             },
             Value::Float(f) => match builtin {
@@ -107,8 +146,8 @@ impl Value {
                 BuiltinTy::Float => CastResult::SelfValid,
                 BuiltinTy::Str => CastResult::Value(Value::Str(f.to_string())),
                 BuiltinTy::Bool => CastResult::Value(Value::Bool(*f != 0.0)),
-                BuiltinTy::Array => unimplemented!("TODO : cast float to array value"),
-                BuiltinTy::Any => unimplemented!("TODO : cast float to Any value"),
+                BuiltinTy::Array => CastResult::Invalid,
+                BuiltinTy::Any => CastResult::SelfValid,
                 BuiltinTy::None => CastResult::Invalid,
             },
             Value::Str(s) => match builtin {
@@ -124,13 +163,47 @@ impl Value {
                 },
                 BuiltinTy::Str => CastResult::SelfValid,
                 BuiltinTy::Bool => CastResult::Value(Value::Bool(!s.is_empty())),
-                BuiltinTy::Array => unimplemented!("TODO : cast str to array value"),
-                BuiltinTy::Any => unimplemented!("TODO : cast str to Any value"),
+                BuiltinTy::Array => CastResult::Invalid,
+                BuiltinTy::Any => CastResult::SelfValid,
                 BuiltinTy::None => CastResult::Invalid,
             },
-            Value::Unset => CastResult::Invalid,
-            Value::Ref(_) => panic!("Reference gotten even though self was dereferenced (self: {:?})", self),
-            Value::FunctionRef(_) => CastResult::Invalid,
+            Value::Bool(b) => match builtin {
+                BuiltinTy::Int => CastResult::Value(Value::Int(*b as i64)),
+                BuiltinTy::Float => CastResult::Value(Value::Float(*b as i64 as f64)),
+                BuiltinTy::Str => CastResult::Value(Value::Str(b.to_string())),
+                | BuiltinTy::Bool
+                | BuiltinTy::Any => CastResult::SelfValid,
+                | BuiltinTy::Array
+                | BuiltinTy::None => CastResult::Invalid,
+            },
+            Value::Array(_) => match builtin {
+                | BuiltinTy::Array
+                | BuiltinTy::Any => CastResult::SelfValid,
+                _ => CastResult::Invalid,
+            },
+            Value::Unset => match builtin {
+                BuiltinTy::Any => CastResult::SelfValid,
+                BuiltinTy::Array | BuiltinTy::None => CastResult::Invalid,
+                builtin => {
+                    storage.warn_unset(&format!("as {}", builtin));
+                    match builtin {
+                        BuiltinTy::Int => CastResult::Value(Value::Int(0)),
+                        BuiltinTy::Float => CastResult::Value(Value::Float(0.0)),
+                        BuiltinTy::Str => CastResult::Value(Value::Str(String::new())),
+                        BuiltinTy::Bool => CastResult::Value(Value::Bool(false)),
+                        _ => unreachable!(),
+                    }
+                }
+            },
+            Value::Ref(_) | Value::Constant(_) =>
+                panic!("Reference gotten even though self was dereferenced (self: {:?})", self),
+            | Value::FunctionRef(_)
+            | Value::Regex(_)
+            | Value::Variant(_) => if builtin == BuiltinTy::Any {
+                CastResult::SelfValid
+            } else {
+                CastResult::Invalid
+            },
             r => panic!("Attempted to cast invalid value {:?} to {:?}", r, builtin),
         }
     }
@@ -141,10 +214,17 @@ impl Value {
             Value::Float(f) => Ok(*f != 0.0),
             Value::Str(s) => Ok(!s.is_empty()),
             Value::Bool(b) => Ok(*b),
-            Value::Array(_) => unimplemented!("TODO(array) : is_truthy"),
+            Value::Array(items) => Ok(!items.is_empty()),
             Value::Ref(sym) => storage.load(*sym)?.is_truthy(storage),
-            Value::FunctionRef(_) => Ok(true),
-            Value::RefCanary | Value::FunctionRefCanary | Value::Unset =>
+            Value::Constant(_) => storage.dereference(self)?.is_truthy(storage),
+            | Value::FunctionRef(_)
+            | Value::Regex(_)
+            | Value::Variant(_) => Ok(true),
+            Value::Unset => {
+                storage.warn_unset("in a condition");
+                Ok(false)
+            }
+            Value::RefCanary | Value::FunctionRefCanary | Value::ArgsCanary | Value::Omitted =>
                 panic!("invalid truthy value checked on value {:?}", self),
         }
     }
@@ -175,6 +255,84 @@ impl Value {
             _ => unreachable!(),
         }
     }
+
+    /// Gets the numeric value of this value, if it is a number or a numeric string.
+    pub fn to_number(&self, storage: &Storage) -> Option<f64> {
+        match storage.dereference(self).ok()? {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Str(_) if storage.pragmas.strict_numbers => None,
+            Value::Str(s) => s.trim().parse::<f64>().ok(),
+            Value::Unset => {
+                storage.warn_unset("as a number");
+                Some(0.0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Smart-matches this value against another value.
+    ///
+    /// * Regexes match against the string form of this value.
+    /// * Booleans match against the truthiness of this value.
+    /// * Numbers match numerically, if this value is numeric.
+    /// * Strings match against the string form of this value.
+    /// * Arrays and enum variants match item-by-item.
+    pub fn smartmatch(&self, other: &Value, storage: &Storage) -> Result<bool> {
+        let lhs = storage.dereference(self)?;
+        let rhs = storage.dereference(other)?;
+        let matched = match rhs {
+            Value::Regex(r) => r.is_match(&lhs.display_string()),
+            Value::Bool(b) => lhs.is_truthy(storage)? == *b,
+            | Value::Int(_)
+            | Value::Float(_) => match (lhs.to_number(storage), rhs.to_number(storage)) {
+                (Some(l), Some(r)) => l == r,
+                _ => false,
+            },
+            Value::Str(s) => match lhs {
+                | Value::Int(_)
+                | Value::Float(_)
+                | Value::Str(_)
+                | Value::Bool(_) => lhs.display_string() == *s,
+                _ => false,
+            },
+            Value::Array(rhs_items) => if let Value::Array(lhs_items) = lhs {
+                if lhs_items.len() != rhs_items.len() {
+                    return Ok(false);
+                }
+                for (l, r) in lhs_items.iter().zip(rhs_items.iter()) {
+                    if !l.smartmatch(r, storage)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            } else {
+                false
+            },
+            Value::Variant(r) => if let Value::Variant(l) = lhs {
+                if l.ty != r.ty || l.tag != r.tag {
+                    return Ok(false);
+                }
+                for (l, r) in l.fields.iter().zip(r.fields.iter()) {
+                    if !l.smartmatch(r, storage)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            } else {
+                false
+            },
+            | Value::FunctionRef(_)
+            | Value::Unset => lhs == rhs,
+            | Value::Ref(_)
+            | Value::Constant(_)
+            | Value::RefCanary
+            | Value::FunctionRefCanary
+            | Value::ArgsCanary
+            | Value::Omitted => panic!("invalid smartmatch value {:?}", rhs),
+        };
+        Ok(matched)
+    }
 }
 
 impl<'n> From<Const> for Value {
@@ -184,10 +342,62 @@ impl<'n> From<Const> for Value {
             Const::Int(i) => Value::Int(i),
             Const::Float(f) => Value::Float(f),
             Const::Bool(b) => Value::Bool(b),
+            Const::Regex(r) => Value::Regex(RegexValue::new(&r)
+                .expect("regex literal was not validated by the lexer")),
+            Const::Undef => Value::Unset,
+        }
+    }
+}
+
+/// A value of an enum variant, tagged with the type and the variant that it was constructed with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantValue {
+    pub ty: TySymbol,
+    pub tag: usize,
+    pub name: String,
+    pub fields: Vec<Value>,
+}
+
+impl VariantValue {
+    pub fn display_string(&self) -> String {
+        if self.fields.is_empty() {
+            self.name.clone()
+        } else {
+            let fields: Vec<_> = self.fields
+                .iter()
+                .map(Value::display_string)
+                .collect();
+            format!("{}({})", self.name, fields.join(", "))
         }
     }
 }
 
+/// A compiled regular expression value.
+#[derive(Debug, Clone)]
+pub struct RegexValue(Regex);
+
+impl RegexValue {
+    pub fn new(pattern: &str) -> ::std::result::Result<Self, String> {
+        Regex::new(pattern)
+            .map(RegexValue)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl ::std::ops::Deref for RegexValue {
+    type Target = Regex;
+
+    fn deref(&self) -> &Regex {
+        &self.0
+    }
+}
+
+impl PartialEq for RegexValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
 pub enum CastResult {
     SelfValid,
     Value(Value),